@@ -33,15 +33,16 @@ fn main() {
             // num_cpus::get(),  // n_threads
             0.25,             // eta
             training_samples, // samples
-        );
+        )
+        .unwrap();
     }
 
-    println!("loss = {}", nn.loss(training_samples));
+    println!("loss = {}", nn.loss(training_samples).unwrap());
 
     println!("[Results]");
     for sample in training_samples.chunks(3) {
         let x = ColRef::from_slice(&sample[0..2]);
-        let a = nn.forward(x);
+        let a = nn.forward(x).unwrap();
         println!("{} xor {} = {}", x[0], x[1], a[0]);
     }
 }
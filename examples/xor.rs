@@ -6,7 +6,10 @@ use std::{
 };
 
 use gnuplot::{AxesCommon, ColorType, Figure, PlotOption};
-use mlp::{Gym, LayerDescription, NeuralNetwork, Topology, activation_functions::*};
+use mlp::{
+    Control, Gym, Hyperparameters, LayerDescription, NeuralNetwork, Progress, Topology,
+    TrainingHistory, activation_functions::*,
+};
 
 use faer::prelude::*;
 
@@ -17,21 +20,17 @@ fn time<T>(f: impl FnOnce() -> T) -> (Duration, T) {
     (after.duration_since(before), result)
 }
 
-struct LossRecords {
-    i_epochs_records: Vec<f32>,
-    loss_records: Vec<f32>,
-}
-
-fn plot_loss<'a>(records: &LossRecords, output_path: impl Into<Option<&'a str>>) {
+fn plot_loss<'a>(history: &TrainingHistory, output_path: impl Into<Option<&'a str>>) {
     let output_path = output_path.into();
     match output_path {
         Some(output_path) => println!("Plotting loss progress to file {output_path:?}..."),
         None => println!("Plotting loss progress to gnuplot window..."),
     }
+    let records = history.records();
     let mut figure = Figure::new();
     figure.axes2d().set_y_log(Some(10.0)).lines(
-        &records.i_epochs_records,
-        &records.loss_records,
+        records.iter().map(|record| record.i_epoch as f32),
+        records.iter().map(|record| record.loss),
         &[
             PlotOption::Caption("Loss"),
             PlotOption::Color(ColorType::Black),
@@ -44,7 +43,7 @@ fn plot_loss<'a>(records: &LossRecords, output_path: impl Into<Option<&'a str>>)
     figure.show_and_keep_running().unwrap();
 }
 
-fn train(samples: &[f32], nn: &mut NeuralNetwork, single_thread: bool) -> LossRecords {
+fn train(samples: &[f32], nn: &mut NeuralNetwork, single_thread: bool) -> TrainingHistory {
     let eta = 0.2;
 
     let n_epochs = 1_000_000;
@@ -62,32 +61,31 @@ fn train(samples: &[f32], nn: &mut NeuralNetwork, single_thread: bool) -> LossRe
         println!("Single threaded");
     }
 
-    let mut i_epochs_records: Vec<f32> = Vec::with_capacity(n_records);
-    let mut loss_records: Vec<f32> = Vec::with_capacity(n_records);
+    let mut history = TrainingHistory::with_interval(n_epochs / n_epochs.min(n_records));
 
-    let mut gym = Gym::new(nn);
-
-    for i_epoch in 0usize..n_epochs {
-        let loss = match single_thread {
-            true => gym.train_single_threaded(eta, samples),
-            false => gym.train(n_threads, eta, samples),
-        };
-        // Log.
-        if i_epoch % (n_epochs / n_epochs.min(n_logs)) == 0 || i_epoch == n_epochs - 1 {
+    let mut log = |progress: &Progress, _: &mut Hyperparameters| {
+        let i_epoch = progress.i_epoch;
+        if i_epoch.is_multiple_of(n_epochs / n_epochs.min(n_logs)) || i_epoch == n_epochs - 1 {
             let percentage = (i_epoch as f32) / (n_epochs as f32) * 100.0;
-            println!("[{percentage:.0}%] L = {loss}");
-        }
-        // Record.
-        if i_epoch % (n_epochs / n_epochs.min(n_records)) == 0 || i_epoch == n_epochs - 1 {
-            i_epochs_records.push(i_epoch as f32);
-            loss_records.push(loss);
+            println!("[{percentage:.0}%] L = {}", progress.loss);
         }
-    }
+        Control::Continue
+    };
 
-    LossRecords {
-        i_epochs_records,
-        loss_records,
-    }
+    let mut gym = Gym::new(nn);
+    let hyperparameters = Hyperparameters {
+        n_threads,
+        ..Hyperparameters::new(eta)
+    };
+    gym.fit(
+        hyperparameters,
+        samples,
+        n_epochs,
+        &mut [&mut history, &mut log],
+    )
+    .unwrap();
+
+    history
 }
 
 fn load_params(nn: &mut NeuralNetwork) -> Result<(), Box<dyn Error>> {
@@ -142,12 +140,12 @@ fn main() {
 
     println!("Training:");
 
-    let (training_duration, records) = time(|| train(samples, &mut nn, true));
+    let (training_duration, history) = time(|| train(samples, &mut nn, true));
     println!("training took {training_duration:?}");
 
     dump_params(&nn).unwrap();
 
-    plot_loss(&records, "loss_graph.svg");
+    plot_loss(&history, "loss_graph.svg");
 
     // Print parameters.
     // for i_layer in 0..nn.topology().n_layers() {
@@ -161,7 +159,7 @@ fn main() {
     for (i, sample) in samples.chunks(3).enumerate() {
         let x_i = ColRef::from_slice(&sample[0..2]);
         let y_i = ColRef::from_slice(&sample[2..3]);
-        let a_i = nn.forward(x_i);
+        let a_i = nn.forward(x_i).unwrap();
         println!("[i = {i}] expected: {x_i:?} => {y_i:?}, result: {a_i:?}");
     }
 }
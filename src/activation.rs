@@ -115,4 +115,21 @@ pub mod activation_functions {
             1.0 - f32::tanh(x).powi(2)
         }
     }
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Relu;
+    impl ActivationFunction for Relu {
+        const NAME: &'static str = "relu";
+
+        fn apply(x: f32) -> f32 {
+            x.max(0.0)
+        }
+
+        fn deriv(x: f32) -> f32 {
+            match x > 0.0 {
+                true => 1.0,
+                false => 0.0,
+            }
+        }
+    }
 }
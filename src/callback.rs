@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+/// Settings of `Gym::fit` that callbacks are allowed to change mid-training.
+///
+/// Changes made by a callback take effect from the next step, except for `batch_size`, which
+/// takes effect from the next epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hyperparameters {
+    /// Learning rate.
+    pub eta: f32,
+    /// Number of threads used for training, see `Gym::train`.
+    pub n_threads: usize,
+    /// Number of samples per step, `None` for full-batch training (one step per epoch).
+    pub batch_size: Option<usize>,
+}
+
+impl Hyperparameters {
+    /// Single threaded full-batch training with learning rate `eta`.
+    pub fn new(eta: f32) -> Self {
+        Self {
+            eta,
+            n_threads: 0,
+            batch_size: None,
+        }
+    }
+}
+
+/// Snapshot of the training progress, passed to callbacks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of steps performed so far, including the current one.
+    pub i_step: usize,
+    /// Index of the current epoch.
+    pub i_epoch: usize,
+    /// Training loss of the last step, or the mean training loss over the epoch in
+    /// `Callback::on_epoch_end`.
+    pub loss: f32,
+    /// L2 norm of the gradient applied in the last step.
+    pub gradient_norm: f32,
+    /// Validation loss from the most recent evaluation, if any.
+    pub validation_loss: Option<f32>,
+    /// Learning rate used for the last step.
+    pub eta: f32,
+    /// Time since the start of `Gym::fit`.
+    pub elapsed: Duration,
+}
+
+/// Returned by callbacks to tell `Gym::fit` whether to keep going.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    #[default]
+    Continue,
+    Stop,
+}
+
+/// Hooks into `Gym::fit`.
+///
+/// All methods do nothing by default.
+pub trait Callback {
+    /// Called after every training step.
+    fn on_step(&mut self, progress: &Progress, hyperparameters: &mut Hyperparameters) -> Control {
+        let _ = (progress, hyperparameters);
+        Control::Continue
+    }
+
+    /// Called after every pass over the whole training set.
+    fn on_epoch_end(
+        &mut self,
+        progress: &Progress,
+        hyperparameters: &mut Hyperparameters,
+    ) -> Control {
+        let _ = (progress, hyperparameters);
+        Control::Continue
+    }
+
+    /// Called after every evaluation of the validation set, see `Gym::set_validation`.
+    fn on_validation(
+        &mut self,
+        progress: &Progress,
+        hyperparameters: &mut Hyperparameters,
+    ) -> Control {
+        let _ = (progress, hyperparameters);
+        Control::Continue
+    }
+}
+
+/// Any `FnMut(&Progress, &mut Hyperparameters) -> Control` is a callback that is called on every
+/// step.
+impl<F> Callback for F
+where
+    F: FnMut(&Progress, &mut Hyperparameters) -> Control,
+{
+    fn on_step(&mut self, progress: &Progress, hyperparameters: &mut Hyperparameters) -> Control {
+        self(progress, hyperparameters)
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Conv, Embedding, Gym, Hyperparameters, LayerKind, MlpError, Pool, PoolKind, Shape, Skip,
+    Topology,
+    gym::BestParams,
+    serialize::{Decoder, Encoder, invalid_data},
+};
+
+const MAGIC: &[u8; 8] = b"MLPCKPT\0";
+const VERSION: u32 = 5;
+const FILE_PREFIX: &str = "checkpoint-";
+const FILE_EXTENSION: &str = "bin";
+
+/// Where and how often `Gym::fit` writes checkpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointConfig {
+    /// Directory the checkpoints are written into, created if it does not exist.
+    pub dir: PathBuf,
+    /// Write a checkpoint once every `interval` epochs.
+    pub interval: usize,
+    /// Number of most recent checkpoints to keep, older ones are deleted. `0` keeps all of them.
+    pub keep_last: usize,
+}
+
+impl CheckpointConfig {
+    /// # Panics
+    ///
+    /// - if `interval` is `0`
+    pub fn new(dir: impl Into<PathBuf>, interval: usize, keep_last: usize) -> Self {
+        assert!(interval != 0);
+        Self {
+            dir: dir.into(),
+            interval,
+            keep_last,
+        }
+    }
+
+    /// Path of the checkpoint written at the end of epoch `i_epoch`.
+    pub fn path_for_epoch(&self, i_epoch: usize) -> PathBuf {
+        self.dir
+            .join(format!("{FILE_PREFIX}{i_epoch:010}.{FILE_EXTENSION}"))
+    }
+
+    /// All checkpoints in `dir`, oldest first.
+    pub fn list(&self) -> io::Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let is_checkpoint = path.extension().is_some_and(|e| e == FILE_EXTENSION)
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(FILE_PREFIX));
+            if is_checkpoint {
+                paths.push(path);
+            }
+        }
+        // Epoch numbers are zero-padded, so lexical order is chronological order.
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// The most recent checkpoint in `dir`, if any.
+    pub fn latest(&self) -> io::Result<Option<PathBuf>> {
+        Ok(self.list()?.pop())
+    }
+
+    /// Deletes all but the `keep_last` most recent checkpoints.
+    fn rotate(&self) -> io::Result<()> {
+        if self.keep_last == 0 {
+            return Ok(());
+        }
+        let paths = self.list()?;
+        let n_outdated = paths.len().saturating_sub(self.keep_last);
+        for path in &paths[..n_outdated] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Gym<'_> {
+    /// Write checkpoints during `Gym::fit`, see `CheckpointConfig`.
+    pub fn set_checkpointing(&mut self, config: CheckpointConfig) {
+        self.checkpointing = Some(config);
+    }
+
+    /// Writes a checkpoint according to `set_checkpointing` and deletes outdated ones.
+    ///
+    /// Returns the path of the new checkpoint.
+    pub(crate) fn write_checkpoint(&self) -> io::Result<Option<PathBuf>> {
+        let Some(config) = &self.checkpointing else {
+            return Ok(None);
+        };
+        fs::create_dir_all(&config.dir)?;
+        let path = config.path_for_epoch(self.i_epoch);
+        self.save_checkpoint(&path)?;
+        config.rotate()?;
+        Ok(Some(path))
+    }
+
+    /// Saves the complete training state to `path`.
+    ///
+    /// That is the parameters along with the pruning mask, frozen layers and learning rate
+    /// multipliers, the step and epoch counters, the hyperparameters last used by
+    /// `Gym::fit` (which callbacks may have changed), and the best parameters seen on the
+    /// validation set. The validation samples themselves are not saved.
+    ///
+    /// Training is plain gradient descent over the samples in order, so there are no optimizer
+    /// moments or RNG state to save. A learning rate schedule is whatever callbacks do to the
+    /// hyperparameters: the learning rate it reached is saved, and callbacks can resume from the
+    /// step and epoch counters, but state kept by the callbacks themselves is not.
+    pub fn save_checkpoint(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut encoder = Encoder::new(MAGIC, VERSION);
+        encode_topology(&mut encoder, &self.topology);
+        encoder.usize(self.i_step);
+        encoder.usize(self.i_epoch);
+        encoder.bool(self.hyperparameters.is_some());
+        if let Some(hyperparameters) = &self.hyperparameters {
+            encoder.f32(hyperparameters.eta);
+            encoder.usize(hyperparameters.n_threads);
+            encoder.option_usize(hyperparameters.batch_size);
+        }
+        encoder.f32_slice(self.params().as_slice());
+        encoder.bool(self.params().mask().is_some());
+        if let Some(mask) = self.params().mask() {
+            encoder.bool_slice(mask);
+        }
+        for index in 0..self.topology.n_layers() {
+            encoder.bool(self.params().is_frozen(index));
+            encoder.f32(self.params().lr_multiplier(index));
+        }
+        encoder.bool(self.validation.is_some());
+        if let Some(validation) = &self.validation {
+            encoder.option_f32(validation.last_loss);
+            encoder.usize(validation.n_evaluations_since_best);
+            encoder.bool(validation.best.is_some());
+            if let Some(best) = &validation.best {
+                encoder.f32(best.loss);
+                encoder.usize(best.i_step);
+                encoder.f32_slice(&best.params);
+            }
+        }
+        // Write to a temporary file first, so that a job killed mid-write does not leave a
+        // corrupted checkpoint behind.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, encoder.into_bytes())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Restores the training state saved by `save_checkpoint`.
+    ///
+    /// The network's parameters, pruning mask, frozen layers and learning rate multipliers are
+    /// overwritten. Validation state is only restored if a validation set has been provided with
+    /// `set_validation`.
+    ///
+    /// Returns the hyperparameters to resume `Gym::fit` with, if the checkpoint was written by it.
+    /// Fails with `MlpError::TopologyMismatch` if the checkpoint is of a different network.
+    pub fn load_checkpoint(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Option<Hyperparameters>, MlpError> {
+        let bytes = fs::read(path)?;
+        let (mut decoder, version) = Decoder::new(&bytes, MAGIC)?;
+        if version != VERSION {
+            return Err(invalid_data("unsupported checkpoint version").into());
+        }
+        decode_topology(&mut decoder, &self.topology)?;
+        let i_step = decoder.usize()?;
+        let i_epoch = decoder.usize()?;
+        let hyperparameters = match decoder.bool()? {
+            true => Some(Hyperparameters {
+                eta: decoder.f32()?,
+                n_threads: decoder.usize()?,
+                batch_size: decoder.option_usize()?,
+            }),
+            false => None,
+        };
+        let params = decoder.f32_vec()?;
+        if params.len() != self.params().as_slice().len() {
+            return Err(invalid_data("parameter count mismatch").into());
+        }
+        let mask = match decoder.bool()? {
+            true => Some(decoder.bool_vec()?),
+            false => None,
+        };
+        if mask.as_ref().is_some_and(|mask| mask.len() != params.len()) {
+            return Err(invalid_data("parameter count mismatch").into());
+        }
+        let training = (0..self.topology.n_layers())
+            .map(|_| Ok((decoder.bool()?, decoder.f32()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut validation_state = None;
+        if decoder.bool()? {
+            let last_loss = decoder.option_f32()?;
+            let n_evaluations_since_best = decoder.usize()?;
+            let best = match decoder.bool()? {
+                true => Some(BestParams {
+                    loss: decoder.f32()?,
+                    i_step: decoder.usize()?,
+                    params: decoder.f32_vec()?.into(),
+                }),
+                false => None,
+            };
+            if best
+                .as_ref()
+                .is_some_and(|best| best.params.len() != params.len())
+            {
+                return Err(invalid_data("parameter count mismatch").into());
+            }
+            validation_state = Some((last_loss, n_evaluations_since_best, best));
+        }
+        decoder.finish()?;
+        // Only modify state once the whole file is known to be valid.
+        self.i_step = i_step;
+        self.i_epoch = i_epoch;
+        self.hyperparameters = hyperparameters;
+        let param_buffer = self.params_mut();
+        param_buffer.as_mut_slice().copy_from_slice(&params);
+        param_buffer.set_mask(mask.map(Into::into));
+        for (index, (frozen, lr_multiplier)) in training.into_iter().enumerate() {
+            param_buffer.set_frozen(index, frozen);
+            param_buffer.set_lr_multiplier(index, lr_multiplier);
+        }
+        if let (Some(validation), Some((last_loss, n_evaluations_since_best, best))) =
+            (&mut self.validation, validation_state)
+        {
+            validation.last_loss = last_loss;
+            validation.n_evaluations_since_best = n_evaluations_since_best;
+            validation.best = best;
+        }
+        Ok(hyperparameters)
+    }
+}
+
+pub(crate) fn encode_topology(encoder: &mut Encoder, topology: &Topology) {
+    encoder.usize(topology.n_inputs());
+    encoder.usize(topology.n_layers());
+    for layer_description in topology.layer_descriptions() {
+        encoder.usize(layer_description.n_neurons);
+        encoder.str(layer_description.phi.name());
+        encode_skip(encoder, layer_description.skip);
+        encoder.bool(layer_description.bias);
+        encode_kind(encoder, layer_description.kind);
+    }
+}
+
+fn encode_skip(encoder: &mut Encoder, skip: Option<Skip>) {
+    match skip {
+        None => encoder.u8(0),
+        Some(Skip::Add { from }) => {
+            encoder.u8(1);
+            encoder.usize(from);
+        }
+        Some(Skip::Concat { from }) => {
+            encoder.u8(2);
+            encoder.usize(from);
+        }
+    }
+}
+
+fn decode_skip(decoder: &mut Decoder) -> io::Result<Option<Skip>> {
+    match decoder.u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(Skip::Add {
+            from: decoder.usize()?,
+        })),
+        2 => Ok(Some(Skip::Concat {
+            from: decoder.usize()?,
+        })),
+        _ => Err(invalid_data("invalid skip connection")),
+    }
+}
+
+fn encode_kind(encoder: &mut Encoder, kind: LayerKind) {
+    let encode_pair = |encoder: &mut Encoder, (x, y): (usize, usize)| {
+        encoder.usize(x);
+        encoder.usize(y);
+    };
+    let encode_shape = |encoder: &mut Encoder, shape: Shape| {
+        encoder.usize(shape.channels);
+        encoder.usize(shape.height);
+        encoder.usize(shape.width);
+    };
+    match kind {
+        LayerKind::Dense => encoder.u8(0),
+        LayerKind::Recurrent => encoder.u8(4),
+        LayerKind::Conv(conv) => {
+            encoder.u8(1);
+            encode_shape(encoder, conv.input);
+            encoder.usize(conv.out_channels);
+            encode_pair(encoder, conv.kernel);
+            encode_pair(encoder, conv.stride);
+            encode_pair(encoder, conv.padding);
+        }
+        LayerKind::Pool(pool) => {
+            encoder.u8(2);
+            encode_shape(encoder, pool.input);
+            encoder.bool(pool.kind == PoolKind::Max);
+            encode_pair(encoder, pool.size);
+            encode_pair(encoder, pool.stride);
+        }
+        LayerKind::Embedding(embedding) => {
+            encoder.u8(3);
+            encoder.usize(embedding.n_ids);
+            encoder.usize(embedding.n_numeric);
+            encoder.usize(embedding.n_categories);
+            encoder.usize(embedding.dim);
+        }
+    }
+}
+
+fn decode_kind(decoder: &mut Decoder) -> io::Result<LayerKind> {
+    let decode_pair = |decoder: &mut Decoder| -> io::Result<(usize, usize)> {
+        Ok((decoder.usize()?, decoder.usize()?))
+    };
+    let decode_shape = |decoder: &mut Decoder| -> io::Result<Shape> {
+        Ok(Shape::new(
+            decoder.usize()?,
+            decoder.usize()?,
+            decoder.usize()?,
+        ))
+    };
+    match decoder.u8()? {
+        0 => Ok(LayerKind::Dense),
+        1 => Ok(LayerKind::Conv(Conv {
+            input: decode_shape(decoder)?,
+            out_channels: decoder.usize()?,
+            kernel: decode_pair(decoder)?,
+            stride: decode_pair(decoder)?,
+            padding: decode_pair(decoder)?,
+        })),
+        2 => Ok(LayerKind::Pool(Pool {
+            input: decode_shape(decoder)?,
+            kind: match decoder.bool()? {
+                true => PoolKind::Max,
+                false => PoolKind::Average,
+            },
+            size: decode_pair(decoder)?,
+            stride: decode_pair(decoder)?,
+        })),
+        3 => Ok(LayerKind::Embedding(Embedding {
+            n_ids: decoder.usize()?,
+            n_numeric: decoder.usize()?,
+            n_categories: decoder.usize()?,
+            dim: decoder.usize()?,
+        })),
+        4 => Ok(LayerKind::Recurrent),
+        _ => Err(invalid_data("invalid layer kind")),
+    }
+}
+
+/// Checks that the encoded topology is the same as `topology`.
+pub(crate) fn decode_topology(decoder: &mut Decoder, topology: &Topology) -> Result<(), MlpError> {
+    let mismatch = || MlpError::TopologyMismatch;
+    if decoder.usize()? != topology.n_inputs() || decoder.usize()? != topology.n_layers() {
+        return Err(mismatch());
+    }
+    for layer_description in topology.layer_descriptions() {
+        if decoder.usize()? != layer_description.n_neurons
+            || decoder.string()? != layer_description.phi.name()
+            || decode_skip(decoder)? != layer_description.skip
+            || decoder.bool()? != layer_description.bias
+            || decode_kind(decoder)? != layer_description.kind
+        {
+            return Err(mismatch());
+        }
+    }
+    Ok(())
+}
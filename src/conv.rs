@@ -0,0 +1,168 @@
+/// Shape of an activation, laid out channel by channel and row by row within a channel. 1D
+/// signals have a `height` of `1`.
+///
+/// Activations are always stored as flat columns, so a `Shape` only tells convolution and pooling
+/// layers how to read their input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shape {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Shape {
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        Self {
+            channels,
+            height,
+            width,
+        }
+    }
+
+    /// Number of values, i.e. the number of neurons of a layer of this shape.
+    pub fn size(&self) -> usize {
+        self.channels * self.height * self.width
+    }
+}
+
+/// Convolution layer with zero padding.
+///
+/// `w` has a row per output channel and a column per input channel and kernel position, with the
+/// kernel positions of each input channel row by row. Each output channel shares one bias.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conv {
+    pub input: Shape,
+    pub out_channels: usize,
+    /// `(height, width)`, same for `stride` and `padding`.
+    pub kernel: (usize, usize),
+    pub stride: (usize, usize),
+    pub padding: (usize, usize),
+}
+
+impl Conv {
+    /// `None` if the kernel does not fit in the padded input, or the kernel or stride is empty.
+    pub fn output(&self) -> Option<Shape> {
+        Some(Shape {
+            channels: self.out_channels,
+            height: window_output(
+                self.input.height,
+                self.kernel.0,
+                self.stride.0,
+                self.padding.0,
+            )?,
+            width: window_output(
+                self.input.width,
+                self.kernel.1,
+                self.stride.1,
+                self.padding.1,
+            )?,
+        })
+    }
+
+    /// Shape of `w`.
+    pub fn w_shape(&self) -> (usize, usize) {
+        let n_kernel = self.kernel.0 * self.kernel.1;
+        (self.out_channels, self.input.channels * n_kernel)
+    }
+
+    pub(crate) fn windows(&self) -> Windows {
+        Windows::new(self.input, self.kernel, self.stride, self.padding)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolKind {
+    Max,
+    Average,
+}
+
+/// Pooling layer without padding, each channel is pooled on its own. It has no params.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pool {
+    pub input: Shape,
+    pub kind: PoolKind,
+    /// `(height, width)`, same for `stride`.
+    pub size: (usize, usize),
+    pub stride: (usize, usize),
+}
+
+impl Pool {
+    /// `None` if the window does not fit in the input, or the window or stride is empty.
+    pub fn output(&self) -> Option<Shape> {
+        Some(Shape {
+            channels: self.input.channels,
+            height: window_output(self.input.height, self.size.0, self.stride.0, 0)?,
+            width: window_output(self.input.width, self.size.1, self.stride.1, 0)?,
+        })
+    }
+
+    pub(crate) fn windows(&self) -> Windows {
+        Windows::new(self.input, self.size, self.stride, (0, 0))
+    }
+}
+
+/// Number of positions of a window sliding over `len` values.
+fn window_output(len: usize, window: usize, stride: usize, padding: usize) -> Option<usize> {
+    let padded = len + 2 * padding;
+    match window != 0 && stride != 0 && padded >= window {
+        true => Some((padded - window) / stride + 1),
+        false => None,
+    }
+}
+
+/// Where a window sliding over a single channel reads from.
+pub(crate) struct Windows {
+    input: Shape,
+    output_width: usize,
+    window: (usize, usize),
+    stride: (usize, usize),
+    padding: (usize, usize),
+    /// Output positions within a channel.
+    pub(crate) n_positions: usize,
+    /// Positions within a window.
+    pub(crate) n_taps: usize,
+}
+
+impl Windows {
+    fn new(
+        input: Shape,
+        window: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+    ) -> Self {
+        let output_height = window_output(input.height, window.0, stride.0, padding.0).unwrap();
+        let output_width = window_output(input.width, window.1, stride.1, padding.1).unwrap();
+        Self {
+            input,
+            output_width,
+            window,
+            stride,
+            padding,
+            n_positions: output_height * output_width,
+            n_taps: window.0 * window.1,
+        }
+    }
+
+    /// Values within an input channel.
+    pub(crate) fn channel_size(&self) -> usize {
+        self.input.height * self.input.width
+    }
+
+    /// `(tap, i)` for every position `tap` of the window at output position `p`, with `i` the
+    /// position within the input channel it reads from. Taps over padding are left out.
+    pub(crate) fn taps(&self, p: usize) -> impl Iterator<Item = (usize, usize)> + use<'_> {
+        let (y, x) = (p / self.output_width, p % self.output_width);
+        let (window_height, window_width) = self.window;
+        (0..window_height).flat_map(move |dy| {
+            let iy = (y * self.stride.0 + dy)
+                .checked_sub(self.padding.0)
+                .filter(|&iy| iy < self.input.height);
+            (0..window_width).filter_map(move |dx| {
+                let ix = (x * self.stride.1 + dx)
+                    .checked_sub(self.padding.1)
+                    .filter(|&ix| ix < self.input.width)?;
+                Some((dy * window_width + dx, iy? * self.input.width + ix))
+            })
+        })
+    }
+}
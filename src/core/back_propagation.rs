@@ -3,16 +3,17 @@ use std::iter;
 use faer::prelude::*;
 
 use crate::{
-    assume,
+    Conv, Embedding, LayerKind, Pool, PoolKind, Skip, assume,
     core::{
         DerivBuffer, ParamBuffer, ResultBuffer, deriv_buffer, forward_unchecked, param_buffer,
-        result_buffer,
+        param_buffer::UpdateView, reset_state, result_buffer,
     },
 };
 
 /// Calculates derivative.
 ///
-/// Returns loss over the provided samples.
+/// Returns loss over the provided samples. Targets that are NaN are missing, they add neither
+/// loss nor derivative.
 ///
 /// # Safety
 ///
@@ -23,6 +24,45 @@ pub unsafe fn calculate_derivs(
     result_buffer: &mut ResultBuffer,
     deriv_buffer: &mut DerivBuffer,
     samples: &[f32],
+) -> f32 {
+    unsafe {
+        calculate_derivs_weighted_by(param_buffer, result_buffer, deriv_buffer, samples, None)
+    }
+}
+
+/// Same as `calculate_derivs`, but with the loss and derivative of sample `i` weighted by
+/// `weights[i]`. Averages are weighted averages, i.e. divided by the sum of `weights`.
+///
+/// # Safety
+///
+/// - same as `calculate_derivs`
+/// - `weights` must have a weight for each sample, and a positive sum
+pub unsafe fn calculate_weighted_derivs(
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    samples: &[f32],
+    weights: &[f32],
+) -> f32 {
+    unsafe {
+        calculate_derivs_weighted_by(
+            param_buffer,
+            result_buffer,
+            deriv_buffer,
+            samples,
+            Some(weights),
+        )
+    }
+}
+
+/// `calculate_derivs` with every sample weighing `1` if there are no `weights`.
+#[inline(always)]
+unsafe fn calculate_derivs_weighted_by(
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    samples: &[f32],
+    weights: Option<&[f32]>,
 ) -> f32 {
     unsafe { assume!(param_buffer.n_layers() == result_buffer.n_layers()) };
     unsafe { assume!(result_buffer.n_layers() == deriv_buffer.n_layers()) };
@@ -34,27 +74,107 @@ pub unsafe fn calculate_derivs(
     unsafe { assume!(samples.len().is_multiple_of(n_inputs + n_outputs)) };
     let mut loss = 0.0f32;
     deriv_buffer.clear_params();
-    let mut n = 0usize;
+    let first_trainable_layer = param_buffer.first_trainable_layer();
+    let mut total_weight = 0.0f32;
     let sample_size = n_inputs + n_outputs;
-    for sample_data in samples.chunks(sample_size) {
+    for (i, sample_data) in samples.chunks(sample_size).enumerate() {
         let x_i = unsafe { sample_data.get_unchecked(0..n_inputs) };
         let y_i = unsafe { sample_data.get_unchecked(n_inputs..n_inputs + n_outputs) };
-        n += 1;
-        loss += unsafe {
-            back_propagate_sample(
+        let weight = weights.map_or(1.0, |weights| weights[i]);
+        total_weight += weight;
+        loss += weight
+            * unsafe {
+                back_propagate_sample(
+                    param_buffer,
+                    result_buffer,
+                    deriv_buffer,
+                    ColRef::from_slice(x_i),
+                    ColRef::from_slice(y_i),
+                    weight,
+                    first_trainable_layer,
+                )
+            };
+    }
+    deriv_buffer.divide_params(total_weight);
+    loss / total_weight
+}
+
+/// Calculates derivative from a caller supplied derivative of the loss by the output of each
+/// sample, rather than from targets. Column `i` of `da_outputs` is `dl_i/da_output` of the sample
+/// in column `i` of `inputs`, scaled by `scales[i]` if provided.
+///
+/// Like `calculate_derivs`, the derivatives are averaged over the samples.
+///
+/// # Safety
+///
+/// - `param_buffer`, `result_buffer` and `deriv_buffer` must be of the same topology
+/// - `inputs` must have `n_inputs` rows, `da_outputs` must have `n_outputs` rows, and both must
+///   have the same non-zero number of columns, which is also the number of rows of `scales`
+pub unsafe fn calculate_output_derivs(
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    inputs: MatRef<f32>,
+    da_outputs: MatRef<f32>,
+    scales: Option<ColRef<f32>>,
+) {
+    unsafe { assume!(inputs.ncols() == da_outputs.ncols()) };
+    deriv_buffer.clear_params();
+    for i in 0..inputs.ncols() {
+        let scale = scales.map_or(1.0, |scales| scales[i]);
+        unsafe {
+            accumulate_output_derivs(
                 param_buffer,
                 result_buffer,
                 deriv_buffer,
-                ColRef::from_slice(x_i),
-                ColRef::from_slice(y_i),
+                inputs.col(i),
+                da_outputs.col(i),
+                scale,
             )
         };
     }
-    let n = n as f32;
-    for p in deriv_buffer.params_mut() {
-        *p /= n;
+    let n = inputs.ncols() as f32;
+    deriv_buffer.divide_params(n);
+}
+
+/// Runs `x` from a zero state, and adds the derivatives of `l` by the params onto `dw` and `db`
+/// of `deriv_buffer`, where `l` is a loss whose derivative by the network's output is
+/// `scale * da_output`.
+///
+/// Frozen layers are left out, as in `calculate_derivs`.
+///
+/// # Safety
+///
+/// - `param_buffer`, `result_buffer` and `deriv_buffer` must be of the same topology
+/// - `x` must have `n_inputs` rows, `da_output` must have `n_outputs` rows
+pub unsafe fn accumulate_output_derivs(
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    x: ColRef<f32>,
+    da_output: ColRef<f32>,
+    scale: f32,
+) {
+    reset_state(param_buffer, result_buffer);
+    unsafe { forward_unchecked(x, param_buffer, result_buffer) };
+    set_output_da(deriv_buffer, da_output);
+    let n_layers = deriv_buffer.n_layers();
+    let mut da = deriv_buffer.layer_mut(n_layers - 1).unwrap().da;
+    for k in 0..da.nrows() {
+        da[k] *= scale;
     }
-    loss / n
+    unsafe {
+        back_propagate_layers(
+            param_buffer,
+            result_buffer,
+            deriv_buffer,
+            x,
+            Wrt::Params {
+                first_trainable_layer: param_buffer.first_trainable_layer(),
+            },
+            None,
+        )
+    };
 }
 
 /// Calculates and applies derivative.
@@ -69,14 +189,46 @@ pub unsafe fn apply_derivs(param_buffer: &mut ParamBuffer, deriv_buffer: &DerivB
     // Params buffer and deriv buffer has the same layout for the weights and biases (deriv buffer
     // has an additional da section at the end, but it does not affect the layout for its param
     // section).
-    let param_buffer = param_buffer.as_mut_slice();
+    let UpdateView {
+        params: param_buffer,
+        mask,
+        training,
+    } = param_buffer.update_view();
     let deriv_param_buffer = deriv_buffer.params();
     unsafe { assume!(param_buffer.len() == deriv_param_buffer.len()) };
-    for (p, dp) in iter::zip(param_buffer, deriv_param_buffer) {
-        *p -= eta * (*dp);
+    // Embedding layers only have derivatives for the ids in the samples, so only those embeddings
+    // are updated.
+    for (u, range) in deriv_buffer.param_ranges() {
+        let training = unsafe { training.get_unchecked(u) };
+        if training.frozen {
+            continue;
+        }
+        let eta = eta * training.lr_multiplier;
+        // Safety: ranges are within the param section of the deriv buffer, which has the same
+        // layout as the param buffer.
+        let params = unsafe { param_buffer.get_unchecked_mut(range.clone()) };
+        let derivs = unsafe { deriv_param_buffer.get_unchecked(range.clone()) };
+        match mask {
+            None => {
+                for (p, dp) in iter::zip(params, derivs) {
+                    *p -= eta * (*dp);
+                }
+            }
+            // Pruned params are left at zero.
+            Some(mask) => {
+                let mask = unsafe { mask.get_unchecked(range) };
+                for ((p, dp), &keep) in iter::zip(iter::zip(params, derivs), mask) {
+                    if keep {
+                        *p -= eta * (*dp);
+                    }
+                }
+            }
+        }
     }
 }
 
+/// Layers before `first_trainable_layer` are frozen, back propagation stops there. Derivatives are
+/// scaled by `weight`, the returned squared error is not.
 #[inline(always)]
 unsafe fn back_propagate_sample(
     param_buffer: &ParamBuffer,
@@ -84,96 +236,355 @@ unsafe fn back_propagate_sample(
     deriv_buffer: &mut DerivBuffer,
     x: ColRef<f32>,
     y: ColRef<f32>,
+    weight: f32,
+    first_trainable_layer: usize,
 ) -> f32 {
+    reset_state(param_buffer, result_buffer);
     unsafe { forward_unchecked(x, param_buffer, result_buffer) };
+    // `da` is a per-sample vector, which is unlike `dw` and `db`. Layers add onto the `da` of every
+    // layer they read from (including through skip connections), so all of them are zeroed here.
+    deriv_buffer.clear_da();
+    let l_i = unsafe { output_da(result_buffer, deriv_buffer, y) };
+    if weight != 1.0 {
+        let n_layers = deriv_buffer.n_layers();
+        let mut da_output = deriv_buffer.layer_mut(n_layers - 1).unwrap().da;
+        for k in 0..da_output.nrows() {
+            da_output[k] *= weight;
+        }
+    }
+    unsafe {
+        back_propagate_layers(
+            param_buffer,
+            result_buffer,
+            deriv_buffer,
+            x,
+            Wrt::Params {
+                first_trainable_layer,
+            },
+            None,
+        )
+    };
+    l_i
+}
+
+/// Sets `da` of the output layer to the error against `y`, returns the squared error. Targets
+/// that are NaN are missing, their `da` is zero.
+///
+/// # Safety
+///
+/// - `result_buffer` and `deriv_buffer` must be of the same topology
+/// - `y` must have `n_outputs` rows
+#[inline(always)]
+pub(crate) unsafe fn output_da(
+    result_buffer: &ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    y: ColRef<f32>,
+) -> f32 {
+    let n_layers = result_buffer.n_layers();
+    let output = result_buffer.layer(n_layers - 1).unwrap().a;
+    let mut da_output = deriv_buffer.layer_mut(n_layers - 1).unwrap().da;
+    unsafe { assume!(y.nrows() == output.nrows()) };
+    unsafe { assume!(da_output.nrows() == output.nrows()) };
     let mut l_i = 0.0f32;
+    for k in 0..output.nrows() {
+        if y[k].is_nan() {
+            da_output[k] = 0.0;
+            continue;
+        }
+        // da[k] = e[k] for output layer.
+        let e_k = output[k] - y[k];
+        da_output[k] = e_k;
+        l_i += e_k.powi(2);
+    }
+    l_i
+}
+
+/// Calculates `dl/dx` for a sample `x` into `dx`, where `l` is a loss whose derivative by the
+/// network's output is `da_output`. For example, `da_output = e_j` makes `dx` the derivative of
+/// output `j` by the input.
+///
+/// `result_buffer` must hold the results of `x`, as left by `forward_unchecked` from a zero state
+/// (see `reset_state`), so that several `da_output` can be back propagated after one forward
+/// pass. Derivatives of the params (`dw` and `db`) are left untouched. Ids of an embedding layer
+/// get a zero derivative.
+///
+/// # Safety
+///
+/// - `param_buffer`, `result_buffer` and `deriv_buffer` must be of the same topology
+/// - `x` and `dx` must have `n_inputs` rows, `da_output` must have `n_outputs` rows
+pub unsafe fn back_propagate_input_unchecked(
+    param_buffer: &ParamBuffer,
+    result_buffer: &ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    x: ColRef<f32>,
+    da_output: ColRef<f32>,
+    mut dx: ColMut<f32>,
+) {
+    set_output_da(deriv_buffer, da_output);
+    dx.fill(0.0);
+    unsafe {
+        back_propagate_layers(
+            param_buffer,
+            result_buffer,
+            deriv_buffer,
+            x,
+            Wrt::Input(dx),
+            None,
+        )
+    };
+}
+
+/// Calculates the derivatives of `l` by every param, frozen or not, into `dw` and `db` of
+/// `deriv_buffer` (overwriting them), where `l` is a loss whose derivative by the network's output
+/// is `da_output`.
+///
+/// `result_buffer` must hold the results of `x`, see `back_propagate_input_unchecked`.
+///
+/// # Safety
+///
+/// - `param_buffer`, `result_buffer` and `deriv_buffer` must be of the same topology
+/// - `x` must have `n_inputs` rows, `da_output` must have `n_outputs` rows
+pub unsafe fn back_propagate_params_unchecked(
+    param_buffer: &ParamBuffer,
+    result_buffer: &ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    x: ColRef<f32>,
+    da_output: ColRef<f32>,
+) {
+    deriv_buffer.clear_params();
+    set_output_da(deriv_buffer, da_output);
+    unsafe {
+        back_propagate_layers(
+            param_buffer,
+            result_buffer,
+            deriv_buffer,
+            x,
+            Wrt::AllParams,
+            None,
+        )
+    };
+}
+
+/// Zeroes all the `da`s, then sets the one of the output layer to `da_output`.
+fn set_output_da(deriv_buffer: &mut DerivBuffer, da_output: ColRef<f32>) {
+    deriv_buffer.clear_da();
+    let n_layers = deriv_buffer.n_layers();
+    deriv_buffer
+        .layer_mut(n_layers - 1)
+        .unwrap()
+        .da
+        .copy_from(da_output);
+}
+
+/// What `back_propagate_layers` calculates the derivatives by, besides the `da`s of the layers.
+pub(crate) enum Wrt<'a> {
+    /// `dw` and `db` of the layers from `first_trainable_layer` on, back propagation stops there.
+    Params { first_trainable_layer: usize },
+    /// `dw` and `db` of every layer, including frozen ones.
+    AllParams,
+    /// The input, into the column, leaving `dw` and `db` untouched.
+    Input(ColMut<'a, f32>),
+}
+
+/// The previous step of a sequence, for back propagating through recurrent layers.
+pub(crate) struct Recurrence<'a> {
+    /// Results of the previous step, holding the state the current step read.
+    pub(crate) state: &'a ResultBuffer,
+    /// `da` of the state of each recurrent layer (empty for other layers), which is added onto
+    /// here, to be carried over to the previous step.
+    pub(crate) da_state: &'a mut [Vec<f32>],
+}
+
+/// Back propagates the `da` of the output layer (and any other `da` set by the caller) through
+/// the layers, for a sample `x` whose results are in `result_buffer`.
+///
+/// Recurrent layers are taken to have a zero state if there is no `recurrence`.
+///
+/// # Safety
+///
+/// - `param_buffer`, `result_buffer` and `deriv_buffer` must be of the same topology, and so must
+///   `recurrence.state`
+/// - `dx` of `Wrt::Input` must have `n_inputs` rows
+/// - `x` must have `n_inputs` rows
+pub(crate) unsafe fn back_propagate_layers(
+    param_buffer: &ParamBuffer,
+    result_buffer: &ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    x: ColRef<f32>,
+    wrt: Wrt,
+    mut recurrence: Option<Recurrence>,
+) {
+    // Whether all layers are taken as frozen or not, `None` goes by each layer's own setting.
+    let (first_trainable_layer, frozen, mut dx) = match wrt {
+        Wrt::Params {
+            first_trainable_layer,
+        } => (first_trainable_layer, None, None),
+        Wrt::AllParams => (0, Some(false), None),
+        Wrt::Input(dx) => (0, Some(true), Some(dx)),
+    };
     let n_layers = param_buffer.n_layers();
-    for u in (0..n_layers).rev() {
+    for u in (first_trainable_layer..n_layers).rev() {
         let u_prev = u.checked_sub(1);
         let a_prev = match u_prev {
             None => x,
             Some(u_prev) => unsafe { result_buffer.layer_unchecked(u_prev).a },
         };
         let layer_results = result_buffer.layer(u).unwrap();
+        // No need for da of the previous layer if it is frozen.
         let (da_prev, layer_derivs) = match u_prev {
-            None => (None, deriv_buffer.layer_mut(u).unwrap()),
-            Some(u_prev) => {
+            Some(u_prev) if u_prev >= first_trainable_layer => {
                 let [deriv_layer_prev, deriv_layer] =
                     unsafe { deriv_buffer.layer_disjoint_unchecked_mut([u_prev, u]) };
                 (Some(deriv_layer_prev.da), deriv_layer)
             }
+            Some(_) => (None, deriv_buffer.layer_mut(u).unwrap()),
+            None => (
+                dx.as_mut().map(|dx| dx.rb_mut()),
+                deriv_buffer.layer_mut(u).unwrap(),
+            ),
         };
         let nn_layer = param_buffer.layer(u).unwrap();
-        let is_output_layer = u + 1 == n_layers;
+        let is_frozen = frozen.unwrap_or_else(|| param_buffer.is_frozen(u));
         let n_k = layer_results.n;
-        let n_g = layer_results.n_previous;
-        unsafe { assume!(a_prev.nrows() == n_g) };
+        unsafe { assume!(a_prev.nrows() <= layer_results.n_previous) };
         unsafe { assume!(layer_results.z.nrows() == n_k) }
         unsafe { assume!(layer_results.a.nrows() == n_k) }
-        if is_output_layer {
-            unsafe { assume!(layer_results.a.nrows() == layer_results.n) };
-            unsafe { assume!(y.nrows() == layer_results.n) };
-            for k in 0..layer_results.n {
-                l_i += (layer_results.a[k] - y[k]).powi(2);
+        match param_buffer.kind(u) {
+            LayerKind::Dense => unsafe {
+                back_propagate_layer(
+                    is_frozen,
+                    a_prev,
+                    nn_layer,
+                    layer_derivs,
+                    layer_results,
+                    da_prev,
+                );
+            },
+            LayerKind::Recurrent => {
+                unsafe {
+                    back_propagate_layer(
+                        is_frozen,
+                        a_prev,
+                        nn_layer,
+                        layer_derivs,
+                        layer_results,
+                        da_prev,
+                    )
+                };
+                if let Some(recurrence) = &mut recurrence {
+                    unsafe {
+                        back_propagate_state(
+                            param_buffer,
+                            result_buffer,
+                            deriv_buffer,
+                            u,
+                            is_frozen,
+                            recurrence,
+                        )
+                    };
+                }
+            }
+            // Safety: `ParamBuffer::create` checks that `a_prev` is of the shape the layer reads.
+            LayerKind::Conv(conv) => unsafe {
+                back_propagate_conv(
+                    &conv,
+                    is_frozen,
+                    a_prev,
+                    nn_layer,
+                    layer_derivs,
+                    layer_results,
+                    da_prev,
+                );
+            },
+            LayerKind::Embedding(embedding) => {
+                unsafe {
+                    back_propagate_embedding(
+                        &embedding,
+                        is_frozen,
+                        a_prev,
+                        nn_layer,
+                        layer_derivs,
+                        layer_results,
+                        da_prev,
+                    )
+                };
+                if !is_frozen {
+                    for id in embedding.ids(a_prev) {
+                        deriv_buffer.touch(u, id);
+                    }
+                }
             }
+            LayerKind::Pool(pool) => unsafe {
+                back_propagate_pool(
+                    &pool,
+                    a_prev,
+                    nn_layer,
+                    layer_derivs,
+                    layer_results,
+                    da_prev,
+                );
+            },
         }
-        unsafe {
-            back_propagate_layer(
-                is_output_layer,
-                a_prev,
-                nn_layer,
-                layer_derivs,
-                layer_results,
-                da_prev,
-                y,
-            );
+        if let Some(skip) = param_buffer.skip(u) {
+            unsafe {
+                back_propagate_skip(
+                    param_buffer,
+                    result_buffer,
+                    deriv_buffer,
+                    u,
+                    is_frozen,
+                    skip,
+                    first_trainable_layer,
+                )
+            };
         }
     }
-    l_i
 }
 
+/// Back propagates through the weights of the previous layer's output, see
+/// `back_propagate_skip` for the ones of a skip connection.
 #[inline(always)]
 unsafe fn back_propagate_layer(
-    is_output_layer: bool,
+    is_frozen: bool,
     a_prev: ColRef<f32>,
     layer_params: param_buffer::LayerRef,
     mut layer_derivs: deriv_buffer::LayerMut,
     layer_results: result_buffer::LayerRef,
     mut da_prev: Option<ColMut<f32>>,
-    y: ColRef<f32>,
 ) {
     let n_k = layer_params.n;
-    let n_g = layer_params.n_previous;
+    // Only the first `n_g` columns of `w` if the layer has a `Skip::Concat`.
+    let n_g = a_prev.nrows();
     let phi = layer_params.phi;
     let w = layer_params.w;
     let a = layer_results.a;
     let z = layer_results.z;
     let da = layer_derivs.da;
+    let has_bias = layer_params.b.nrows() != 0;
     unsafe { assume!(w.nrows() == n_k) };
-    unsafe { assume!(w.ncols() == n_g) };
+    unsafe { assume!(w.ncols() >= n_g) };
+    unsafe { assume!(layer_derivs.db.nrows() == layer_params.b.nrows()) };
+    if has_bias {
+        unsafe { assume!(layer_params.b.nrows() == n_k) };
+    }
     unsafe { assume!(a.nrows() == n_k) };
-    unsafe { assume!(a_prev.nrows() == n_g) };
     unsafe { assume!(z.nrows() == n_k) };
-    unsafe { assume!(a.nrows() == n_k) };
     unsafe { assume!(da.nrows() == n_k) };
-    // Zero da_prev for the summing that happens later.
-    // `da` is a per-sample vector, which is unlike `dw` and `db`.
-    if let Some(ref mut da_prev) = da_prev {
-        for dag in da_prev.rb_mut().iter_mut() {
-            *dag = 0.0;
-        }
+    if let Some(ref da_prev) = da_prev {
+        unsafe { assume!(da_prev.nrows() == n_g) };
     }
     for k in 0..n_k {
         let phi_deriv_z = phi.deriv(z[k]);
-        let dak = match is_output_layer {
-            // da[k] = e[k] for output layer.
-            true => a[k] - y[k],
-            // Next layer have calculated it for us. (We're iterating through layers backwards)
-            false => da[k],
-        };
-        layer_derivs.db[k] += dak * phi_deriv_z;
+        // Next layers have calculated it for us. (We're iterating through layers backwards)
+        let dak = da[k];
+        // Frozen layers only pass da on to the previous layer.
+        if !is_frozen && has_bias {
+            layer_derivs.db[k] += dak * phi_deriv_z;
+        }
         for g in 0..n_g {
-            layer_derivs.dw[(k, g)] += dak * phi_deriv_z * a_prev[g];
+            if !is_frozen {
+                layer_derivs.dw[(k, g)] += dak * phi_deriv_z * a_prev[g];
+            }
             // Calculate da for the previous layer.
             if let Some(ref mut da_prev) = da_prev {
                 da_prev[g] += dak * phi_deriv_z * w[(k, g)];
@@ -181,3 +592,265 @@ unsafe fn back_propagate_layer(
         }
     }
 }
+
+/// Same as `back_propagate_layer` for a convolution layer, where each weight and bias is shared
+/// across the positions of its output channel.
+///
+/// # Safety
+///
+/// - `a_prev` must be of the shape of `conv.input`, and the other arguments of the layer
+#[inline(always)]
+unsafe fn back_propagate_conv(
+    conv: &Conv,
+    is_frozen: bool,
+    a_prev: ColRef<f32>,
+    layer_params: param_buffer::LayerRef,
+    mut layer_derivs: deriv_buffer::LayerMut,
+    layer_results: result_buffer::LayerRef,
+    mut da_prev: Option<ColMut<f32>>,
+) {
+    let windows = conv.windows();
+    let n_p = windows.n_positions;
+    let n_taps = windows.n_taps;
+    let n_i = windows.channel_size();
+    let phi = layer_params.phi;
+    let w = layer_params.w;
+    let z = layer_results.z;
+    let da = layer_derivs.da;
+    let has_bias = layer_params.b.nrows() != 0;
+    // Safety: function's safety contract.
+    unsafe { assume!(a_prev.nrows() == conv.input.channels * n_i) };
+    unsafe { assume!(z.nrows() == conv.out_channels * n_p) };
+    unsafe { assume!(da.nrows() == conv.out_channels * n_p) };
+    unsafe { assume!(w.nrows() == conv.out_channels) };
+    unsafe { assume!(w.ncols() == conv.input.channels * n_taps) };
+    unsafe { assume!(layer_derivs.dw.nrows() == w.nrows()) };
+    unsafe { assume!(layer_derivs.dw.ncols() == w.ncols()) };
+    unsafe { assume!(layer_derivs.db.nrows() == layer_params.b.nrows()) };
+    if has_bias {
+        unsafe { assume!(layer_params.b.nrows() == conv.out_channels) };
+    }
+    if let Some(ref da_prev) = da_prev {
+        unsafe { assume!(da_prev.nrows() == a_prev.nrows()) };
+    }
+    for c_out in 0..conv.out_channels {
+        for p in 0..n_p {
+            let k = c_out * n_p + p;
+            let dzk = da[k] * phi.deriv(z[k]);
+            if !is_frozen && has_bias {
+                layer_derivs.db[c_out] += dzk;
+            }
+            for (tap, i) in windows.taps(p) {
+                for c_in in 0..conv.input.channels {
+                    let j = c_in * n_taps + tap;
+                    let g = c_in * n_i + i;
+                    if !is_frozen {
+                        layer_derivs.dw[(c_out, j)] += dzk * a_prev[g];
+                    }
+                    if let Some(ref mut da_prev) = da_prev {
+                        da_prev[g] += dzk * w[(c_out, j)];
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Back propagates onto the embeddings of the ids in `x`. It is always the first layer, so
+/// `da_prev` is the derivative by the input, of which only the numeric inputs get a value.
+///
+/// # Safety
+///
+/// - `x` must be of `embedding.n_inputs()` rows, and the other arguments of the layer
+#[inline(always)]
+unsafe fn back_propagate_embedding(
+    embedding: &Embedding,
+    is_frozen: bool,
+    x: ColRef<f32>,
+    layer_params: param_buffer::LayerRef,
+    mut layer_derivs: deriv_buffer::LayerMut,
+    layer_results: result_buffer::LayerRef,
+    da_prev: Option<ColMut<f32>>,
+) {
+    let dim = embedding.dim;
+    let n_embedded = embedding.n_ids * dim;
+    let phi = layer_params.phi;
+    let z = layer_results.z;
+    let da = layer_derivs.da;
+    // Safety: function's safety contract.
+    unsafe { assume!(x.nrows() == embedding.n_inputs()) };
+    unsafe { assume!(z.nrows() == embedding.n_outputs()) };
+    unsafe { assume!(da.nrows() == embedding.n_outputs()) };
+    unsafe { assume!(layer_derivs.dw.nrows() == dim) };
+    unsafe { assume!(layer_derivs.dw.ncols() == embedding.n_categories) };
+    if let Some(mut da_prev) = da_prev {
+        unsafe { assume!(da_prev.nrows() == embedding.n_inputs()) };
+        for i in 0..embedding.n_numeric {
+            let k = n_embedded + i;
+            da_prev[embedding.n_ids + i] += da[k] * phi.deriv(z[k]);
+        }
+    }
+    if is_frozen {
+        return;
+    }
+    for (j, id) in embedding.ids(x).enumerate() {
+        for r in 0..dim {
+            let k = j * dim + r;
+            layer_derivs.dw[(r, id)] += da[k] * phi.deriv(z[k]);
+        }
+    }
+}
+
+/// Back propagates through a pooling layer, which has no params. Max pooling passes `da` on to
+/// the first maximum of each window, average pooling spreads it evenly over the window.
+///
+/// # Safety
+///
+/// - `a_prev` must be of the shape of `pool.input`, and the other arguments of the layer
+#[inline(always)]
+unsafe fn back_propagate_pool(
+    pool: &Pool,
+    a_prev: ColRef<f32>,
+    layer_params: param_buffer::LayerRef,
+    layer_derivs: deriv_buffer::LayerMut,
+    layer_results: result_buffer::LayerRef,
+    da_prev: Option<ColMut<f32>>,
+) {
+    let Some(mut da_prev) = da_prev else {
+        return;
+    };
+    let windows = pool.windows();
+    let n_p = windows.n_positions;
+    let n_i = windows.channel_size();
+    let phi = layer_params.phi;
+    let z = layer_results.z;
+    let da = layer_derivs.da;
+    // Safety: function's safety contract.
+    unsafe { assume!(a_prev.nrows() == pool.input.channels * n_i) };
+    unsafe { assume!(da_prev.nrows() == a_prev.nrows()) };
+    unsafe { assume!(z.nrows() == pool.input.channels * n_p) };
+    unsafe { assume!(da.nrows() == pool.input.channels * n_p) };
+    for c in 0..pool.input.channels {
+        let a_prev = a_prev.subrows(c * n_i, n_i);
+        let mut da_prev = da_prev.rb_mut().subrows_mut(c * n_i, n_i);
+        for p in 0..n_p {
+            let k = c * n_p + p;
+            let dzk = da[k] * phi.deriv(z[k]);
+            match pool.kind {
+                PoolKind::Max => {
+                    let i_max = windows
+                        .taps(p)
+                        .map(|(_, i)| i)
+                        .reduce(|i_max, i| match a_prev[i] > a_prev[i_max] {
+                            true => i,
+                            false => i_max,
+                        })
+                        .unwrap();
+                    da_prev[i_max] += dzk;
+                }
+                PoolKind::Average => {
+                    let dzk = dzk / windows.n_taps as f32;
+                    for (_, i) in windows.taps(p) {
+                        da_prev[i] += dzk;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Back propagates through the weights of the state of recurrent layer `u`, which are the last
+/// columns of its `w`, onto `da` of the state at the previous step.
+#[inline(always)]
+unsafe fn back_propagate_state(
+    param_buffer: &ParamBuffer,
+    result_buffer: &ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    u: usize,
+    is_frozen: bool,
+    recurrence: &mut Recurrence,
+) {
+    let layer_params = param_buffer.layer(u).unwrap();
+    let z = unsafe { result_buffer.layer_unchecked(u).z };
+    let h_prev = unsafe { recurrence.state.layer_unchecked(u).a };
+    let mut layer_derivs = deriv_buffer.layer_mut(u).unwrap();
+    let da = layer_derivs.da.rb();
+    let da_state = &mut recurrence.da_state[u];
+    let n_k = layer_params.n;
+    let w = layer_params.w;
+    unsafe { assume!(w.ncols() >= n_k) };
+    let offset = w.ncols() - n_k;
+    unsafe { assume!(w.nrows() == n_k) };
+    unsafe { assume!(z.nrows() == n_k) };
+    unsafe { assume!(da.nrows() == n_k) };
+    unsafe { assume!(h_prev.nrows() == n_k) };
+    unsafe { assume!(da_state.len() == n_k) };
+    for k in 0..n_k {
+        let dzk = da[k] * layer_params.phi.deriv(z[k]);
+        for g in 0..n_k {
+            if !is_frozen {
+                layer_derivs.dw[(k, offset + g)] += dzk * h_prev[g];
+            }
+            da_state[g] += dzk * w[(k, offset + g)];
+        }
+    }
+}
+
+/// Back propagates through the skip connection of layer `u`, onto the `da` of the layer it comes
+/// from and, for `Skip::Concat`, the weights of that layer's output.
+#[inline(always)]
+unsafe fn back_propagate_skip(
+    param_buffer: &ParamBuffer,
+    result_buffer: &ResultBuffer,
+    deriv_buffer: &mut DerivBuffer,
+    u: usize,
+    is_frozen: bool,
+    skip: Skip,
+    first_trainable_layer: usize,
+) {
+    let from = skip.from();
+    unsafe { assume!(from < u) };
+    // No need for da of the layer the connection comes from if it is frozen.
+    let needs_da_from = from >= first_trainable_layer;
+    let [layer_derivs_from, mut layer_derivs] =
+        unsafe { deriv_buffer.layer_disjoint_unchecked_mut([from, u]) };
+    let mut da_from = layer_derivs_from.da;
+    let da = layer_derivs.da.rb();
+    match skip {
+        // a = phi(z) + a_from, so da_from = da.
+        Skip::Add { .. } => {
+            unsafe { assume!(da_from.nrows() == da.nrows()) };
+            if needs_da_from {
+                for k in 0..da.nrows() {
+                    da_from[k] += da[k];
+                }
+            }
+        }
+        // Same as `back_propagate_layer`, over the last columns of `w`.
+        Skip::Concat { .. } => {
+            let layer_params = param_buffer.layer(u).unwrap();
+            let z = unsafe { result_buffer.layer_unchecked(u).z };
+            let a_from = unsafe { result_buffer.layer_unchecked(from).a };
+            let n_k = layer_params.n;
+            let n_from = a_from.nrows();
+            let w = layer_params.w;
+            unsafe { assume!(w.ncols() >= n_from) };
+            let offset = w.ncols() - n_from;
+            unsafe { assume!(w.nrows() == n_k) };
+            unsafe { assume!(z.nrows() == n_k) };
+            unsafe { assume!(da.nrows() == n_k) };
+            unsafe { assume!(da_from.nrows() == n_from) };
+            for k in 0..n_k {
+                let dzk = da[k] * layer_params.phi.deriv(z[k]);
+                for g in 0..n_from {
+                    if !is_frozen {
+                        layer_derivs.dw[(k, offset + g)] += dzk * a_from[g];
+                    }
+                    if needs_da_from {
+                        da_from[g] += dzk * w[(k, offset + g)];
+                    }
+                }
+            }
+        }
+    }
+}
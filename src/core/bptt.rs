@@ -0,0 +1,162 @@
+use std::iter;
+
+use crate::{
+    LayerKind, SequenceConfig, assume,
+    core::{
+        DerivBuffer, ParamBuffer, Recurrence, ResultBuffer, Wrt, back_propagate_layers,
+        forward_unchecked, output_da, reset_state,
+    },
+};
+
+/// Calculates derivative over `samples` grouped into sequences according to `config`, back
+/// propagating through time across the steps of each sequence.
+///
+/// `result_buffers[j + 1]` holds the results of the `j`th step of the current truncation window,
+/// and `result_buffers[0]` holds the state the window starts from.
+///
+/// Returns loss over the provided sequences.
+///
+/// # Safety
+///
+/// - `param_buffer`, `deriv_buffer` and every buffer in `result_buffers` must be of the same
+///   topology
+/// - `result_buffers` must have more than `config.chunk_len()` buffers
+/// - `samples.len()` must be a multiple of `config.sample_size(n_inputs, n_outputs)`
+pub unsafe fn calculate_sequence_derivs(
+    param_buffer: &ParamBuffer,
+    result_buffers: &mut [ResultBuffer],
+    deriv_buffer: &mut DerivBuffer,
+    samples: &[f32],
+    config: &SequenceConfig,
+) -> f32 {
+    let (n_inputs, n_outputs) = {
+        let layer0 = param_buffer.layer(0).unwrap();
+        let layer_last = param_buffer.layer(param_buffer.n_layers() - 1).unwrap();
+        (layer0.n_previous, layer_last.n)
+    };
+    let sample_size = config.sample_size(n_inputs, n_outputs);
+    let chunk_len = config.chunk_len();
+    unsafe { assume!(samples.len().is_multiple_of(sample_size)) };
+    unsafe { assume!(result_buffers.len() > chunk_len) };
+    deriv_buffer.clear_params();
+    let first_trainable_layer = param_buffer.first_trainable_layer();
+    let mut da_state: Vec<Vec<f32>> = (0..param_buffer.n_layers())
+        .map(|u| match param_buffer.kind(u) {
+            LayerKind::Recurrent => vec![0.0; param_buffer.layer(u).unwrap().n],
+            _ => Vec::new(),
+        })
+        .collect();
+    let mut loss = 0.0f32;
+    let mut n = 0usize;
+    for sequence in samples.chunks(sample_size) {
+        n += 1;
+        reset_state(param_buffer, &mut result_buffers[0]);
+        for chunk_start in (0..config.len).step_by(chunk_len) {
+            let chunk = chunk_start..(chunk_start + chunk_len).min(config.len);
+            for (j, t) in chunk.clone().enumerate() {
+                let (x, _) = config.step(sequence, t, n_inputs, n_outputs);
+                let [state, results] = result_buffers.get_disjoint_mut([j, j + 1]).unwrap();
+                copy_state(param_buffer, state, results);
+                unsafe { forward_unchecked(x, param_buffer, results) };
+            }
+            // Nothing flows in from beyond the end of the window.
+            for da in &mut da_state {
+                da.fill(0.0);
+            }
+            for (j, t) in chunk.clone().enumerate().rev() {
+                let (x, y) = config.step(sequence, t, n_inputs, n_outputs);
+                let (state, results) = (&result_buffers[j], &result_buffers[j + 1]);
+                deriv_buffer.clear_da();
+                if let Some(y) = y {
+                    loss += unsafe { output_da(results, deriv_buffer, y) };
+                }
+                // The state this step outputs is read by the next step.
+                for (u, da_state) in da_state.iter_mut().enumerate() {
+                    if da_state.is_empty() {
+                        continue;
+                    }
+                    let da = deriv_buffer.layer_mut(u).unwrap().da;
+                    for (da_k, da_state_k) in iter::zip(da.iter_mut(), &mut *da_state) {
+                        *da_k += *da_state_k;
+                        *da_state_k = 0.0;
+                    }
+                }
+                unsafe {
+                    back_propagate_layers(
+                        param_buffer,
+                        results,
+                        deriv_buffer,
+                        x,
+                        Wrt::Params {
+                            first_trainable_layer,
+                        },
+                        Some(Recurrence {
+                            state,
+                            da_state: &mut da_state,
+                        }),
+                    )
+                };
+            }
+            // The next window starts from the state this one ends with.
+            let [state, results] = result_buffers.get_disjoint_mut([0, chunk.len()]).unwrap();
+            copy_state(param_buffer, results, state);
+        }
+    }
+    let n = n as f32;
+    deriv_buffer.divide_params(n);
+    loss / n
+}
+
+/// Runs `samples` grouped into sequences according to `config` through the network, without
+/// touching any derivative buffer.
+///
+/// Returns the summed squared error over the targets of the provided sequences, leaving out
+/// targets that are NaN.
+///
+/// # Safety
+///
+/// - `param_buffer` and `result_buffer` must be of the same topology
+/// - `samples.len()` must be a multiple of `config.sample_size(n_inputs, n_outputs)`
+pub unsafe fn sequence_loss_unchecked(
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    samples: &[f32],
+    config: &SequenceConfig,
+) -> f32 {
+    let (n_inputs, n_outputs) = {
+        let layer0 = param_buffer.layer(0).unwrap();
+        let layer_last = param_buffer.layer(param_buffer.n_layers() - 1).unwrap();
+        (layer0.n_previous, layer_last.n)
+    };
+    let sample_size = config.sample_size(n_inputs, n_outputs);
+    unsafe { assume!(samples.len().is_multiple_of(sample_size)) };
+    let mut loss = 0.0f32;
+    for sequence in samples.chunks(sample_size) {
+        reset_state(param_buffer, result_buffer);
+        for t in 0..config.len {
+            let (x, y) = config.step(sequence, t, n_inputs, n_outputs);
+            unsafe { forward_unchecked(x, param_buffer, result_buffer) };
+            if let Some(y) = y {
+                let u_last = result_buffer.n_layers() - 1;
+                let a = unsafe { result_buffer.layer_unchecked(u_last).a };
+                loss += iter::zip(a.iter(), y.iter())
+                    .filter(|(_, yk)| !yk.is_nan())
+                    .map(|(&ak, &yk)| (ak - yk).powi(2))
+                    .sum::<f32>();
+            }
+        }
+    }
+    loss
+}
+
+/// Copies the state of the recurrent layers from `from` to `to`.
+fn copy_state(param_buffer: &ParamBuffer, from: &ResultBuffer, to: &mut ResultBuffer) {
+    for u in 0..param_buffer.n_layers() {
+        if param_buffer.kind(u) == LayerKind::Recurrent {
+            to.layer_mut(u)
+                .unwrap()
+                .a
+                .copy_from(from.layer(u).unwrap().a);
+        }
+    }
+}
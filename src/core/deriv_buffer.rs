@@ -1,8 +1,8 @@
-use std::{array, iter, mem::transmute, ptr::NonNull, slice::GetDisjointMutError};
+use std::{array, iter, mem::transmute, ops::Range, ptr::NonNull, slice::GetDisjointMutError};
 
 use faer::prelude::*;
 
-use crate::{ColPtr, MatPtr, PrettyPrintDerivs, Topology};
+use crate::{ColPtr, LayerKind, MatPtr, PrettyPrintDerivs, Topology};
 
 #[derive(Clone, Copy)]
 #[allow(dead_code)]
@@ -68,11 +68,67 @@ pub struct LayerMut<'a> {
     pub da: ColMut<'a, f32>,
 }
 
+/// Columns of `dw` of an embedding layer that have been written to since the last
+/// `clear_params`, all other columns are zero.
+pub(crate) struct Touched {
+    columns: Vec<usize>,
+    is_touched: Box<[bool]>,
+}
+
+impl Touched {
+    fn new(n_columns: usize) -> Self {
+        Self {
+            columns: Vec::new(),
+            is_touched: vec![false; n_columns].into_boxed_slice(),
+        }
+    }
+
+    fn touch(&mut self, column: usize) {
+        if !self.is_touched[column] {
+            self.is_touched[column] = true;
+            self.columns.push(column);
+        }
+    }
+
+    fn clear(&mut self) {
+        for column in self.columns.drain(..) {
+            self.is_touched[column] = false;
+        }
+    }
+}
+
+/// See `DerivBuffer::param_ranges`, apart from `DerivBuffer` so that the buffer can be written to
+/// while iterating.
+fn param_ranges<'a>(
+    layers: &'a [LayerRaw],
+    touched: &'a [Option<Touched>],
+) -> impl Iterator<Item = (usize, Range<usize>)> + 'a {
+    let mut offset = 0usize;
+    iter::zip(layers, touched)
+        .enumerate()
+        .flat_map(move |(index, (layer, touched))| {
+            let n_rows = layer.dw.nrows;
+            let n_params = n_rows * layer.dw.ncols + layer.db.nrows;
+            let start = offset;
+            offset += n_params;
+            let (whole, columns) = match touched {
+                None => (Some((index, start..start + n_params)), [].iter()),
+                Some(touched) => (None, touched.columns.iter()),
+            };
+            whole.into_iter().chain(columns.map(move |&column| {
+                let start = start + column * n_rows;
+                (index, start..start + n_rows)
+            }))
+        })
+}
+
 /// Buffer needed for performing back propagation on neural network.
 pub struct DerivBuffer {
     layers: Box<[LayerRaw]>,
     da_start: usize,
     buffer: Box<[f32]>,
+    /// `Some` for embedding layers, whose derivatives are sparse.
+    touched: Box<[Option<Touched>]>,
 }
 
 unsafe impl Send for DerivBuffer {}
@@ -83,18 +139,17 @@ impl DerivBuffer {
         let (n_floats, da_start) = {
             let mut n_floats = 0usize;
             let mut da_start = 0usize;
-            let mut n_previous = topology.n_inputs();
-            for layer_description in topology.layer_descriptions() {
+            for (index, layer_description) in topology.layer_descriptions().iter().enumerate() {
                 let n = layer_description.n_neurons;
-                let dw_size = n * n_previous;
-                let db_size = n;
+                let (n_w_rows, n_w_cols) = topology.layer_w_shape(index);
+                let dw_size = n_w_rows * n_w_cols;
+                let db_size = topology.layer_n_biases(index);
                 let da_size = n;
                 n_floats += dw_size; // dw
                 n_floats += db_size; // db
                 n_floats += da_size; // da
                 da_start += dw_size; // db
                 da_start += db_size; // db
-                n_previous = n;
             }
             (n_floats, da_start)
         };
@@ -103,41 +158,84 @@ impl DerivBuffer {
         let buffer_ptr = NonNull::from_ref(&buffer[0]);
         let layers: Box<[LayerRaw]> = unsafe {
             let mut layers = Box::new_uninit_slice(topology.layer_descriptions().len());
-            let mut n_previous = topology.n_inputs();
             let mut counter_params = 0usize;
             let mut counter_da = da_start;
-            for (layer, layer_description) in
-                iter::zip(&mut layers[..], topology.layer_descriptions())
+            for (index, (layer, layer_description)) in
+                iter::zip(&mut layers[..], topology.layer_descriptions()).enumerate()
             {
                 let n = layer_description.n_neurons;
+                let n_previous = topology.layer_n_inputs(index);
+                let (n_w_rows, n_w_cols) = topology.layer_w_shape(index);
+                let n_b = topology.layer_n_biases(index);
                 let offset_dw = counter_params;
-                let offset_db = counter_params + n * n_previous;
-                counter_params = offset_db + n;
+                let offset_db = counter_params + n_w_rows * n_w_cols;
+                counter_params = offset_db + n_b;
                 let offset_da = counter_da;
                 counter_da += n;
                 // Safety: offset_w, offset_b < buffer.len(), so we're offseting within the buffer.
                 layer.write(LayerRaw {
                     n,
                     n_previous,
-                    dw: MatPtr::with_offset(buffer_ptr, offset_dw, n, n_previous),
-                    db: ColPtr::with_offset(buffer_ptr, offset_db, n),
+                    dw: MatPtr::with_offset(buffer_ptr, offset_dw, n_w_rows, n_w_cols),
+                    db: ColPtr::with_offset(buffer_ptr, offset_db, n_b),
                     da: ColPtr::with_offset(buffer_ptr, offset_da, n),
                 });
-                n_previous = n;
             }
             // Safety: all layers are initialized in the loop above.
             layers.assume_init()
         };
+        let touched = topology
+            .layer_descriptions()
+            .iter()
+            .map(|layer_description| match layer_description.kind {
+                LayerKind::Embedding(embedding) => Some(Touched::new(embedding.n_categories)),
+                _ => None,
+            })
+            .collect();
         Self {
             layers,
             da_start,
             buffer,
+            touched,
         }
     }
 
     /// Zero all the `dw` and `db`s.
     pub(crate) fn clear_params(&mut self) {
-        bytemuck::fill_zeroes(self.params_mut());
+        for (_, range) in param_ranges(&self.layers, &self.touched) {
+            bytemuck::fill_zeroes(&mut self.buffer[range]);
+        }
+        for touched in self.touched.iter_mut().flatten() {
+            touched.clear();
+        }
+    }
+
+    /// Divides all the `dw` and `db`s by `divisor`, e.g. to average them over the samples.
+    pub(crate) fn divide_params(&mut self, divisor: f32) {
+        for (_, range) in param_ranges(&self.layers, &self.touched) {
+            for p in &mut self.buffer[range] {
+                *p /= divisor;
+            }
+        }
+    }
+
+    /// Records that column `column` of `dw` of embedding layer `index` is written to.
+    pub(crate) fn touch(&mut self, index: usize, column: usize) {
+        if let Some(touched) = &mut self.touched[index] {
+            touched.touch(column);
+        }
+    }
+
+    /// Ranges of the params section that can be non-zero, along with the layer they are of. That
+    /// is every layer as a whole, except for embedding layers, which only have their touched
+    /// columns of `dw`.
+    pub(crate) fn param_ranges(&self) -> impl Iterator<Item = (usize, Range<usize>)> + '_ {
+        param_ranges(&self.layers, &self.touched)
+    }
+
+    /// Zero all the `da`s.
+    pub(crate) fn clear_da(&mut self) {
+        bytemuck::fill_zeroes(&mut self.buffer[self.da_start..]);
     }
 
     /// Number of layers in the neural network.
@@ -155,11 +253,6 @@ impl DerivBuffer {
         &self.buffer[0..self.da_start]
     }
 
-    /// `&mut` reference to the params section (storage of `dw` and `db`s) of the buffer.
-    pub(crate) fn params_mut(&mut self) -> &mut [f32] {
-        &mut self.buffer[0..self.da_start]
-    }
-
     /// # Safety
     ///
     /// - `index` must be in range.
@@ -1,6 +1,11 @@
-use faer::{col::AsColRef as _, linalg::matmul::matmul, prelude::*};
+use std::iter;
 
-use crate::{assume, core::{result_buffer, ParamBuffer, ResultBuffer}};
+use faer::{linalg::matmul::matmul, prelude::*};
+
+use crate::{
+    Conv, Embedding, LayerKind, Pool, PoolKind, Skip, assume,
+    core::{ParamBuffer, ResultBuffer, param_buffer},
+};
 
 /// # Safety
 ///
@@ -10,50 +15,285 @@ pub unsafe fn forward_unchecked(
     input: ColRef<f32>,
     param_buffer: &ParamBuffer,
     result_buffer: &mut ResultBuffer,
+) {
+    unsafe { forward_par_unchecked(input, param_buffer, result_buffer, Par::Seq) };
+}
+
+/// Same as `forward_unchecked`, but with the matrix multiplications parallelized according to
+/// `par`. Only worth it for wide layers.
+///
+/// # Safety
+///
+/// - `param_buffer` and `result_buffer` must be of the same topology
+/// - `input` must have the correct number of rows
+pub unsafe fn forward_par_unchecked(
+    input: ColRef<f32>,
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    par: Par,
 ) {
     // Safety: function's safety contract.
     unsafe { assume!(param_buffer.n_layers() == result_buffer.n_layers()) };
     for u in 0..param_buffer.n_layers() {
-        let _layer_prev: result_buffer::LayerMut;
         let layer_params = param_buffer.layer(u).unwrap();
-        let (a_prev, mut layer_results): (ColRef<f32>, result_buffer::LayerMut) =
-            match u.checked_sub(1) {
-                None => {
-                    let layer_results = result_buffer.layer_mut(u).unwrap();
-                    (input, layer_results)
-                }
-                Some(u_prev) => {
-                    let [layer_prev_results, layer_results] =
-                        unsafe { result_buffer.layer_disjoint_unchecked_mut([u_prev, u]) };
-                    _layer_prev = layer_prev_results;
-                    (_layer_prev.a.as_col_ref(), layer_results)
-                }
-            };
+        let skip = param_buffer.skip(u);
+        // Safety: only layer `u` is written to below, and both `u_prev` and `skip.from()` are
+        // before it.
+        let a_prev = match u.checked_sub(1) {
+            None => input,
+            Some(u_prev) => unsafe { result_buffer.a_unbounded(u_prev) },
+        };
+        let a_from = skip.map(|skip| unsafe { result_buffer.a_unbounded(skip.from()) });
+        let mut layer_results = result_buffer.layer_mut(u).unwrap();
         let n_k = layer_params.n;
-        let n_g = layer_params.n_previous;
         // Safety: function's safety contract.
-        unsafe { assume!(a_prev.nrows() == n_g) };
         unsafe { assume!(layer_results.z.nrows() == n_k) }
         unsafe { assume!(layer_results.a.nrows() == n_k) }
+        match param_buffer.kind(u) {
+            // Safety: function's safety contract.
+            LayerKind::Dense => {
+                let a_concat = match (skip, a_from) {
+                    (Some(Skip::Concat { .. }), Some(a_from)) => Some(a_from),
+                    _ => None,
+                };
+                unsafe {
+                    dense_forward(
+                        a_prev,
+                        a_concat,
+                        layer_params,
+                        layer_results.z.rb_mut(),
+                        par,
+                    )
+                };
+            }
+            // z = W * [a_prev; h] + b, where `a` still holds the state of the previous step.
+            LayerKind::Recurrent => unsafe {
+                dense_forward(
+                    a_prev,
+                    Some(layer_results.a.rb()),
+                    layer_params,
+                    layer_results.z.rb_mut(),
+                    par,
+                )
+            },
+            // Safety: `ParamBuffer::create` checks that `a_prev` is of the shape the layer reads.
+            LayerKind::Conv(conv) => unsafe {
+                conv_forward(&conv, a_prev, layer_params, layer_results.z.rb_mut())
+            },
+            LayerKind::Pool(pool) => unsafe {
+                pool_forward(&pool, a_prev, layer_results.z.rb_mut())
+            },
+            LayerKind::Embedding(embedding) => unsafe {
+                embedding_forward(&embedding, a_prev, layer_params, layer_results.z.rb_mut())
+            },
+        }
+        // a = phi(z);
+        for k in 0..n_k {
+            layer_results.a[k] = layer_params.phi.apply(layer_results.z[k]);
+        }
+        // a += a_from;
+        if let (Some(Skip::Add { .. }), Some(a_from)) = (skip, a_from) {
+            unsafe { assume!(a_from.nrows() == n_k) };
+            for k in 0..n_k {
+                layer_results.a[k] += a_from[k];
+            }
+        }
+    }
+}
+
+/// `z = W * [a_prev; a_concat] + b`, with `a_concat` being the output of layer `from` for a
+/// `Skip::Concat`, or the state of a recurrent layer.
+///
+/// # Safety
+///
+/// - `w` must have as many columns as `a_prev` and `a_concat` have rows
+#[inline(always)]
+unsafe fn dense_forward(
+    a_prev: ColRef<f32>,
+    a_concat: Option<ColRef<f32>>,
+    layer_params: param_buffer::LayerRef,
+    mut z: ColMut<f32>,
+    par: Par,
+) {
+    let n_k = layer_params.n;
+    let n_g = layer_params.w.ncols();
+    let n_concat = a_concat.map_or(0, |a_concat| a_concat.nrows());
+    // Safety: function's safety contract.
+    unsafe { assume!(a_prev.nrows() + n_concat == n_g) };
+    unsafe { assume!(z.nrows() == n_k) }
+    let has_bias = layer_params.b.nrows() != 0;
+    if has_bias {
         unsafe { assume!(layer_params.b.nrows() == n_k) }
-        unsafe { assume!(layer_params.w.nrows() == n_k) }
-        unsafe { assume!(layer_params.w.ncols() == n_g) }
-        // z = W * a_prev;
+    }
+    unsafe { assume!(layer_params.w.nrows() == n_k) }
+    // z = W * a_prev;
+    matmul(
+        // A = α*L*R + β*A
+        z.rb_mut(),                                // A = z
+        faer::Accum::Replace,                      // β = 0.0
+        layer_params.w.subcols(0, n_g - n_concat), // L = W
+        a_prev,                                    // R = a_prev
+        1.0,                                       // α = 1.0
+        par,
+    );
+    // z += W_concat * a_concat;
+    if let Some(a_concat) = a_concat {
         matmul(
-            // A = α*L*R + β*A
-            layer_results.z.rb_mut(), // A = z
-            faer::Accum::Replace,     // β = 0.0
-            layer_params.w,           // L = W
-            a_prev,                   // R = a_prev
-            1.0,                      // α = 1.0
-            Par::Seq,
+            z.rb_mut(),
+            faer::Accum::Add,
+            layer_params.w.subcols(n_g - n_concat, n_concat),
+            a_concat,
+            1.0,
+            par,
         );
-        // z += b; a = phi(z);
-        for k in 0..layer_params.n {
-            layer_results.z[k] += layer_params.b[k];
-            layer_results.a[k] = layer_params.phi.apply(layer_results.z[k]);
+    }
+    // z += b;
+    if has_bias {
+        for k in 0..n_k {
+            z[k] += layer_params.b[k];
+        }
+    }
+}
+
+/// `z` of a convolution layer, see `Conv`.
+///
+/// # Safety
+///
+/// - `a_prev` must be of the shape of `conv.input`, and `layer_params` and `z` of the layer
+#[inline(always)]
+unsafe fn conv_forward(
+    conv: &Conv,
+    a_prev: ColRef<f32>,
+    layer_params: param_buffer::LayerRef,
+    mut z: ColMut<f32>,
+) {
+    let windows = conv.windows();
+    let n_p = windows.n_positions;
+    let n_taps = windows.n_taps;
+    let n_i = windows.channel_size();
+    let w = layer_params.w;
+    let has_bias = layer_params.b.nrows() != 0;
+    // Safety: function's safety contract.
+    unsafe { assume!(a_prev.nrows() == conv.input.channels * n_i) };
+    unsafe { assume!(z.nrows() == conv.out_channels * n_p) };
+    unsafe { assume!(w.nrows() == conv.out_channels) };
+    unsafe { assume!(w.ncols() == conv.input.channels * n_taps) };
+    for c_out in 0..conv.out_channels {
+        let b = match has_bias {
+            true => layer_params.b[c_out],
+            false => 0.0,
+        };
+        for p in 0..n_p {
+            let mut zk = b;
+            for (tap, i) in windows.taps(p) {
+                for c_in in 0..conv.input.channels {
+                    zk += w[(c_out, c_in * n_taps + tap)] * a_prev[c_in * n_i + i];
+                }
+            }
+            z[c_out * n_p + p] = zk;
+        }
+    }
+}
+
+/// `z` of a pooling layer, see `Pool`.
+///
+/// # Safety
+///
+/// - `a_prev` must be of the shape of `pool.input`, and `z` of the layer
+#[inline(always)]
+unsafe fn pool_forward(pool: &Pool, a_prev: ColRef<f32>, mut z: ColMut<f32>) {
+    let windows = pool.windows();
+    let n_p = windows.n_positions;
+    let n_i = windows.channel_size();
+    // Safety: function's safety contract.
+    unsafe { assume!(a_prev.nrows() == pool.input.channels * n_i) };
+    unsafe { assume!(z.nrows() == pool.input.channels * n_p) };
+    for c in 0..pool.input.channels {
+        let a_prev = a_prev.subrows(c * n_i, n_i);
+        for p in 0..n_p {
+            let values = windows.taps(p).map(|(_, i)| a_prev[i]);
+            z[c * n_p + p] = match pool.kind {
+                PoolKind::Max => values.fold(f32::NEG_INFINITY, f32::max),
+                PoolKind::Average => values.sum::<f32>() / windows.n_taps as f32,
+            };
         }
     }
 }
 
+/// `z` of an embedding layer, see `Embedding`.
+///
+/// # Safety
+///
+/// - `x` must be of `embedding.n_inputs()` rows, and `layer_params` and `z` of the layer
+#[inline(always)]
+unsafe fn embedding_forward(
+    embedding: &Embedding,
+    x: ColRef<f32>,
+    layer_params: param_buffer::LayerRef,
+    mut z: ColMut<f32>,
+) {
+    let dim = embedding.dim;
+    let n_embedded = embedding.n_ids * dim;
+    // Safety: function's safety contract.
+    unsafe { assume!(x.nrows() == embedding.n_inputs()) };
+    unsafe { assume!(z.nrows() == embedding.n_outputs()) };
+    unsafe { assume!(layer_params.w.nrows() == dim) };
+    unsafe { assume!(layer_params.w.ncols() == embedding.n_categories) };
+    for (j, id) in embedding.ids(x).enumerate() {
+        z.rb_mut()
+            .subrows_mut(j * dim, dim)
+            .copy_from(layer_params.w.col(id));
+    }
+    z.subrows_mut(n_embedded, embedding.n_numeric)
+        .copy_from(x.subrows(embedding.n_ids, embedding.n_numeric));
+}
+
+/// Zeroes the state of the recurrent layers, see `LayerKind::Recurrent`.
+///
+/// # Panics
+///
+/// - if `param_buffer` and `result_buffer` are not of the same number of layers
+pub fn reset_state(param_buffer: &ParamBuffer, result_buffer: &mut ResultBuffer) {
+    assert!(param_buffer.n_layers() == result_buffer.n_layers());
+    for u in 0..param_buffer.n_layers() {
+        if param_buffer.kind(u) == LayerKind::Recurrent {
+            result_buffer.layer_mut(u).unwrap().a.fill(0.0);
+        }
+    }
+}
 
+/// Runs `samples` through the network without touching any derivative buffer.
+///
+/// Returns the summed squared error over the provided samples, leaving out targets that are NaN.
+/// Recurrent layers start from a zero state for every sample.
+///
+/// # Safety
+///
+/// - `param_buffer` and `result_buffer` must be of the same topology
+/// - all inputs and outputs in `samples` must be of the correct sizes
+pub unsafe fn loss_unchecked(
+    param_buffer: &ParamBuffer,
+    result_buffer: &mut ResultBuffer,
+    samples: &[f32],
+) -> f32 {
+    let (n_inputs, n_outputs) = {
+        let layer0 = param_buffer.layer(0).unwrap();
+        let layer_last = param_buffer.layer(param_buffer.n_layers() - 1).unwrap();
+        (layer0.n_previous, layer_last.n)
+    };
+    unsafe { assume!(samples.len().is_multiple_of(n_inputs + n_outputs)) };
+    let mut loss = 0.0f32;
+    for sample in samples.chunks(n_inputs + n_outputs) {
+        let x = ColRef::from_slice(&sample[0..n_inputs]);
+        let y = ColRef::from_slice(&sample[n_inputs..n_inputs + n_outputs]);
+        reset_state(param_buffer, result_buffer);
+        unsafe { forward_unchecked(x, param_buffer, result_buffer) };
+        let u_last = result_buffer.n_layers() - 1;
+        let a = unsafe { result_buffer.layer_unchecked(u_last).a };
+        loss += iter::zip(a.iter(), y.iter())
+            .filter(|(_, yk)| !yk.is_nan())
+            .map(|(&ak, &yk)| (ak - yk).powi(2))
+            .sum::<f32>();
+    }
+    loss
+}
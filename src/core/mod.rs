@@ -10,6 +10,8 @@ pub use result_buffer::ResultBuffer;
 
 mod forward;
 mod back_propagation;
+mod bptt;
 
 pub use forward::*;
 pub use back_propagation::*;
+pub use bptt::*;
@@ -1,9 +1,9 @@
-use std::{array, iter, mem::transmute, ptr::NonNull, slice::GetDisjointMutError};
+use std::{array, iter, mem::transmute, ops::Range, ptr::NonNull, slice::GetDisjointMutError};
 
 use faer::prelude::*;
 use rand::{Rng, distr::uniform::SampleRange, rngs::ThreadRng};
 
-use crate::{ColPtr, DynActivationFunction, MatPtr, PrettyPrintParams, Topology};
+use crate::{ColPtr, DynActivationFunction, LayerKind, MatPtr, PrettyPrintParams, Skip, Topology};
 
 #[derive(Clone, Copy)]
 #[allow(dead_code)]
@@ -55,10 +55,38 @@ pub struct LayerMut<'a> {
     pub phi: DynActivationFunction,
 }
 
+/// How a layer is trained, see `ParamBuffer::set_frozen` and `ParamBuffer::set_lr_multiplier`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct LayerTraining {
+    pub(crate) frozen: bool,
+    pub(crate) lr_multiplier: f32,
+}
+
+impl Default for LayerTraining {
+    fn default() -> Self {
+        Self {
+            frozen: false,
+            lr_multiplier: 1.0,
+        }
+    }
+}
+
+/// The buffer, along with what `apply_derivs` needs to know to update it.
+pub(crate) struct UpdateView<'a> {
+    pub(crate) params: &'a mut [f32],
+    pub(crate) mask: Option<&'a [bool]>,
+    pub(crate) training: &'a [LayerTraining],
+}
+
 /// Buffer for storing neural network parameters.
 pub struct ParamBuffer {
     layers: Box<[LayerRaw]>,
     buffer: Box<[f32]>,
+    /// `false` for pruned params, see `set_mask`.
+    mask: Option<Box<[bool]>>,
+    training: Box<[LayerTraining]>,
+    skips: Box<[Option<Skip>]>,
+    kinds: Box<[LayerKind]>,
 }
 
 unsafe impl Send for ParamBuffer {}
@@ -66,45 +94,68 @@ unsafe impl Sync for ParamBuffer {}
 
 impl ParamBuffer {
     pub fn create(topology: &Topology) -> Self {
-        let n_floats = {
-            let mut n_floats = 0usize;
-            let mut n_previous = topology.n_inputs();
-            for layer_description in topology.layer_descriptions() {
-                let n = layer_description.n_neurons;
-                n_floats += n * n_previous; // w
-                n_floats += n; // b
-                n_previous = n;
+        // Forward and back propagation rely on skip connections and the shapes of convolution and
+        // pooling layers being valid.
+        for (index, layer_description) in topology.layer_descriptions().iter().enumerate() {
+            assert!(topology.validate_kind(index).is_ok());
+            if let Some(skip) = layer_description.skip {
+                let from = skip.from();
+                assert!(from < index);
+                let n_from = topology.layer_descriptions()[from].n_neurons;
+                if let Skip::Add { .. } = skip {
+                    assert!(n_from == layer_description.n_neurons);
+                }
             }
-            n_floats
-        };
+        }
+        let n_floats = topology.n_params();
         assert!(n_floats != 0);
         let buffer: Box<[f32]> = bytemuck::zeroed_slice_box(n_floats);
         let buffer_ptr = NonNull::from_ref(&buffer[0]);
         let layers: Box<[LayerRaw]> = unsafe {
             let mut layers = Box::new_uninit_slice(topology.layer_descriptions().len());
-            let mut n_previous = topology.n_inputs();
             let mut counter = 0usize;
-            for (layer, layer_description) in
-                iter::zip(&mut layers[..], topology.layer_descriptions())
+            for (index, (layer, layer_description)) in
+                iter::zip(&mut layers[..], topology.layer_descriptions()).enumerate()
             {
                 let n = layer_description.n_neurons;
+                let n_previous = topology.layer_n_inputs(index);
+                let (n_w_rows, n_w_cols) = topology.layer_w_shape(index);
+                let n_b = topology.layer_n_biases(index);
                 let offset_w = counter;
-                let offset_b = counter + n * n_previous;
-                counter = offset_b + n;
-                // Safety: offset_w, offset_b < buffer.len(), so we're offseting within the buffer.
+                let offset_b = counter + n_w_rows * n_w_cols;
+                counter = offset_b + n_b;
+                // Safety: offset_w, offset_b <= buffer.len(), so we're offseting within the buffer
+                // (or right past its end for an empty last layer).
                 layer.write(LayerRaw {
                     n,
                     n_previous,
-                    w: MatPtr::with_offset(buffer_ptr, offset_w, n, n_previous),
-                    b: ColPtr::with_offset(buffer_ptr, offset_b, n),
+                    w: MatPtr::with_offset(buffer_ptr, offset_w, n_w_rows, n_w_cols),
+                    b: ColPtr::with_offset(buffer_ptr, offset_b, n_b),
                     phi: layer_description.phi,
                 });
-                n_previous = n;
             }
             // Safety: all layers are initialized in the loop above.
             layers.assume_init()
         };
-        Self { layers, buffer }
+        let training = vec![LayerTraining::default(); layers.len()].into_boxed_slice();
+        let skips = topology
+            .layer_descriptions()
+            .iter()
+            .map(|layer_description| layer_description.skip)
+            .collect();
+        let kinds = topology
+            .layer_descriptions()
+            .iter()
+            .map(|layer_description| layer_description.kind)
+            .collect();
+        Self {
+            layers,
+            buffer,
+            mask: None,
+            training,
+            skips,
+            kinds,
+        }
     }
 
     pub fn randomize(&mut self, range: impl SampleRange<f32> + Clone) {
@@ -112,6 +163,7 @@ impl ParamBuffer {
         for p in self.as_mut_slice() {
             *p = rng.random_range(range.clone());
         }
+        self.apply_mask();
     }
 
     pub fn pretty_print_layer(&self, index: usize) -> Option<PrettyPrintParams<'_>> {
@@ -131,6 +183,131 @@ impl ParamBuffer {
         &mut self.buffer
     }
 
+    /// Which params are kept, in the same layout as `as_slice`. `false` for pruned params.
+    pub fn mask(&self) -> Option<&[bool]> {
+        self.mask.as_deref()
+    }
+
+    /// Pruned params (`false` in `mask`) are zeroed, and kept at zero by `apply_derivs` and
+    /// `randomize`. `None` removes the mask without changing any param.
+    ///
+    /// # Panics
+    ///
+    /// - if `mask` is not of the same length as `as_slice`
+    pub fn set_mask(&mut self, mask: Option<Box<[bool]>>) {
+        if let Some(mask) = &mask {
+            assert!(mask.len() == self.buffer.len());
+        }
+        self.mask = mask;
+        self.apply_mask();
+    }
+
+    /// Zeroes the pruned params.
+    pub(crate) fn apply_mask(&mut self) {
+        if let Some(mask) = &self.mask {
+            for (p, &keep) in iter::zip(&mut self.buffer[..], &mask[..]) {
+                if !keep {
+                    *p = 0.0;
+                }
+            }
+        }
+    }
+
+    /// A frozen layer is skipped by `calculate_derivs` (its `dw` and `db` stay zero) and left
+    /// untouched by `apply_derivs`.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn set_frozen(&mut self, index: usize, frozen: bool) {
+        self.training[index].frozen = frozen;
+    }
+
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn is_frozen(&self, index: usize) -> bool {
+        self.training[index].frozen
+    }
+
+    /// `apply_derivs` updates the layer with `eta * lr_multiplier`. Defaults to `1.0`.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn set_lr_multiplier(&mut self, index: usize, lr_multiplier: f32) {
+        self.training[index].lr_multiplier = lr_multiplier;
+    }
+
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn lr_multiplier(&self, index: usize) -> f32 {
+        self.training[index].lr_multiplier
+    }
+
+    /// Index of the first layer that is not frozen, `n_layers` if all of them are.
+    pub(crate) fn first_trainable_layer(&self) -> usize {
+        self.training
+            .iter()
+            .position(|training| !training.frozen)
+            .unwrap_or(self.layers.len())
+    }
+
+    pub(crate) fn update_view(&mut self) -> UpdateView<'_> {
+        UpdateView {
+            params: &mut self.buffer,
+            mask: self.mask.as_deref(),
+            training: &self.training,
+        }
+    }
+
+    /// Indices within `as_slice` of the params that training may change, i.e. those not in a
+    /// frozen layer and not pruned.
+    pub(crate) fn free_indices(&self) -> Vec<usize> {
+        (0..self.n_layers())
+            .filter(|&index| !self.is_frozen(index))
+            .flat_map(|index| {
+                let (w, b) = self.layer_ranges(index);
+                w.chain(b)
+            })
+            .filter(|&p| self.mask().is_none_or(|mask| mask[p]))
+            .collect()
+    }
+
+    /// Ranges of `w` (column-major) and `b` of a layer within `as_slice`.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub(crate) fn layer_ranges(&self, index: usize) -> (Range<usize>, Range<usize>) {
+        let offset_w: usize = self.layers[..index]
+            .iter()
+            .map(|layer| layer.w.nrows * layer.w.ncols + layer.b.nrows)
+            .sum();
+        let layer = &self.layers[index];
+        let offset_b = offset_w + layer.w.nrows * layer.w.ncols;
+        (offset_w..offset_b, offset_b..offset_b + layer.b.nrows)
+    }
+
+    /// Skip connection of layer `index`, see `LayerDescription::skip`.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn skip(&self, index: usize) -> Option<Skip> {
+        self.skips[index]
+    }
+
+    /// See `LayerDescription::kind`.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn kind(&self, index: usize) -> LayerKind {
+        self.kinds[index]
+    }
+
     /// Number of layers in the neural network.
     pub fn n_layers(&self) -> usize {
         self.layers.len()
@@ -76,12 +76,12 @@ impl ResultBuffer {
         let buffer_ptr = NonNull::from_ref(&buffer[0]);
         let layers: Box<[LayerRaw]> = unsafe {
             let mut layers = Box::new_uninit_slice(topology.layer_descriptions().len());
-            let mut n_previous = topology.n_inputs();
             let mut counter = 0usize;
-            for (layer, layer_description) in
-                iter::zip(&mut layers[..], topology.layer_descriptions())
+            for (index, (layer, layer_description)) in
+                iter::zip(&mut layers[..], topology.layer_descriptions()).enumerate()
             {
                 let n = layer_description.n_neurons;
+                let n_previous = topology.layer_n_inputs(index);
                 let offset_z = counter;
                 let offset_a = counter + n;
                 counter = offset_a + n;
@@ -94,7 +94,6 @@ impl ResultBuffer {
                     z: ColPtr::with_offset(buffer_ptr, offset_z, n),
                     a: ColPtr::with_offset(buffer_ptr, offset_a, n),
                 });
-                n_previous = n;
             }
             // Safety: all layers are initialized in the loop above.
             layers.assume_init()
@@ -110,6 +109,31 @@ impl ResultBuffer {
         self.layers.len()
     }
 
+    /// `a` of layer `index`, not bound to the lifetime of `self`.
+    ///
+    /// # Safety
+    ///
+    /// - `index` must be in range
+    /// - `self` must outlive the returned column, and the column must not be read from while `a`
+    ///   of the layer is written to
+    #[inline(always)]
+    pub(crate) unsafe fn a_unbounded<'a>(&self, index: usize) -> ColRef<'a, f32> {
+        debug_assert!(index < self.n_layers());
+        // Safety: function's safety contract.
+        unsafe { self.layers.get_unchecked(index).as_ref().a }
+    }
+
+    /// Whether this buffer has the layout of a buffer created from `topology`.
+    pub fn is_of_topology(&self, topology: &Topology) -> bool {
+        self.n_layers() == topology.n_layers()
+            && iter::zip(&self.layers, topology.layer_descriptions())
+                .enumerate()
+                .all(|(index, (layer, description))| {
+                    layer.n == description.n_neurons
+                        && layer.n_previous == topology.layer_n_inputs(index)
+                })
+    }
+
     /// # Safety
     ///
     /// - `index` must be in range.
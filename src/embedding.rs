@@ -0,0 +1,64 @@
+use faer::prelude::*;
+
+use crate::MlpError;
+
+/// Embedding layer for categorical inputs, which must be the first layer.
+///
+/// The first `n_ids` inputs are category ids, integers within `0..n_categories` stored as `f32`.
+/// Each of them is replaced by its learned embedding of `dim` values, and the `n_numeric` inputs
+/// after them are passed on as they are, so the layer outputs `[e(x_0); ..; e(x_{n_ids-1});
+/// x_numeric]`.
+///
+/// `w` has a column per category, its embedding, and the layer has no bias. All id inputs share
+/// the same table, so give each categorical column its own range of ids for separate tables.
+///
+/// Only the embeddings of the ids in a batch get derivatives, and `apply_derivs` only updates
+/// those.
+///
+/// Checked APIs return `MlpError::CategoryId` if an id is not an integer within
+/// `0..n_categories`. Running the layer through an `_unchecked` API panics if an id is out of
+/// range, and truncates a fractional one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Embedding {
+    pub n_ids: usize,
+    pub n_numeric: usize,
+    pub n_categories: usize,
+    pub dim: usize,
+}
+
+impl Embedding {
+    /// Number of inputs the layer reads.
+    pub fn n_inputs(&self) -> usize {
+        self.n_ids + self.n_numeric
+    }
+
+    /// Number of values the layer outputs.
+    pub fn n_outputs(&self) -> usize {
+        self.n_ids * self.dim + self.n_numeric
+    }
+
+    /// Checks that the ids within `x` are integers within `0..n_categories`, see `ids`.
+    pub(crate) fn check_ids(&self, x: ColRef<f32>) -> Result<(), MlpError> {
+        let n_categories = self.n_categories as f32;
+        for (index, &value) in x.subrows(0, self.n_ids).iter().enumerate() {
+            // Also rejects NaN.
+            if !(value >= 0.0 && value < n_categories && value.fract() == 0.0) {
+                return Err(MlpError::CategoryId { index, value });
+            }
+        }
+        Ok(())
+    }
+
+    /// Category ids within `x`.
+    ///
+    /// # Panics
+    ///
+    /// - if an id is not within `0..n_categories`
+    pub(crate) fn ids(&self, x: ColRef<f32>) -> impl Iterator<Item = usize> {
+        let n_categories = self.n_categories;
+        x.subrows(0, self.n_ids).iter().map(move |&id| {
+            assert!((0.0..n_categories as f32).contains(&id));
+            id as usize
+        })
+    }
+}
@@ -0,0 +1,216 @@
+use std::io;
+
+use derive_more::{Display, Error, From};
+use faer::{ColRef, MatRef};
+
+use crate::{SequenceConfig, Topology};
+
+/// Invalid configuration of a `Topology`.
+#[derive(Debug, Display, Error, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyError {
+    #[display("topology has no inputs")]
+    NoInputs,
+    #[display("topology has no layers")]
+    NoLayers,
+    #[display("layer {index} has no neurons")]
+    EmptyLayer { index: usize },
+    #[display("layer {index} has a skip connection from layer {from}, which is not before it")]
+    SkipForward { index: usize, from: usize },
+    #[display("layer {index} adds the output of layer {from}, which is of a different size")]
+    SkipSize { index: usize, from: usize },
+    #[display("layer {index} reads an input of {expected} values, found {found}")]
+    InputShape {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[display("layer {index} outputs {expected} values, found {found} neurons")]
+    OutputShape {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[display("the kernel or window of layer {index} does not fit its input")]
+    Window { index: usize },
+    #[display("layer {index} is not dense, so it cannot have a concatenating skip connection")]
+    SpatialConcat { index: usize },
+    #[display("layer {index} is an embedding layer, which must be the first layer")]
+    Embedding { index: usize },
+    #[display("topology has no params, e.g. it only has pooling layers")]
+    NoParams,
+}
+
+/// Errors from the checked APIs of this crate.
+#[derive(Debug, Display, Error, From)]
+pub enum MlpError {
+    #[display("invalid topology: {_0}")]
+    #[from]
+    Topology(TopologyError),
+    #[display("expected an input of {expected} rows, found {found}")]
+    InputSize { expected: usize, found: usize },
+    #[display("expected an output of {expected} rows, found {found}")]
+    OutputSize { expected: usize, found: usize },
+    #[display("batch of {n_inputs} input columns does not match {n_outputs} output columns")]
+    BatchSize { n_inputs: usize, n_outputs: usize },
+    #[display("expected samples of {sample_size} floats each, found {len} floats in total")]
+    SampleSize { sample_size: usize, len: usize },
+    #[display("no samples provided")]
+    NoSamples,
+    #[display("expected a value for each of the {expected} samples, found {found}")]
+    SampleCount { expected: usize, found: usize },
+    #[display("sample weights must be non-negative, finite and not all zero")]
+    SampleWeights,
+    #[display("validation interval must not be zero")]
+    ValidationInterval,
+    #[display("input {index} is {value}, which is not a category id of the embedding layer")]
+    CategoryId { index: usize, value: f32 },
+    #[display("layer {index} out of range for {n_layers} layers")]
+    LayerIndex { index: usize, n_layers: usize },
+    #[display("neuron {index} out of range for a layer of {n_neurons} neurons")]
+    NeuronIndex { index: usize, n_neurons: usize },
+    #[display("layer {index} of {found} neurons cannot be widened to {n_neurons}")]
+    Narrowing {
+        index: usize,
+        n_neurons: usize,
+        found: usize,
+    },
+    #[display("neurons of the output layer cannot be removed or added")]
+    OutputLayer,
+    #[display("not supported for networks with skip connections")]
+    Skips,
+    #[display(
+        "not supported for networks with convolution, pooling, embedding or recurrent layers"
+    )]
+    NotDense,
+    #[display("layer {index} is of shape {found:?}, expected {expected:?}")]
+    LayerShape {
+        index: usize,
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    #[display("layer {index} is of a different kind than the layer copied from")]
+    KindMismatch { index: usize },
+    #[display("preprocessor maps onto {found:?} inputs and outputs, expected {expected:?}")]
+    Preprocessor {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    #[display("file is of a different topology")]
+    TopologyMismatch,
+    #[display("{_0}")]
+    #[from]
+    Io(io::Error),
+}
+
+/// Checks that `input` is a column of `n_inputs` rows.
+pub(crate) fn check_input(input: ColRef<f32>, n_inputs: usize) -> Result<(), MlpError> {
+    match input.nrows() == n_inputs {
+        true => Ok(()),
+        false => Err(MlpError::InputSize {
+            expected: n_inputs,
+            found: input.nrows(),
+        }),
+    }
+}
+
+/// Checks that `output` is a column of `n_outputs` rows.
+pub(crate) fn check_output(output: ColRef<f32>, n_outputs: usize) -> Result<(), MlpError> {
+    match output.nrows() == n_outputs {
+        true => Ok(()),
+        false => Err(MlpError::OutputSize {
+            expected: n_outputs,
+            found: output.nrows(),
+        }),
+    }
+}
+
+/// Checks that `inputs` and `outputs` are batches of the same number of columns, each being an
+/// input or output.
+pub(crate) fn check_batch(
+    inputs: MatRef<f32>,
+    outputs: MatRef<f32>,
+    n_inputs: usize,
+    n_outputs: usize,
+) -> Result<(), MlpError> {
+    if inputs.nrows() != n_inputs {
+        return Err(MlpError::InputSize {
+            expected: n_inputs,
+            found: inputs.nrows(),
+        });
+    }
+    if outputs.nrows() != n_outputs {
+        return Err(MlpError::OutputSize {
+            expected: n_outputs,
+            found: outputs.nrows(),
+        });
+    }
+    match inputs.ncols() == outputs.ncols() {
+        true => Ok(()),
+        false => Err(MlpError::BatchSize {
+            n_inputs: inputs.ncols(),
+            n_outputs: outputs.ncols(),
+        }),
+    }
+}
+
+/// Checks that `samples` is a non-empty sequence of `sample_size`-floats samples.
+pub(crate) fn check_samples(samples: &[f32], sample_size: usize) -> Result<(), MlpError> {
+    if samples.is_empty() {
+        return Err(MlpError::NoSamples);
+    }
+    match samples.len().is_multiple_of(sample_size) {
+        true => Ok(()),
+        false => Err(MlpError::SampleSize {
+            sample_size,
+            len: samples.len(),
+        }),
+    }
+}
+
+/// Checks that the category ids of `input` are valid, if the first layer of `topology` is an
+/// embedding layer. The size of `input` must have been checked.
+pub(crate) fn check_ids(input: ColRef<f32>, topology: &Topology) -> Result<(), MlpError> {
+    match topology.embedding() {
+        Some(embedding) => embedding.check_ids(input),
+        None => Ok(()),
+    }
+}
+
+/// `check_ids` for every column of `inputs`.
+pub(crate) fn check_batch_ids(inputs: MatRef<f32>, topology: &Topology) -> Result<(), MlpError> {
+    if topology.embedding().is_none() {
+        return Ok(());
+    }
+    (0..inputs.ncols()).try_for_each(|i| check_ids(inputs.col(i), topology))
+}
+
+/// `check_ids` for the input of every sample. Sample sizes must have been checked.
+pub(crate) fn check_sample_ids(samples: &[f32], topology: &Topology) -> Result<(), MlpError> {
+    if topology.embedding().is_none() {
+        return Ok(());
+    }
+    let n_inputs = topology.n_inputs();
+    samples
+        .chunks(n_inputs + topology.n_outputs())
+        .try_for_each(|sample| check_ids(ColRef::from_slice(&sample[..n_inputs]), topology))
+}
+
+/// `check_ids` for the input of every step of every sequence, laid out according to `config`.
+/// Sample sizes must have been checked.
+pub(crate) fn check_sequence_ids(
+    samples: &[f32],
+    config: &SequenceConfig,
+    topology: &Topology,
+) -> Result<(), MlpError> {
+    if topology.embedding().is_none() {
+        return Ok(());
+    }
+    let (n_inputs, n_outputs) = (topology.n_inputs(), topology.n_outputs());
+    for sequence in samples.chunks(config.sample_size(n_inputs, n_outputs)) {
+        for t in 0..config.len {
+            let (x, _) = config.step(sequence, t, n_inputs, n_outputs);
+            check_ids(x, topology)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,458 @@
+use std::{cmp::Ordering, f64::consts::TAU};
+
+use faer::{Side, prelude::*};
+use rand::{Rng, rngs::ThreadRng};
+use rayon::prelude::*;
+
+use crate::{NeuralNetwork, Topology};
+
+/// Settings of a genetic algorithm, see `Evolution::genetic`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneticConfig {
+    /// Number of candidates per generation.
+    pub population: usize,
+    /// Number of the fittest candidates carried over to the next generation unchanged.
+    pub elites: usize,
+    /// Parents are the fittest of this many candidates picked at random.
+    pub tournament_size: usize,
+    /// Probability of a child mixing two parents rather than copying one.
+    pub crossover_rate: f32,
+    /// Probability of each param of a child being mutated.
+    pub mutation_rate: f32,
+    /// Standard deviation of the Gaussian noise added by a mutation.
+    pub mutation_sigma: f32,
+}
+
+impl Default for GeneticConfig {
+    fn default() -> Self {
+        Self {
+            population: 64,
+            elites: 2,
+            tournament_size: 3,
+            crossover_rate: 0.5,
+            mutation_rate: 0.1,
+            mutation_sigma: 0.1,
+        }
+    }
+}
+
+/// Settings of CMA-ES, see `Evolution::cma_es`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CmaEsConfig {
+    /// Number of candidates per generation, `None` for the usual `4 + 3 * ln(n_params)`.
+    pub population: Option<usize>,
+    /// Initial step size, around a quarter of the range the params are expected to move in.
+    pub sigma: f32,
+}
+
+impl Default for CmaEsConfig {
+    fn default() -> Self {
+        Self {
+            population: None,
+            sigma: 0.1,
+        }
+    }
+}
+
+/// Summary of a generation, see `Evolution::step`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generation {
+    /// Index of the generation.
+    pub i_generation: usize,
+    /// Fitness of the fittest candidate of the generation.
+    pub best_fitness: f32,
+    /// Mean fitness of the candidates, leaving out NaN ones. NaN only if all of them are.
+    pub mean_fitness: f32,
+    /// Fitness of the params in the network, the fittest candidate of all generations so far.
+    pub best_fitness_so_far: f32,
+}
+
+/// Gradient-free training by evolving a population of param vectors, for objectives without a
+/// gradient such as the reward of a controller in a simulation.
+///
+/// The fitness function is called on a copy of the network with the candidate's params, from
+/// several threads at once, and higher is better. A NaN fitness counts as the lowest.
+///
+/// After every generation the network holds the fittest params found so far. Frozen layers and
+/// pruned params are left as they are.
+pub struct Evolution<'a> {
+    nn: &'a mut NeuralNetwork,
+    /// Indices of the evolved params within `params_as_slice`.
+    free: Vec<usize>,
+    strategy: Strategy,
+    best_fitness: Option<f32>,
+    i_generation: usize,
+}
+
+enum Strategy {
+    Genetic {
+        config: GeneticConfig,
+        population: Vec<Vec<f32>>,
+    },
+    CmaEs(Box<CmaEs>),
+}
+
+impl<'a> Evolution<'a> {
+    /// Genetic algorithm with tournament selection, uniform crossover, Gaussian mutation and
+    /// elitism. The first population is mutations of the network's params.
+    ///
+    /// # Panics
+    ///
+    /// - if `config.population` is zero, or smaller than `config.elites`
+    /// - if `config.tournament_size` is zero
+    /// - if `config.crossover_rate` or `config.mutation_rate` is not within `0..=1`
+    pub fn genetic(nn: &'a mut NeuralNetwork, config: GeneticConfig) -> Self {
+        assert!(config.population != 0);
+        assert!(config.elites <= config.population);
+        assert!(config.tournament_size != 0);
+        assert!((0.0..=1.0).contains(&config.crossover_rate));
+        assert!((0.0..=1.0).contains(&config.mutation_rate));
+        let free = nn.params().free_indices();
+        let genome: Vec<f32> = free.iter().map(|&p| nn.params_as_slice()[p]).collect();
+        let mut rng = ThreadRng::default();
+        let population = (0..config.population)
+            .map(|i| {
+                let mut candidate = genome.clone();
+                // Keeps the network's own params among the candidates.
+                if i != 0 {
+                    mutate(&mut rng, &mut candidate, &config);
+                }
+                candidate
+            })
+            .collect();
+        Self {
+            nn,
+            free,
+            strategy: Strategy::Genetic { config, population },
+            best_fitness: None,
+            i_generation: 0,
+        }
+    }
+
+    /// CMA-ES (covariance matrix adaptation evolution strategy), starting from a distribution
+    /// centered on the network's params.
+    ///
+    /// The covariance matrix is `n_params x n_params`, so this is meant for networks of up to a
+    /// few thousand params.
+    ///
+    /// # Panics
+    ///
+    /// - if `config.population` is `Some` of less than `2`
+    pub fn cma_es(nn: &'a mut NeuralNetwork, config: CmaEsConfig) -> Self {
+        let free = nn.params().free_indices();
+        let mean: Col<f64> = Col::from_fn(free.len(), |i| nn.params_as_slice()[free[i]] as f64);
+        Self {
+            nn,
+            free,
+            strategy: Strategy::CmaEs(Box::new(CmaEs::new(mean, &config))),
+            best_fitness: None,
+            i_generation: 0,
+        }
+    }
+
+    /// Fitness of the params in the network, `None` before the first generation.
+    pub fn best_fitness(&self) -> Option<f32> {
+        self.best_fitness
+    }
+
+    /// Evaluates a generation of candidates in parallel and breeds the next one.
+    pub fn step(&mut self, fitness: impl Fn(&mut NeuralNetwork) -> f32 + Sync) -> Generation {
+        let candidates: Vec<Vec<f32>> = match &mut self.strategy {
+            Strategy::Genetic { population, .. } => population.clone(),
+            Strategy::CmaEs(cma_es) => cma_es.sample(),
+        };
+        let fitnesses = self.evaluate(&candidates, &fitness);
+        let mut ranking: Vec<usize> = (0..candidates.len()).collect();
+        ranking.sort_by(|&i, &j| compare_fitness(fitnesses[j], fitnesses[i]));
+        let fittest = ranking[0];
+        if self
+            .best_fitness
+            .is_none_or(|best| compare_fitness(fitnesses[fittest], best) == Ordering::Greater)
+        {
+            self.best_fitness = Some(fitnesses[fittest]);
+            let params = self.nn.params_as_mut_slice();
+            for (&p, &value) in self.free.iter().zip(&candidates[fittest]) {
+                params[p] = value;
+            }
+        }
+        match &mut self.strategy {
+            Strategy::Genetic { config, population } => {
+                *population = breed(&candidates, &fitnesses, &ranking, config);
+            }
+            Strategy::CmaEs(cma_es) => cma_es.update(&candidates, &ranking),
+        }
+        let (sum, n) = fitnesses
+            .iter()
+            .filter(|fitness| !fitness.is_nan())
+            .fold((0.0, 0), |(sum, n), fitness| (sum + fitness, n + 1));
+        let generation = Generation {
+            i_generation: self.i_generation,
+            best_fitness: fitnesses[fittest],
+            mean_fitness: sum / n as f32,
+            best_fitness_so_far: self.best_fitness.unwrap(),
+        };
+        self.i_generation += 1;
+        generation
+    }
+
+    /// Fitness of every candidate, each thread running the candidates on its own copy of the
+    /// network. Every candidate starts from the network's frozen and pruned params and a zero
+    /// recurrent state, whatever the fitness function did with the copy before.
+    fn evaluate(
+        &self,
+        candidates: &[Vec<f32>],
+        fitness: &(impl Fn(&mut NeuralNetwork) -> f32 + Sync),
+    ) -> Vec<f32> {
+        let topology: &Topology = self.nn.topology();
+        let params = self.nn.params_as_slice();
+        candidates
+            .par_iter()
+            .map_init(
+                || {
+                    let mut nn = NeuralNetwork::new(topology.clone());
+                    // So that `forward` and `loss` preprocess as with the original network.
+                    nn.set_preprocessor(self.nn.preprocessor().cloned())
+                        .unwrap();
+                    nn
+                },
+                |nn, candidate| {
+                    nn.reset_state();
+                    let nn_params = nn.params_as_mut_slice();
+                    nn_params.copy_from_slice(params);
+                    for (&p, &value) in self.free.iter().zip(candidate) {
+                        nn_params[p] = value;
+                    }
+                    fitness(nn)
+                },
+            )
+            .collect()
+    }
+}
+
+/// Orders NaN below everything else.
+fn compare_fitness(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.total_cmp(&b),
+    }
+}
+
+/// Next population of the genetic algorithm, `ranking` being the candidates from the fittest.
+fn breed(
+    candidates: &[Vec<f32>],
+    fitnesses: &[f32],
+    ranking: &[usize],
+    config: &GeneticConfig,
+) -> Vec<Vec<f32>> {
+    let mut rng = ThreadRng::default();
+    let tournament = |rng: &mut ThreadRng| {
+        (0..config.tournament_size)
+            .map(|_| rng.random_range(0..candidates.len()))
+            .max_by(|&i, &j| compare_fitness(fitnesses[i], fitnesses[j]))
+            .unwrap()
+    };
+    let mut population: Vec<Vec<f32>> = ranking[..config.elites]
+        .iter()
+        .map(|&i| candidates[i].clone())
+        .collect();
+    while population.len() < config.population {
+        let mut child = candidates[tournament(&mut rng)].clone();
+        if rng.random_bool(config.crossover_rate as f64) {
+            let other = &candidates[tournament(&mut rng)];
+            for (gene, &other_gene) in child.iter_mut().zip(other) {
+                if rng.random_bool(0.5) {
+                    *gene = other_gene;
+                }
+            }
+        }
+        mutate(&mut rng, &mut child, config);
+        population.push(child);
+    }
+    population
+}
+
+fn mutate(rng: &mut ThreadRng, genome: &mut [f32], config: &GeneticConfig) {
+    for gene in genome {
+        if rng.random_bool(config.mutation_rate as f64) {
+            *gene += config.mutation_sigma * gaussian(rng) as f32;
+        }
+    }
+}
+
+/// Standard normal sample, by the Box–Muller transform.
+fn gaussian(rng: &mut ThreadRng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+/// State of CMA-ES, following Hansen's "The CMA Evolution Strategy: A Tutorial".
+struct CmaEs {
+    n: usize,
+    lambda: usize,
+    /// Recombination weights of the fittest `weights.len()` candidates.
+    weights: Vec<f64>,
+    mu_eff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    /// Expected norm of a standard normal vector of `n` dimensions.
+    chi_n: f64,
+    mean: Col<f64>,
+    sigma: f64,
+    /// Covariance matrix.
+    c: Mat<f64>,
+    /// Evolution path of `c`.
+    pc: Col<f64>,
+    /// Evolution path of `sigma`.
+    ps: Col<f64>,
+    /// `c = b * d^2 * b^T`.
+    b: Mat<f64>,
+    d: Col<f64>,
+    i_generation: usize,
+    /// Generation `b` and `d` were last computed at.
+    i_eigen: usize,
+}
+
+impl CmaEs {
+    fn new(mean: Col<f64>, config: &CmaEsConfig) -> Self {
+        let n = mean.nrows();
+        let nf = n.max(1) as f64;
+        let lambda = config
+            .population
+            .unwrap_or(4 + (3.0 * nf.ln()).floor() as usize);
+        assert!(lambda >= 2);
+        let mu = lambda / 2;
+        let mut weights: Vec<f64> = (0..mu)
+            .map(|i| (mu as f64 + 0.5).ln() - (i as f64 + 1.0).ln())
+            .collect();
+        let sum: f64 = weights.iter().sum();
+        for w in &mut weights {
+            *w /= sum;
+        }
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+        let cc = (4.0 + mu_eff / nf) / (nf + 4.0 + 2.0 * mu_eff / nf);
+        let cs = (mu_eff + 2.0) / (nf + mu_eff + 5.0);
+        let c1 = 2.0 / ((nf + 1.3).powi(2) + mu_eff);
+        let cmu =
+            (1.0 - c1).min(2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((nf + 2.0).powi(2) + mu_eff));
+        let damps = 1.0 + 2.0 * (((mu_eff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+        Self {
+            n,
+            lambda,
+            weights,
+            mu_eff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n,
+            mean,
+            sigma: config.sigma as f64,
+            c: Mat::identity(n, n),
+            pc: Col::zeros(n),
+            ps: Col::zeros(n),
+            b: Mat::identity(n, n),
+            d: Col::ones(n),
+            i_generation: 0,
+            i_eigen: 0,
+        }
+    }
+
+    /// `mean + sigma * b * (d .* z)` for `lambda` standard normal `z`.
+    fn sample(&self) -> Vec<Vec<f32>> {
+        let mut rng = ThreadRng::default();
+        (0..self.lambda)
+            .map(|_| {
+                let dz = Col::<f64>::from_fn(self.n, |i| self.d[i] * gaussian(&mut rng));
+                let y = &self.b * &dz;
+                (0..self.n)
+                    .map(|i| (self.mean[i] + self.sigma * y[i]) as f32)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Moves the distribution towards the fittest candidates, `ranking` being the candidates from
+    /// the fittest.
+    fn update(&mut self, candidates: &[Vec<f32>], ranking: &[usize]) {
+        let n = self.n;
+        let mu = self.weights.len();
+        // Steps of the fittest candidates from the old mean, in units of `sigma`.
+        let steps = Mat::<f64>::from_fn(n, mu, |i, k| {
+            (candidates[ranking[k]][i] as f64 - self.mean[i]) / self.sigma
+        });
+        let y_w = Col::<f64>::from_fn(n, |i| {
+            (0..mu).map(|k| self.weights[k] * steps[(i, k)]).sum()
+        });
+        for i in 0..n {
+            self.mean[i] += self.sigma * y_w[i];
+        }
+        // c^(-1/2) * y_w = b * d^-1 * b^T * y_w
+        let mut whitened = self.b.transpose() * &y_w;
+        for i in 0..n {
+            whitened[i] /= self.d[i];
+        }
+        let whitened = &self.b * &whitened;
+        let cs_scale = (self.cs * (2.0 - self.cs) * self.mu_eff).sqrt();
+        for i in 0..n {
+            self.ps[i] = (1.0 - self.cs) * self.ps[i] + cs_scale * whitened[i];
+        }
+        self.i_generation += 1;
+        let ps_norm = self.ps.norm_l2();
+        let ps_decay = 1.0 - (1.0 - self.cs).powi(2 * self.i_generation as i32);
+        let is_stalled = ps_norm / ps_decay.sqrt() / self.chi_n < 1.4 + 2.0 / (n as f64 + 1.0);
+        let h_sigma = match is_stalled {
+            true => 1.0,
+            false => 0.0,
+        };
+        let cc_scale = (self.cc * (2.0 - self.cc) * self.mu_eff).sqrt();
+        for i in 0..n {
+            self.pc[i] = (1.0 - self.cc) * self.pc[i] + h_sigma * cc_scale * y_w[i];
+        }
+        let weighted_steps =
+            Mat::<f64>::from_fn(n, mu, |i, k| self.weights[k].sqrt() * steps[(i, k)]);
+        let rank_mu = &weighted_steps * weighted_steps.transpose();
+        let old_scale =
+            1.0 - self.c1 - self.cmu + (1.0 - h_sigma) * self.c1 * self.cc * (2.0 - self.cc);
+        for j in 0..n {
+            for i in 0..n {
+                self.c[(i, j)] = old_scale * self.c[(i, j)]
+                    + self.c1 * self.pc[i] * self.pc[j]
+                    + self.cmu * rank_mu[(i, j)];
+            }
+        }
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).exp();
+        // The decomposition is O(n^3), so it is only refreshed once `c` has moved enough.
+        let eigen_interval = self.lambda as f64 / (self.c1 + self.cmu) / n as f64 / 10.0;
+        if (self.i_generation - self.i_eigen) as f64 >= eigen_interval {
+            self.decompose();
+        }
+    }
+
+    /// Recomputes `b` and `d` from `c`.
+    fn decompose(&mut self) {
+        self.i_eigen = self.i_generation;
+        // Keeps `c` symmetric against rounding.
+        let n = self.n;
+        for j in 0..n {
+            for i in j + 1..n {
+                self.c[(j, i)] = self.c[(i, j)];
+            }
+        }
+        let Ok(eigen) = self.c.self_adjoint_eigen(Side::Lower) else {
+            return;
+        };
+        self.b.copy_from(eigen.U());
+        let s = eigen.S().column_vector();
+        for i in 0..n {
+            self.d[i] = s[i].max(0.0).sqrt();
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use faer::prelude::*;
+
+use crate::{
+    MlpError, NeuralNetwork,
+    core::{
+        back_propagate_input_unchecked, back_propagate_params_unchecked, forward_unchecked,
+        reset_state,
+    },
+    error::{check_ids, check_input, check_output},
+};
+
+impl NeuralNetwork {
+    /// Derivative of the squared error of sample `(x, y)` (see `loss`) by the input `x`, e.g. for
+    /// saliency maps or adversarial examples.
+    ///
+    /// Leaves the network's params untouched. Recurrent layers start from a zero state, and ids of
+    /// an embedding layer get a zero derivative.
+    pub fn input_gradient(&mut self, x: ColRef<f32>, y: ColRef<f32>) -> Result<Col<f32>, MlpError> {
+        check_input(x, self.n_inputs())?;
+        check_ids(x, self.topology())?;
+        check_output(y, self.n_outputs())?;
+        // Safety: sizes are checked above.
+        Ok(unsafe {
+            self.input_gradient_by(x, |output| {
+                // d/da of sum((a - y)^2), leaving out missing targets.
+                Col::from_fn(output.nrows(), |k| match y[k].is_nan() {
+                    true => 0.0,
+                    false => 2.0 * (output[k] - y[k]),
+                })
+            })
+        })
+    }
+
+    /// Derivative of output `j` by the input `x`, e.g. for finding an input that maximizes it.
+    ///
+    /// Leaves the network's params untouched, see `input_gradient`.
+    pub fn output_input_gradient(
+        &mut self,
+        x: ColRef<f32>,
+        j: usize,
+    ) -> Result<Col<f32>, MlpError> {
+        check_input(x, self.n_inputs())?;
+        check_ids(x, self.topology())?;
+        let n_outputs = self.n_outputs();
+        if j >= n_outputs {
+            return Err(MlpError::NeuronIndex {
+                index: j,
+                n_neurons: n_outputs,
+            });
+        }
+        // Safety: sizes are checked above.
+        Ok(unsafe {
+            self.input_gradient_by(x, |_| {
+                let mut da_output = Col::zeros(n_outputs);
+                da_output[j] = 1.0;
+                da_output
+            })
+        })
+    }
+
+    /// Derivatives of the outputs by the input at `x`, as a `n_outputs x n_inputs` matrix.
+    ///
+    /// Takes a forward pass and a backward pass per output. Leaves the network's params
+    /// untouched, see `input_gradient`.
+    pub fn jacobian_input(&mut self, x: ColRef<f32>) -> Result<Mat<f32>, MlpError> {
+        check_input(x, self.n_inputs())?;
+        check_ids(x, self.topology())?;
+        let n_outputs = self.n_outputs();
+        let mut jacobian = Mat::zeros(n_outputs, x.nrows());
+        let mut dx = Col::zeros(x.nrows());
+        let mut da_output = Col::zeros(n_outputs);
+        let (params, results, derivs) = self.buffers_mut();
+        reset_state(params, results);
+        // Safety: buffers are of the network's topology, input size is checked above.
+        unsafe { forward_unchecked(x, params, results) };
+        for j in 0..n_outputs {
+            da_output[j] = 1.0;
+            // Safety: same as above.
+            unsafe {
+                back_propagate_input_unchecked(
+                    params,
+                    results,
+                    derivs,
+                    x,
+                    da_output.as_ref(),
+                    dx.as_mut(),
+                )
+            };
+            da_output[j] = 0.0;
+            jacobian.row_mut(j).copy_from(dx.transpose());
+        }
+        Ok(jacobian)
+    }
+
+    /// Derivatives of the outputs by every param (frozen or not) at `x`, as a
+    /// `n_outputs x n_params` matrix. Columns are in the layout of `params_as_slice`.
+    ///
+    /// Takes a forward pass and a backward pass per output. Recurrent layers start from a zero
+    /// state.
+    pub fn jacobian_params(&mut self, x: ColRef<f32>) -> Result<Mat<f32>, MlpError> {
+        check_input(x, self.n_inputs())?;
+        check_ids(x, self.topology())?;
+        let n_outputs = self.n_outputs();
+        let n_params = self.params_as_slice().len();
+        let mut jacobian = Mat::zeros(n_outputs, n_params);
+        let mut da_output = Col::zeros(n_outputs);
+        let (params, results, derivs) = self.buffers_mut();
+        reset_state(params, results);
+        // Safety: buffers are of the network's topology, input size is checked above.
+        unsafe { forward_unchecked(x, params, results) };
+        for j in 0..n_outputs {
+            da_output[j] = 1.0;
+            // Safety: same as above.
+            unsafe {
+                back_propagate_params_unchecked(params, results, derivs, x, da_output.as_ref())
+            };
+            da_output[j] = 0.0;
+            jacobian
+                .row_mut(j)
+                .copy_from(ColRef::from_slice(derivs.params()).transpose());
+        }
+        Ok(jacobian)
+    }
+
+    /// Runs `x` from a zero state, and back propagates the derivative by the output that
+    /// `da_output` makes of the output, onto the input.
+    ///
+    /// # Safety
+    ///
+    /// - `x` must have `n_inputs` rows, `da_output` must return `n_outputs` rows
+    unsafe fn input_gradient_by(
+        &mut self,
+        x: ColRef<f32>,
+        da_output: impl FnOnce(ColRef<f32>) -> Col<f32>,
+    ) -> Col<f32> {
+        let mut dx = Col::zeros(x.nrows());
+        let (params, results, derivs) = self.buffers_mut();
+        reset_state(params, results);
+        // Safety: buffers are of the network's topology, sizes are guaranteed by the function's
+        // safety contract.
+        unsafe {
+            forward_unchecked(x, params, results);
+            let da_output = da_output(results.layer(results.n_layers() - 1).unwrap().a);
+            back_propagate_input_unchecked(
+                params,
+                results,
+                derivs,
+                x,
+                da_output.as_ref(),
+                dx.as_mut(),
+            );
+        }
+        dx
+    }
+}
@@ -1,22 +1,85 @@
-use std::{marker::PhantomData, ptr::NonNull, sync::mpsc};
+use std::{borrow::Cow, iter, marker::PhantomData, ptr::NonNull, sync::mpsc, time::Instant};
 
-use faer::ColRef;
+use faer::{Col, ColRef, MatRef};
 
 use crate::{
-    NeuralNetwork, Topology,
+    Callback, CheckpointConfig, Control, Hyperparameters, MlpError, NeuralNetwork, Preprocessor,
+    Progress, SequenceConfig, Topology,
     core::{
-        DerivBuffer, ParamBuffer, ResultBuffer, apply_derivs, calculate_derivs, forward_unchecked,
+        DerivBuffer, ParamBuffer, ResultBuffer, apply_derivs, calculate_derivs,
+        calculate_output_derivs, calculate_sequence_derivs, calculate_weighted_derivs,
+        forward_unchecked, loss_unchecked,
     },
+    error::{
+        check_batch, check_batch_ids, check_ids, check_input, check_sample_ids, check_samples,
+        check_sequence_ids,
+    },
+    preprocess::{preprocess_samples, preprocess_sequences},
 };
 
 pub struct Gym<'a> {
-    topology: Topology,
+    pub(crate) topology: Topology,
     params: NonNull<ParamBuffer>,
     results: Option<ResultBuffer>,
     derivs: Option<DerivBuffer>,
+    /// The network's, see `NeuralNetwork::set_preprocessor`.
+    preprocessor: Option<Preprocessor>,
+    /// Output of `forward` scaled back by the preprocessor.
+    raw_output: Col<f32>,
+    /// Results of each step of a truncation window, created by `train_sequences` as needed.
+    sequence_results: Vec<ResultBuffer>,
+    pub(crate) validation: Option<Validation<'a>>,
+    pub(crate) checkpointing: Option<CheckpointConfig>,
+    pub(crate) i_step: usize,
+    pub(crate) i_epoch: usize,
+    /// Hyperparameters most recently used by `fit`.
+    pub(crate) hyperparameters: Option<Hyperparameters>,
+    pub(crate) gradient_norm: f32,
     _marker: PhantomData<&'a mut ParamBuffer>,
 }
 
+/// A held-out sample set that is periodically evaluated during training.
+///
+/// Evaluation uses its own result buffer, so the training buffers are never touched.
+pub(crate) struct Validation<'a> {
+    /// Preprocessed.
+    samples: Cow<'a, [f32]>,
+    /// Evaluate once every `interval` steps.
+    interval: usize,
+    results: ResultBuffer,
+    /// Number of evaluations without improvement after which training should stop.
+    patience: Option<usize>,
+    /// Minimum decrease in loss that counts as an improvement.
+    min_delta: f32,
+    pub(crate) last_loss: Option<f32>,
+    pub(crate) best: Option<BestParams>,
+    pub(crate) n_evaluations_since_best: usize,
+}
+
+pub(crate) struct BestParams {
+    pub(crate) loss: f32,
+    pub(crate) i_step: usize,
+    pub(crate) params: Box<[f32]>,
+}
+
+/// Summary of a `Gym::fit` run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FitReport {
+    /// Number of training steps performed.
+    pub n_steps: usize,
+    /// Number of epochs started.
+    pub n_epochs: usize,
+    /// Training loss of the last step.
+    pub loss: f32,
+    /// Best validation loss seen, if a validation set was provided.
+    pub best_validation_loss: Option<f32>,
+    /// Step at which `best_validation_loss` was reached, the restored parameters are from then.
+    pub best_step: Option<usize>,
+    /// Whether training was stopped by early stopping or by a callback before reaching
+    /// `max_epochs`.
+    pub stopped_early: bool,
+}
+
 struct WorkerResult {
     loss: f32,
     derivs: DerivBuffer,
@@ -29,23 +92,336 @@ impl<'a> Gym<'a> {
             params: unsafe { NonNull::from_mut(nn.params_unchecked_mut()) },
             results: None,
             derivs: None,
+            preprocessor: nn.preprocessor().cloned(),
+            raw_output: Col::zeros(nn.n_outputs()),
+            sequence_results: Vec::new(),
+            validation: None,
+            checkpointing: None,
+            i_step: 0,
+            i_epoch: 0,
+            hyperparameters: None,
+            gradient_norm: f32::NAN,
             _marker: PhantomData,
         }
     }
 
-    pub fn forward(&mut self, input: ColRef<f32>) -> ColRef<'_, f32> {
+    /// Evaluate `samples` as a validation set once every `interval` training steps.
+    ///
+    /// Parameters with the best validation loss are remembered, see `restore_best_params`.
+    pub fn set_validation(&mut self, samples: &'a [f32], interval: usize) -> Result<(), MlpError> {
+        let samples = self.preprocess(samples)?;
+        if interval == 0 {
+            return Err(MlpError::ValidationInterval);
+        }
+        self.validation = Some(Validation {
+            samples,
+            interval,
+            results: ResultBuffer::create(&self.topology),
+            patience: None,
+            min_delta: 0.0,
+            last_loss: None,
+            best: None,
+            n_evaluations_since_best: 0,
+        });
+        Ok(())
+    }
+
+    /// Request stopping once `patience` validation evaluations in a row did not improve the best
+    /// validation loss by more than `min_delta`.
+    ///
+    /// Has no effect without a validation set, see `set_validation`, which must be called first.
+    pub fn set_early_stopping(&mut self, patience: usize, min_delta: f32) {
+        if let Some(validation) = self.validation.as_mut() {
+            validation.patience = Some(patience);
+            validation.min_delta = min_delta;
+        }
+    }
+
+    /// Number of training steps performed so far.
+    pub fn i_step(&self) -> usize {
+        self.i_step
+    }
+
+    /// Number of epochs completed by `fit` so far.
+    pub fn i_epoch(&self) -> usize {
+        self.i_epoch
+    }
+
+    fn sample_size(&self) -> usize {
+        self.topology.n_inputs() + self.topology.n_outputs()
+    }
+
+    /// `samples` as the network is trained on, see `NeuralNetwork::set_preprocessor`, checked to
+    /// be whole samples of valid ids.
+    pub(crate) fn preprocess<'s>(&self, samples: &'s [f32]) -> Result<Cow<'s, [f32]>, MlpError> {
+        let samples = preprocess_samples(self.preprocessor.as_ref(), samples)?;
+        check_samples(&samples, self.sample_size())?;
+        check_sample_ids(&samples, &self.topology)?;
+        Ok(samples)
+    }
+
+    pub(crate) fn params(&self) -> &ParamBuffer {
+        unsafe { self.params.as_ref() }
+    }
+
+    pub(crate) fn params_mut(&mut self) -> &mut ParamBuffer {
+        unsafe { self.params.as_mut() }
+    }
+
+    /// Params, results and a deriv buffer of the trained network's topology, all at once.
+    pub(crate) fn buffers_mut(&mut self) -> (&ParamBuffer, &mut ResultBuffer, &mut DerivBuffer) {
+        let params = unsafe { self.params.as_ref() };
+        let results = self
+            .results
+            .get_or_insert_with(|| ResultBuffer::create(&self.topology));
+        let derivs = self
+            .derivs
+            .get_or_insert_with(|| DerivBuffer::create(&self.topology));
+        (params, results, derivs)
+    }
+
+    /// Validation loss from the most recent evaluation.
+    pub fn validation_loss(&self) -> Option<f32> {
+        self.validation.as_ref()?.last_loss
+    }
+
+    /// Best validation loss so far.
+    pub fn best_validation_loss(&self) -> Option<f32> {
+        Some(self.validation.as_ref()?.best.as_ref()?.loss)
+    }
+
+    /// Whether early stopping has been triggered.
+    pub fn should_stop(&self) -> bool {
+        let Some(validation) = self.validation.as_ref() else {
+            return false;
+        };
+        match validation.patience {
+            Some(patience) => validation.n_evaluations_since_best >= patience,
+            None => false,
+        }
+    }
+
+    /// Copy the parameters with the best validation loss back into the network.
+    ///
+    /// Returns `false` if no validation evaluation has happened yet.
+    pub fn restore_best_params(&mut self) -> bool {
+        let Some(best) = self.validation.as_ref().and_then(|v| v.best.as_ref()) else {
+            return false;
+        };
+        let params = unsafe { &mut *self.params.as_ptr() };
+        params.as_mut_slice().copy_from_slice(&best.params);
+        true
+    }
+
+    /// Evaluates the validation set right away, regardless of the interval.
+    ///
+    /// Returns the mean loss per sample, or `None` if there is no validation set.
+    pub fn validate(&mut self) -> Option<f32> {
+        let validation = self.validation.as_mut()?;
+        let params = unsafe { &*self.params.as_ptr() };
+        let sample_size = self.topology.n_inputs() + self.topology.n_outputs();
+        let n_samples = validation.samples.len() / sample_size;
+        // Safety: `validation.results` is created from the same topology, sample sizes are checked
+        // in `set_validation`.
+        let loss = unsafe { loss_unchecked(params, &mut validation.results, &validation.samples) }
+            / (n_samples as f32);
+        validation.last_loss = Some(loss);
+        let is_improvement = match &validation.best {
+            None => true,
+            Some(best) => loss < best.loss - validation.min_delta,
+        };
+        if is_improvement {
+            match &mut validation.best {
+                Some(best) => {
+                    best.loss = loss;
+                    best.i_step = self.i_step;
+                    best.params.copy_from_slice(params.as_slice());
+                }
+                None => {
+                    validation.best = Some(BestParams {
+                        loss,
+                        i_step: self.i_step,
+                        params: params.as_slice().into(),
+                    })
+                }
+            }
+            validation.n_evaluations_since_best = 0;
+        } else {
+            validation.n_evaluations_since_best += 1;
+        }
+        Some(loss)
+    }
+
+    pub(crate) fn finish_step(&mut self) {
+        self.i_step += 1;
+        let is_validation_due = self
+            .validation
+            .as_ref()
+            .is_some_and(|validation| self.i_step.is_multiple_of(validation.interval));
+        if is_validation_due {
+            self.validate();
+        }
+    }
+
+    /// Trains for up to `max_epochs` more passes over `samples`, stopping early if requested by
+    /// `set_early_stopping` or by any of the `callbacks`.
+    ///
+    /// If a validation set is provided, the parameters with the best validation loss are restored
+    /// at the end.
+    ///
+    /// Step and epoch counters carry on from previous calls and from `load_checkpoint`.
+    pub fn fit(
+        &mut self,
+        mut hyperparameters: Hyperparameters,
+        samples: &[f32],
+        max_epochs: usize,
+        callbacks: &mut [&mut dyn Callback],
+    ) -> Result<FitReport, MlpError> {
+        let sample_size = self.sample_size();
+        let samples = &*self.preprocess(samples)?;
+        let start_time = Instant::now();
+        let mut loss = f32::NAN;
+        let mut n_steps = 0usize;
+        let mut n_epochs = 0usize;
+        let mut stopped_early = false;
+        'epochs: for i_run_epoch in 0..max_epochs {
+            let i_epoch = self.i_epoch;
+            let batch_size = match hyperparameters.batch_size {
+                Some(batch_size) => batch_size.max(1) * sample_size,
+                None => samples.len(),
+            };
+            let mut epoch_loss = 0.0f32;
+            let mut n_epoch_steps = 0usize;
+            let mut progress = None;
+            for batch in samples.chunks(batch_size) {
+                let eta = hyperparameters.eta;
+                self.hyperparameters = Some(hyperparameters);
+                // Safety: `samples` is checked above, and batches are whole samples.
+                loss = unsafe { self.train_unchecked(hyperparameters.n_threads, eta, batch) };
+                n_steps += 1;
+                epoch_loss += loss;
+                n_epoch_steps += 1;
+                let is_validation_step = self
+                    .validation
+                    .as_ref()
+                    .is_some_and(|validation| self.i_step.is_multiple_of(validation.interval));
+                let progress = progress.insert(Progress {
+                    i_step: self.i_step,
+                    i_epoch,
+                    loss,
+                    gradient_norm: self.gradient_norm,
+                    validation_loss: self.validation_loss(),
+                    eta,
+                    elapsed: start_time.elapsed(),
+                });
+                let mut control = Control::Continue;
+                for callback in callbacks.iter_mut() {
+                    if callback.on_step(progress, &mut hyperparameters) == Control::Stop {
+                        control = Control::Stop;
+                    }
+                }
+                if is_validation_step {
+                    for callback in callbacks.iter_mut() {
+                        if callback.on_validation(progress, &mut hyperparameters) == Control::Stop {
+                            control = Control::Stop;
+                        }
+                    }
+                }
+                if control == Control::Stop || self.should_stop() {
+                    stopped_early = true;
+                    n_epochs = i_run_epoch + 1;
+                    break 'epochs;
+                }
+            }
+            n_epochs = i_run_epoch + 1;
+            let Some(mut progress) = progress else {
+                break;
+            };
+            self.i_epoch += 1;
+            progress.loss = epoch_loss / (n_epoch_steps as f32);
+            progress.elapsed = start_time.elapsed();
+            let mut control = Control::Continue;
+            for callback in callbacks.iter_mut() {
+                if callback.on_epoch_end(&progress, &mut hyperparameters) == Control::Stop {
+                    control = Control::Stop;
+                }
+            }
+            self.hyperparameters = Some(hyperparameters);
+            if self
+                .checkpointing
+                .as_ref()
+                .is_some_and(|config| self.i_epoch.is_multiple_of(config.interval))
+            {
+                self.write_checkpoint()?;
+            }
+            if control == Control::Stop {
+                stopped_early = i_run_epoch + 1 != max_epochs;
+                break;
+            }
+        }
+        if let Some(interval) = self.validation.as_ref().map(|v| v.interval) {
+            // Give the final parameters a chance to be the best ones as well.
+            if !self.i_step.is_multiple_of(interval) {
+                self.validate();
+            }
+            self.restore_best_params();
+        }
+        let best = self.validation.as_ref().and_then(|v| v.best.as_ref());
+        Ok(FitReport {
+            n_steps,
+            n_epochs,
+            loss,
+            best_validation_loss: best.map(|best| best.loss),
+            best_step: best.map(|best| best.i_step),
+            stopped_early,
+        })
+    }
+
+    /// L2 norm of the gradient applied in the most recent training step.
+    pub fn gradient_norm(&self) -> f32 {
+        self.gradient_norm
+    }
+
+    /// Same as `NeuralNetwork::forward`, applies the preprocessor.
+    pub fn forward(&mut self, input: ColRef<f32>) -> Result<ColRef<'_, f32>, MlpError> {
+        let transformed;
+        let input = match &self.preprocessor {
+            Some(preprocessor) => {
+                transformed = preprocessor.transform_input(input)?;
+                transformed.as_ref()
+            }
+            None => input,
+        };
+        check_input(input, self.topology.n_inputs())?;
+        check_ids(input, &self.topology)?;
         let results = self
             .results
             .get_or_insert_with(|| ResultBuffer::create(&self.topology));
         let params: &'a mut ParamBuffer = unsafe { &mut *self.params.as_ptr() };
-        assert!(input.nrows() == self.topology.n_inputs());
+        // Safety: input size is checked above.
         unsafe { forward_unchecked(input, params, results) };
-        results.layer(results.n_layers() - 1).unwrap().a
+        let a = results.layer(results.n_layers() - 1).unwrap().a;
+        let Some(preprocessor) = &self.preprocessor else {
+            return Ok(a);
+        };
+        self.raw_output.copy_from(a);
+        preprocessor.invert_in_place(self.raw_output.as_mut());
+        Ok(self.raw_output.as_ref())
     }
 
     /// Returns the loss.
-    pub fn train_single_threaded(&mut self, eta: f32, samples: &[f32]) -> f32 {
-        assert!(!samples.is_empty());
+    pub fn train_single_threaded(&mut self, eta: f32, samples: &[f32]) -> Result<f32, MlpError> {
+        let samples = self.preprocess(samples)?;
+        // Safety: sample sizes are checked above.
+        Ok(unsafe { self.train_single_threaded_unchecked(eta, &samples) })
+    }
+
+    /// Does not apply the preprocessor, `samples` must be preprocessed already.
+    ///
+    /// # Safety
+    ///
+    /// - `samples` must be non-empty, and its length a multiple of `n_inputs + n_outputs`
+    pub unsafe fn train_single_threaded_unchecked(&mut self, eta: f32, samples: &[f32]) -> f32 {
         let params = unsafe { &mut *self.params.as_ptr() };
         self.results
             .get_or_insert_with(|| ResultBuffer::create(&self.topology));
@@ -55,24 +431,167 @@ impl<'a> Gym<'a> {
         let derivs = self.derivs.as_mut().unwrap();
         let loss = unsafe { calculate_derivs(params, results, derivs, samples) };
         unsafe { apply_derivs(params, derivs, eta) };
+        self.gradient_norm = l2_norm(derivs.params());
+        self.finish_step();
         loss
     }
 
+    /// Trains from a caller supplied derivative of the loss by the output of each sample, rather
+    /// than from targets, e.g. for policy gradient methods. Column `i` of `da_outputs` belongs to
+    /// column `i` of `inputs`, and is scaled by `scales[i]` if provided. Single threaded.
+    ///
+    /// With a preprocessor, `inputs` are raw ones and `da_outputs` are by the outputs of `forward`,
+    /// i.e. in the units of the raw targets.
+    pub fn train_with_output_derivs(
+        &mut self,
+        eta: f32,
+        inputs: MatRef<f32>,
+        da_outputs: MatRef<f32>,
+        scales: Option<ColRef<f32>>,
+    ) -> Result<(), MlpError> {
+        let transformed;
+        let (inputs, da_outputs) = match &self.preprocessor {
+            Some(preprocessor) => {
+                check_batch(
+                    inputs,
+                    da_outputs,
+                    preprocessor.n_raw_inputs(),
+                    self.topology.n_outputs(),
+                )?;
+                transformed = (
+                    preprocessor.transform_batch(inputs),
+                    preprocessor.scale_output_derivs(da_outputs),
+                );
+                (transformed.0.as_ref(), transformed.1.as_ref())
+            }
+            None => (inputs, da_outputs),
+        };
+        check_batch(
+            inputs,
+            da_outputs,
+            self.topology.n_inputs(),
+            self.topology.n_outputs(),
+        )?;
+        check_batch_ids(inputs, &self.topology)?;
+        if inputs.ncols() == 0 {
+            return Err(MlpError::NoSamples);
+        }
+        if let Some(scales) = scales
+            && scales.nrows() != inputs.ncols()
+        {
+            return Err(MlpError::SampleCount {
+                expected: inputs.ncols(),
+                found: scales.nrows(),
+            });
+        }
+        let params = unsafe { &mut *self.params.as_ptr() };
+        self.results
+            .get_or_insert_with(|| ResultBuffer::create(&self.topology));
+        self.derivs
+            .get_or_insert_with(|| DerivBuffer::create(&self.topology));
+        let results = self.results.as_mut().unwrap();
+        let derivs = self.derivs.as_mut().unwrap();
+        // Safety: buffers are created from the same topology, sizes are checked above.
+        unsafe { calculate_output_derivs(params, results, derivs, inputs, da_outputs, scales) };
+        unsafe { apply_derivs(params, derivs, eta) };
+        self.gradient_norm = l2_norm(derivs.params());
+        self.finish_step();
+        Ok(())
+    }
+
+    /// Trains on `samples` with sample `i` weighted by `weights[i]`, e.g. for class imbalance or
+    /// importance sampling. Derivatives are averaged by the sum of the weights. Single threaded.
+    ///
+    /// Returns the weighted mean loss. Targets that are NaN are missing, see `mask_targets`.
+    pub fn train_weighted(
+        &mut self,
+        eta: f32,
+        samples: &[f32],
+        weights: &[f32],
+    ) -> Result<f32, MlpError> {
+        let samples = &*self.preprocess(samples)?;
+        let n_samples = samples.len() / self.sample_size();
+        if weights.len() != n_samples {
+            return Err(MlpError::SampleCount {
+                expected: n_samples,
+                found: weights.len(),
+            });
+        }
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().sum::<f32>() <= 0.0
+        {
+            return Err(MlpError::SampleWeights);
+        }
+        let params = unsafe { &mut *self.params.as_ptr() };
+        self.results
+            .get_or_insert_with(|| ResultBuffer::create(&self.topology));
+        self.derivs
+            .get_or_insert_with(|| DerivBuffer::create(&self.topology));
+        let results = self.results.as_mut().unwrap();
+        let derivs = self.derivs.as_mut().unwrap();
+        // Safety: buffers are created from the same topology, sizes are checked above.
+        let loss = unsafe { calculate_weighted_derivs(params, results, derivs, samples, weights) };
+        unsafe { apply_derivs(params, derivs, eta) };
+        self.gradient_norm = l2_norm(derivs.params());
+        self.finish_step();
+        Ok(loss)
+    }
+
+    /// Trains on `samples` grouped into sequences according to `config`, back propagating through
+    /// time. Single threaded.
+    ///
+    /// Returns the loss over the targets of the sequences.
+    pub fn train_sequences(
+        &mut self,
+        eta: f32,
+        samples: &[f32],
+        config: &SequenceConfig,
+    ) -> Result<f32, MlpError> {
+        let samples = &*preprocess_sequences(self.preprocessor.as_ref(), samples, config)?;
+        check_samples(
+            samples,
+            config.sample_size(self.topology.n_inputs(), self.topology.n_outputs()),
+        )?;
+        check_sequence_ids(samples, config, &self.topology)?;
+        let params = unsafe { &mut *self.params.as_ptr() };
+        let n_buffers = config.chunk_len() + 1;
+        while self.sequence_results.len() < n_buffers {
+            self.sequence_results
+                .push(ResultBuffer::create(&self.topology));
+        }
+        let derivs = self
+            .derivs
+            .get_or_insert_with(|| DerivBuffer::create(&self.topology));
+        // Safety: buffers are created from the same topology, there are enough result buffers for
+        // a truncation window, sample sizes are checked above.
+        let loss = unsafe {
+            calculate_sequence_derivs(params, &mut self.sequence_results, derivs, samples, config)
+        };
+        unsafe { apply_derivs(params, derivs, eta) };
+        self.gradient_norm = l2_norm(derivs.params());
+        self.finish_step();
+        Ok(loss)
+    }
+
     /// Returns the loss.
     ///
     /// Calls `train_single_threaded` if `n_threads == 0`.
-    pub fn train(&mut self, n_threads: usize, eta: f32, samples: &[f32]) -> f32 {
+    pub fn train(&mut self, n_threads: usize, eta: f32, samples: &[f32]) -> Result<f32, MlpError> {
+        let samples = self.preprocess(samples)?;
+        // Safety: sample sizes are checked above.
+        Ok(unsafe { self.train_unchecked(n_threads, eta, &samples) })
+    }
+
+    /// Does not apply the preprocessor, `samples` must be preprocessed already.
+    ///
+    /// # Safety
+    ///
+    /// - `samples` must be non-empty, and its length a multiple of `n_inputs + n_outputs`
+    pub unsafe fn train_unchecked(&mut self, n_threads: usize, eta: f32, samples: &[f32]) -> f32 {
         if n_threads == 0 {
-            return self.train_single_threaded(eta, samples);
+            return unsafe { self.train_single_threaded_unchecked(eta, samples) };
         }
-        let n_threads = n_threads.min(samples.len());
-        let (n_inputs, n_outputs) = {
-            let params = unsafe { self.params.as_ref() };
-            let layer0 = params.layer(0).unwrap();
-            let layer_last = params.layer(params.n_layers() - 1).unwrap();
-            (layer0.n_previous, layer_last.n)
-        };
-        let sample_size = n_inputs + n_outputs;
+        let sample_size = self.sample_size();
+        let n_threads = n_threads.min(samples.len() / sample_size).max(1);
         let chunk_size = samples.len() / sample_size / n_threads * sample_size;
         let (tx, rx) = mpsc::channel();
         std::thread::scope(|s| {
@@ -92,12 +611,20 @@ impl<'a> Gym<'a> {
             }
         });
         let mut loss = 0.0f32;
+        let mut gradient = vec![0.0f32; unsafe { self.params.as_ref() }.as_slice().len()];
         for _ in 0..n_threads {
             let result = rx.recv().unwrap();
             loss += result.loss;
             let params = unsafe { &mut *self.params.as_ptr() };
             unsafe { apply_derivs(params, &result.derivs, eta) };
+            for (g, &dp) in iter::zip(&mut gradient, result.derivs.params()) {
+                *g += dp;
+            }
         }
+        // Each worker's derivs are a mean over its chunk, average them like the loss so that the
+        // norm is comparable to the single threaded one.
+        self.gradient_norm = l2_norm(&gradient) / (n_threads as f32);
+        self.finish_step();
         loss / (n_threads as f32)
     }
 }
@@ -108,3 +635,7 @@ fn worker(params: &ParamBuffer, topology: &Topology, samples: &[f32]) -> WorkerR
     let loss = unsafe { calculate_derivs(params, &mut results, &mut derivs, samples) };
     WorkerResult { loss, derivs }
 }
+
+fn l2_norm(xs: &[f32]) -> f32 {
+    xs.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
@@ -0,0 +1,167 @@
+use std::{fmt, fs, io, path::Path, time::Duration};
+
+use crate::{Callback, Control, Hyperparameters, Progress};
+
+/// One row of a `TrainingHistory`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryRecord {
+    pub i_step: usize,
+    pub i_epoch: usize,
+    pub loss: f32,
+    pub eta: f32,
+    pub gradient_norm: f32,
+    /// Time since the start of training.
+    pub elapsed: Duration,
+    /// Validation loss, if the validation set was evaluated at this step.
+    pub validation_loss: Option<f32>,
+}
+
+/// A callback that records the training progress.
+#[derive(Debug, Clone)]
+pub struct TrainingHistory {
+    /// Record once every `interval` steps.
+    interval: usize,
+    records: Vec<HistoryRecord>,
+}
+
+impl TrainingHistory {
+    /// Records every step.
+    pub fn new() -> Self {
+        Self::with_interval(1)
+    }
+
+    /// Records once every `interval` steps. Steps at which the validation set is evaluated are
+    /// always recorded.
+    pub fn with_interval(interval: usize) -> Self {
+        assert!(interval != 0);
+        Self {
+            interval,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn records(&self) -> &[HistoryRecord] {
+        &self.records
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Writes the records as CSV, with a header line.
+    pub fn write_csv(&self, mut writer: impl fmt::Write) -> fmt::Result {
+        writeln!(
+            writer,
+            "i_step,i_epoch,loss,eta,gradient_norm,elapsed_secs,validation_loss"
+        )?;
+        for record in &self.records {
+            write!(
+                writer,
+                "{},{},{},{},{},{}",
+                record.i_step,
+                record.i_epoch,
+                record.loss,
+                record.eta,
+                record.gradient_norm,
+                record.elapsed.as_secs_f64(),
+            )?;
+            match record.validation_loss {
+                Some(validation_loss) => writeln!(writer, ",{validation_loss}")?,
+                None => writeln!(writer, ",")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the records as a JSON array of objects.
+    pub fn write_json(&self, mut writer: impl fmt::Write) -> fmt::Result {
+        write!(writer, "[")?;
+        for (i, record) in self.records.iter().enumerate() {
+            if i != 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"i_step\":{},\"i_epoch\":{},\"loss\":{},\"eta\":{},\"gradient_norm\":{},\
+                \"elapsed_secs\":{},\"validation_loss\":{}}}",
+                record.i_step,
+                record.i_epoch,
+                JsonNumber(Some(record.loss)),
+                JsonNumber(Some(record.eta)),
+                JsonNumber(Some(record.gradient_norm)),
+                record.elapsed.as_secs_f64(),
+                JsonNumber(record.validation_loss),
+            )?;
+        }
+        write!(writer, "]")
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut string = String::new();
+        self.write_csv(&mut string).unwrap();
+        string
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut string = String::new();
+        self.write_json(&mut string).unwrap();
+        string
+    }
+
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    fn record(&mut self, progress: &Progress, validation_loss: Option<f32>) {
+        self.records.push(HistoryRecord {
+            i_step: progress.i_step,
+            i_epoch: progress.i_epoch,
+            loss: progress.loss,
+            eta: progress.eta,
+            gradient_norm: progress.gradient_norm,
+            elapsed: progress.elapsed,
+            validation_loss,
+        });
+    }
+}
+
+impl Default for TrainingHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Callback for TrainingHistory {
+    fn on_step(&mut self, progress: &Progress, _: &mut Hyperparameters) -> Control {
+        if progress.i_step.is_multiple_of(self.interval) {
+            self.record(progress, None);
+        }
+        Control::Continue
+    }
+
+    fn on_validation(&mut self, progress: &Progress, _: &mut Hyperparameters) -> Control {
+        match self.records.last_mut() {
+            Some(last) if last.i_step == progress.i_step => {
+                last.validation_loss = progress.validation_loss;
+            }
+            _ => self.record(progress, progress.validation_loss),
+        }
+        Control::Continue
+    }
+}
+
+/// JSON has no representation for NaN and infinities, those become `null`.
+struct JsonNumber(Option<f32>);
+
+impl fmt::Display for JsonNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(x) if x.is_finite() => write!(f, "{x}"),
+            _ => write!(f, "null"),
+        }
+    }
+}
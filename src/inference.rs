@@ -0,0 +1,333 @@
+use std::sync::{Arc, Mutex};
+
+use faer::prelude::*;
+use rayon::prelude::*;
+
+use crate::{
+    MlpError, NeuralNetwork, Preprocessor, Topology,
+    core::{ParamBuffer, ResultBuffer, forward_par_unchecked, forward_unchecked, reset_state},
+    error::{check_batch, check_batch_ids, check_ids, check_input, check_output},
+};
+
+/// A frozen network for serving inference from many threads at once.
+///
+/// Unlike `NeuralNetwork`, running it only needs `&self`. The parameters are shared via `Arc`, and
+/// activation results go into a scratch `ResultBuffer` that is either provided by the caller or
+/// taken from an internal pool.
+pub struct InferenceModel {
+    topology: Topology,
+    params: Arc<ParamBuffer>,
+    /// See `NeuralNetwork::set_preprocessor`.
+    preprocessor: Option<Preprocessor>,
+    pool: Mutex<Vec<ResultBuffer>>,
+}
+
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<InferenceModel>();
+};
+
+impl From<NeuralNetwork> for InferenceModel {
+    /// Carries the network's preprocessor over, see `predict`.
+    fn from(nn: NeuralNetwork) -> Self {
+        let topology = nn.topology().clone();
+        let preprocessor = nn.preprocessor().cloned();
+        let (params, results) = nn.into_raw_parts();
+        // Safety: the network's params are created from its own topology.
+        let mut model = unsafe { Self::from_raw_parts(topology, Arc::new(params)) };
+        model.preprocessor = preprocessor;
+        model.pool.lock().unwrap().push(results);
+        model
+    }
+}
+
+impl Clone for InferenceModel {
+    /// Shares the parameters with `self`, the clone gets its own scratch buffer pool.
+    fn clone(&self) -> Self {
+        // Safety: `self.params` is already of `self.topology`.
+        let mut model = unsafe { Self::from_raw_parts(self.topology.clone(), self.params.clone()) };
+        model.preprocessor = self.preprocessor.clone();
+        model
+    }
+}
+
+impl InferenceModel {
+    /// # Safety
+    ///
+    /// - `params` must be created from `topology`.
+    pub unsafe fn from_raw_parts(topology: Topology, params: Arc<ParamBuffer>) -> Self {
+        Self {
+            topology,
+            params,
+            preprocessor: None,
+            pool: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    pub fn n_inputs(&self) -> usize {
+        self.topology.n_inputs()
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.topology.n_outputs()
+    }
+
+    pub fn params(&self) -> &Arc<ParamBuffer> {
+        &self.params
+    }
+
+    /// See `NeuralNetwork::set_preprocessor`.
+    pub fn set_preprocessor(&mut self, preprocessor: Option<Preprocessor>) -> Result<(), MlpError> {
+        if let Some(preprocessor) = &preprocessor {
+            preprocessor.check_topology(&self.topology)?;
+        }
+        self.preprocessor = preprocessor;
+        Ok(())
+    }
+
+    pub fn preprocessor(&self) -> Option<&Preprocessor> {
+        self.preprocessor.as_ref()
+    }
+
+    /// Creates a scratch buffer for `forward`.
+    pub fn create_results(&self) -> ResultBuffer {
+        ResultBuffer::create(&self.topology)
+    }
+
+    /// Runs the network with `results` as the scratch buffer. Does not apply the preprocessor,
+    /// see `predict` for that.
+    ///
+    /// # Panics
+    ///
+    /// - if `results` is not of this model's topology, see `create_results`
+    pub fn forward<'r>(
+        &self,
+        input: ColRef<f32>,
+        results: &'r mut ResultBuffer,
+    ) -> Result<ColRef<'r, f32>, MlpError> {
+        check_input(input, self.n_inputs())?;
+        check_ids(input, &self.topology)?;
+        assert!(results.is_of_topology(&self.topology));
+        // Safety: input size and the topology of `results` are checked above.
+        Ok(unsafe { self.forward_unchecked(input, results) })
+    }
+
+    /// # Safety
+    ///
+    /// - `input` must have `n_inputs` rows
+    /// - `results` must be of this model's topology
+    pub unsafe fn forward_unchecked<'r>(
+        &self,
+        input: ColRef<f32>,
+        results: &'r mut ResultBuffer,
+    ) -> ColRef<'r, f32> {
+        unsafe { forward_unchecked(input, &self.params, results) };
+        results.layer(results.n_layers() - 1).unwrap().a
+    }
+
+    /// Same as `forward`, but with the matrix multiplications parallelized according to `par`.
+    ///
+    /// # Panics
+    ///
+    /// - if `results` is not of this model's topology, see `create_results`
+    pub fn forward_par<'r>(
+        &self,
+        input: ColRef<f32>,
+        results: &'r mut ResultBuffer,
+        par: Par,
+    ) -> Result<ColRef<'r, f32>, MlpError> {
+        check_input(input, self.n_inputs())?;
+        check_ids(input, &self.topology)?;
+        assert!(results.is_of_topology(&self.topology));
+        // Safety: input size and the topology of `results` are checked above.
+        unsafe { forward_par_unchecked(input, &self.params, results, par) };
+        Ok(results.layer(results.n_layers() - 1).unwrap().a)
+    }
+
+    /// Runs the network over every column of `inputs` in parallel, writing the outputs into the
+    /// corresponding columns of `outputs`.
+    ///
+    /// Scratch buffers are taken from the pool. Recurrent layers start from a zero state for every
+    /// column. Applies the preprocessor, see `predict`.
+    pub fn predict_batch(&self, inputs: MatRef<f32>, outputs: MatMut<f32>) -> Result<(), MlpError> {
+        predict_batch(
+            &self.params,
+            &self.topology,
+            self.preprocessor.as_ref(),
+            inputs,
+            outputs,
+            || self.take_results(),
+            |results| self.return_results(results),
+        )
+    }
+
+    /// Runs the network with a scratch buffer from the pool, writing the result into `output`.
+    /// Recurrent layers start from a zero state, see `forward` to carry it over.
+    ///
+    /// Applies the preprocessor, see `predict`.
+    pub fn predict_into(
+        &self,
+        input: ColRef<f32>,
+        mut output: ColMut<f32>,
+    ) -> Result<(), MlpError> {
+        let transformed;
+        let input = match &self.preprocessor {
+            Some(preprocessor) => {
+                transformed = preprocessor.transform_input(input)?;
+                transformed.as_ref()
+            }
+            None => input,
+        };
+        check_input(input, self.n_inputs())?;
+        check_ids(input, &self.topology)?;
+        check_output(output.rb(), self.n_outputs())?;
+        let mut results = self.take_results();
+        // Pooled buffers hold the state of whichever input they ran last.
+        reset_state(&self.params, &mut results);
+        // Safety: input size is checked above, pooled buffers are of this model's topology.
+        let a = unsafe { self.forward_unchecked(input, &mut results) };
+        output.copy_from(a);
+        self.return_results(results);
+        if let Some(preprocessor) = &self.preprocessor {
+            preprocessor.invert_in_place(output);
+        }
+        Ok(())
+    }
+
+    /// Runs the network with a scratch buffer from the pool.
+    ///
+    /// With a preprocessor, `input` is a raw one and the output is scaled back to the units of the
+    /// raw targets, see `NeuralNetwork::set_preprocessor`.
+    pub fn predict(&self, input: ColRef<f32>) -> Result<Col<f32>, MlpError> {
+        let mut output = Col::zeros(self.n_outputs());
+        self.predict_into(input, output.as_mut())?;
+        Ok(output)
+    }
+
+    /// Takes a scratch buffer out of the pool, or creates one if the pool is empty.
+    fn take_results(&self) -> ResultBuffer {
+        let pooled = self.pool.lock().unwrap().pop();
+        pooled.unwrap_or_else(|| self.create_results())
+    }
+
+    fn return_results(&self, results: ResultBuffer) {
+        self.pool.lock().unwrap().push(results);
+    }
+}
+
+impl NeuralNetwork {
+    /// Runs the network over every column of `inputs` in parallel, writing the outputs into the
+    /// corresponding columns of `outputs`.
+    ///
+    /// Each worker thread uses its own `ResultBuffer`, so this does not touch `results`. Recurrent
+    /// layers start from a zero state for every column. Applies the preprocessor, see
+    /// `set_preprocessor`.
+    pub fn predict_batch(&self, inputs: MatRef<f32>, outputs: MatMut<f32>) -> Result<(), MlpError> {
+        let topology = self.topology();
+        predict_batch(
+            self.params(),
+            topology,
+            self.preprocessor(),
+            inputs,
+            outputs,
+            || ResultBuffer::create(topology),
+            drop,
+        )
+    }
+}
+
+/// Checks the batch and runs it through `preprocessor` (if any), `predict_batch_unchecked` and
+/// back from the scaled outputs.
+///
+/// `params` must be of `topology`, and so must buffers from `take_results`.
+fn predict_batch(
+    params: &ParamBuffer,
+    topology: &Topology,
+    preprocessor: Option<&Preprocessor>,
+    inputs: MatRef<f32>,
+    mut outputs: MatMut<f32>,
+    take_results: impl Fn() -> ResultBuffer + Sync,
+    return_results: impl Fn(ResultBuffer) + Sync,
+) -> Result<(), MlpError> {
+    let transformed;
+    let inputs = match preprocessor {
+        Some(preprocessor) => {
+            let n_raw_inputs = preprocessor.n_raw_inputs();
+            check_batch(inputs, outputs.rb(), n_raw_inputs, topology.n_outputs())?;
+            transformed = preprocessor.transform_batch(inputs);
+            transformed.as_ref()
+        }
+        None => inputs,
+    };
+    check_batch(
+        inputs,
+        outputs.rb(),
+        topology.n_inputs(),
+        topology.n_outputs(),
+    )?;
+    check_batch_ids(inputs, topology)?;
+    // Safety: batch shapes are checked above, result buffers are of the topology of `params` by
+    // the function's contract.
+    unsafe {
+        predict_batch_unchecked(
+            params,
+            inputs,
+            outputs.rb_mut(),
+            take_results,
+            return_results,
+        )
+    };
+    if let Some(preprocessor) = preprocessor {
+        for j in 0..outputs.ncols() {
+            preprocessor.invert_in_place(outputs.rb_mut().col_mut(j));
+        }
+    }
+    Ok(())
+}
+
+/// Splits the columns of `inputs` and `outputs` into chunks that are processed in parallel.
+///
+/// Every chunk gets a scratch buffer from `take_results`, which is handed back to `return_results`
+/// once the chunk is done.
+///
+/// # Safety
+///
+/// - `inputs` must have as many rows as the inputs of `params`
+/// - `outputs` must have as many rows as the outputs of `params`
+/// - `inputs` and `outputs` must have the same number of columns
+/// - buffers from `take_results` must be of the same topology as `params`
+unsafe fn predict_batch_unchecked(
+    params: &ParamBuffer,
+    inputs: MatRef<f32>,
+    outputs: MatMut<f32>,
+    take_results: impl Fn() -> ResultBuffer + Sync,
+    return_results: impl Fn(ResultBuffer) + Sync,
+) {
+    let n_samples = inputs.ncols();
+    if n_samples == 0 {
+        return;
+    }
+    // A few chunks per thread to even out the load, but large enough for the scratch buffer
+    // handling to not matter.
+    let chunk_size = n_samples.div_ceil(rayon::current_num_threads() * 4).max(16);
+    inputs
+        .par_col_chunks(chunk_size)
+        .zip(outputs.par_col_chunks_mut(chunk_size))
+        .for_each(|(inputs, mut outputs)| {
+            let mut results = take_results();
+            for j in 0..inputs.ncols() {
+                // Every sample starts from a zero state, not that of whichever sample the buffer
+                // ran last.
+                reset_state(params, &mut results);
+                // Safety: function's safety contract.
+                unsafe { forward_unchecked(inputs.col(j), params, &mut results) };
+                let a = results.layer(results.n_layers() - 1).unwrap().a;
+                outputs.rb_mut().col_mut(j).copy_from(a);
+            }
+            return_results(results);
+        });
+}
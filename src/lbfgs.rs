@@ -0,0 +1,318 @@
+use std::{collections::VecDeque, iter};
+
+use crate::{Gym, MlpError, core::calculate_derivs};
+
+/// Settings of `Gym::train_lbfgs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LbfgsConfig {
+    /// Number of most recent steps the inverse Hessian is approximated from.
+    pub history: usize,
+    /// Maximum number of iterations, each being a line search.
+    pub max_iterations: usize,
+    /// Stops once the largest component of the gradient of half the loss, averaged over samples,
+    /// is at most this.
+    pub gradient_tolerance: f32,
+    /// Stops once an iteration decreases the loss by at most this, relative to the loss.
+    pub loss_tolerance: f32,
+    /// Sufficient decrease constant of the strong Wolfe conditions.
+    pub c1: f32,
+    /// Curvature constant of the strong Wolfe conditions.
+    pub c2: f32,
+    /// Maximum number of evaluations per line search.
+    pub max_line_search: usize,
+}
+
+impl Default for LbfgsConfig {
+    fn default() -> Self {
+        Self {
+            history: 10,
+            max_iterations: 1000,
+            gradient_tolerance: 1e-6,
+            loss_tolerance: 1e-9,
+            c1: 1e-4,
+            c2: 0.9,
+            max_line_search: 20,
+        }
+    }
+}
+
+/// Why `Gym::train_lbfgs` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LbfgsStop {
+    GradientTolerance,
+    LossTolerance,
+    MaxIterations,
+    /// No step satisfying the strong Wolfe conditions was found, usually because the loss is at
+    /// the limit of `f32` precision.
+    LineSearch,
+}
+
+/// Summary of a `Gym::train_lbfgs` run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LbfgsReport {
+    /// Training loss at the final params.
+    pub loss: f32,
+    pub n_iterations: usize,
+    /// Number of loss and gradient evaluations, each being a pass over the samples.
+    pub n_evaluations: usize,
+    pub stop: LbfgsStop,
+}
+
+/// Loss and gradient at a point along the search direction.
+struct Evaluation {
+    alpha: f32,
+    /// Half the training loss, of which `gradient` is the gradient.
+    f: f32,
+    gradient: Vec<f32>,
+    /// Derivative of `f` along the search direction.
+    df: f32,
+}
+
+impl Gym<'_> {
+    /// Trains full-batch with L-BFGS over all the params, with a strong Wolfe line search. Meant
+    /// for small, smooth problems, where it takes far fewer passes over `samples` than `train`.
+    ///
+    /// Frozen layers and pruned params are left as they are, learning rate multipliers are
+    /// ignored. Every iteration counts as a step, see `validation_loss`.
+    pub fn train_lbfgs(
+        &mut self,
+        samples: &[f32],
+        config: &LbfgsConfig,
+    ) -> Result<LbfgsReport, MlpError> {
+        let samples = &*self.preprocess(samples)?;
+        let mask = self.params().mask().map(<[bool]>::to_vec);
+        let mut n_evaluations = 0usize;
+        let mut x = self.params().as_slice().to_vec();
+        let mut d = vec![0.0f32; x.len()];
+        // Evaluates at `x + alpha * d`, leaving the params there.
+        let mut evaluate = |gym: &mut Self, x: &[f32], d: &[f32], alpha: f32| {
+            n_evaluations += 1;
+            for (p, (&x, &d)) in iter::zip(gym.params_mut().as_mut_slice(), iter::zip(x, d)) {
+                *p = x + alpha * d;
+            }
+            let (params, results, derivs) = gym.buffers_mut();
+            // Safety: buffers are of the same topology, sample sizes are checked above.
+            let loss = unsafe { calculate_derivs(params, results, derivs, samples) };
+            let mut gradient = derivs.params().to_vec();
+            if let Some(mask) = &mask {
+                for (g, &is_kept) in iter::zip(&mut gradient, mask) {
+                    if !is_kept {
+                        *g = 0.0;
+                    }
+                }
+            }
+            // `calculate_derivs` derives half the loss.
+            Evaluation {
+                alpha,
+                f: loss / 2.0,
+                df: dot(&gradient, d),
+                gradient,
+            }
+        };
+        let mut current = evaluate(self, &x, &d, 0.0);
+        let mut history: VecDeque<(Vec<f32>, Vec<f32>, f32)> = VecDeque::new();
+        let mut n_iterations = 0usize;
+        let stop = loop {
+            if max_abs(&current.gradient) <= config.gradient_tolerance {
+                break LbfgsStop::GradientTolerance;
+            }
+            if n_iterations == config.max_iterations {
+                break LbfgsStop::MaxIterations;
+            }
+            direction(&current.gradient, &history, &mut d);
+            let mut df0 = dot(&current.gradient, &d);
+            if df0 >= 0.0 {
+                // Not a descent direction, start over from steepest descent.
+                history.clear();
+                direction(&current.gradient, &history, &mut d);
+                df0 = dot(&current.gradient, &d);
+            }
+            let alpha0 = match history.is_empty() {
+                true => (1.0 / norm(&current.gradient)).min(1.0),
+                false => 1.0,
+            };
+            let start = Evaluation {
+                alpha: 0.0,
+                df: df0,
+                f: current.f,
+                gradient: Vec::new(),
+            };
+            let Some(next) = line_search(self, &mut evaluate, &x, &d, &start, alpha0, config)
+            else {
+                // Back to the params of the last iteration.
+                self.params_mut().as_mut_slice().copy_from_slice(&x);
+                break LbfgsStop::LineSearch;
+            };
+            n_iterations += 1;
+            let s: Vec<f32> = d.iter().map(|&d| next.alpha * d).collect();
+            let y: Vec<f32> = iter::zip(&next.gradient, &current.gradient)
+                .map(|(&g_next, &g)| g_next - g)
+                .collect();
+            let sy = dot(&s, &y);
+            if sy > f32::EPSILON * dot(&y, &y) {
+                if history.len() == config.history {
+                    history.pop_front();
+                }
+                history.push_back((s, y, 1.0 / sy));
+            }
+            for (x, &d) in iter::zip(&mut x, &d) {
+                *x += next.alpha * d;
+            }
+            // Of the gradient the step was taken along, as with `train_levenberg_marquardt`.
+            self.gradient_norm = norm(&current.gradient);
+            let decrease = current.f - next.f;
+            current = next;
+            self.finish_step();
+            if decrease <= config.loss_tolerance * current.f.abs().max(1.0) {
+                break LbfgsStop::LossTolerance;
+            }
+        };
+        Ok(LbfgsReport {
+            loss: current.f * 2.0,
+            n_iterations,
+            n_evaluations,
+            stop,
+        })
+    }
+}
+
+/// Search direction `-H * gradient`, with `H` the inverse Hessian approximated from `history` by
+/// the two-loop recursion.
+fn direction(gradient: &[f32], history: &VecDeque<(Vec<f32>, Vec<f32>, f32)>, d: &mut [f32]) {
+    for (d, &g) in iter::zip(&mut *d, gradient) {
+        *d = -g;
+    }
+    let mut alphas = Vec::with_capacity(history.len());
+    for (s, y, rho) in history.iter().rev() {
+        let alpha = rho * dot(s, d);
+        for (d, &y) in iter::zip(&mut *d, y) {
+            *d -= alpha * y;
+        }
+        alphas.push(alpha);
+    }
+    if let Some((s, y, _)) = history.back() {
+        let gamma = dot(s, y) / dot(y, y);
+        for d in &mut *d {
+            *d *= gamma;
+        }
+    }
+    for ((s, y, rho), alpha) in iter::zip(history, alphas.into_iter().rev()) {
+        let beta = rho * dot(y, d);
+        for (d, &s) in iter::zip(&mut *d, s) {
+            *d += (alpha - beta) * s;
+        }
+    }
+}
+
+/// Finds a step along `d` satisfying the strong Wolfe conditions, by Algorithm 3.5 of Nocedal &
+/// Wright's Numerical Optimization. Leaves the params at the step found.
+fn line_search<G>(
+    gym: &mut G,
+    evaluate: &mut impl FnMut(&mut G, &[f32], &[f32], f32) -> Evaluation,
+    x: &[f32],
+    d: &[f32],
+    start: &Evaluation,
+    alpha0: f32,
+    config: &LbfgsConfig,
+) -> Option<Evaluation> {
+    let is_sufficient = |e: &Evaluation| e.f <= start.f + config.c1 * e.alpha * start.df;
+    let is_flat = |e: &Evaluation| e.df.abs() <= -config.c2 * start.df;
+    let mut previous: Option<Evaluation> = None;
+    let mut alpha = alpha0;
+    for _ in 0..config.max_line_search {
+        let e = evaluate(gym, x, d, alpha);
+        let previous_f = previous.as_ref().map_or(start.f, |p| p.f);
+        if !e.f.is_finite() || !is_sufficient(&e) || (previous.is_some() && e.f >= previous_f) {
+            let lo = previous.unwrap_or(Evaluation {
+                gradient: Vec::new(),
+                ..*start
+            });
+            return zoom(gym, evaluate, x, d, start, lo, e, config);
+        }
+        if is_flat(&e) {
+            return Some(e);
+        }
+        if e.df >= 0.0 {
+            let hi = previous.unwrap_or(Evaluation {
+                gradient: Vec::new(),
+                ..*start
+            });
+            return zoom(gym, evaluate, x, d, start, e, hi, config);
+        }
+        alpha *= 2.0;
+        previous = Some(e);
+    }
+    None
+}
+
+/// Narrows down `[lo, hi]` (in either order) to a step satisfying the strong Wolfe conditions,
+/// `lo` being the end with the lower loss that satisfies the sufficient decrease condition.
+#[allow(clippy::too_many_arguments)]
+fn zoom<G>(
+    gym: &mut G,
+    evaluate: &mut impl FnMut(&mut G, &[f32], &[f32], f32) -> Evaluation,
+    x: &[f32],
+    d: &[f32],
+    start: &Evaluation,
+    mut lo: Evaluation,
+    mut hi: Evaluation,
+    config: &LbfgsConfig,
+) -> Option<Evaluation> {
+    for _ in 0..config.max_line_search {
+        let alpha = interpolate(&lo, &hi);
+        let e = evaluate(gym, x, d, alpha);
+        if !e.f.is_finite() || e.f > start.f + config.c1 * alpha * start.df || e.f >= lo.f {
+            hi = e;
+        } else {
+            if e.df.abs() <= -config.c2 * start.df {
+                return Some(e);
+            }
+            if e.df * (hi.alpha - lo.alpha) >= 0.0 {
+                hi = lo;
+            }
+            lo = e;
+        }
+        if (hi.alpha - lo.alpha).abs() <= f32::EPSILON * lo.alpha.max(hi.alpha) {
+            break;
+        }
+    }
+    // Settle for a step that decreases the loss if there is one.
+    match lo.alpha > 0.0 && lo.f < start.f {
+        true => Some(evaluate(gym, x, d, lo.alpha)),
+        false => None,
+    }
+}
+
+/// Minimizer of the cubic through `lo` and `hi`, kept away from the ends of the interval. Falls
+/// back to bisection if the cubic has no minimizer.
+fn interpolate(lo: &Evaluation, hi: &Evaluation) -> f32 {
+    let (a, b) = (lo.alpha, hi.alpha);
+    let d1 = lo.df + hi.df - 3.0 * (lo.f - hi.f) / (a - b);
+    let d2_squared = d1 * d1 - lo.df * hi.df;
+    let bisection = (a + b) / 2.0;
+    if d2_squared.is_nan() || d2_squared < 0.0 {
+        return bisection;
+    }
+    let d2 = d2_squared.sqrt().copysign(b - a);
+    let alpha = b - (b - a) * (hi.df + d2 - d1) / (hi.df - lo.df + 2.0 * d2);
+    let (min, max) = (a.min(b), a.max(b));
+    let margin = 0.1 * (max - min);
+    match alpha.is_finite() && alpha >= min + margin && alpha <= max - margin {
+        true => alpha,
+        false => bisection,
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    iter::zip(a, b)
+        .map(|(&a, &b)| a as f64 * b as f64)
+        .sum::<f64>() as f32
+}
+
+fn norm(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn max_abs(a: &[f32]) -> f32 {
+    a.iter().fold(0.0, |max, &a| max.max(a.abs()))
+}
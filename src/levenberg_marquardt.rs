@@ -0,0 +1,190 @@
+use faer::{Side, linalg::solvers::Solve, prelude::*};
+
+use crate::{
+    Gym, MlpError,
+    core::{back_propagate_params_unchecked, forward_unchecked, loss_unchecked, reset_state},
+};
+
+/// Settings of `Gym::train_levenberg_marquardt`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevenbergMarquardtConfig {
+    /// Maximum number of iterations, each being an accepted step.
+    pub max_iterations: usize,
+    /// Damping factor to start from, larger ones take shorter steps closer to gradient descent.
+    pub initial_damping: f32,
+    /// Damping is multiplied by this after a rejected step.
+    pub damping_increase: f32,
+    /// Damping is divided by this after an accepted step.
+    pub damping_decrease: f32,
+    /// Stops once the damping exceeds this, i.e. no step decreases the loss.
+    pub max_damping: f32,
+    /// Stops once the largest component of the gradient is at most this, the gradient being of
+    /// half the loss averaged over samples as with `LbfgsConfig::gradient_tolerance`.
+    pub gradient_tolerance: f32,
+    /// Stops once a step decreases the loss by at most this, relative to the loss.
+    pub loss_tolerance: f32,
+}
+
+impl Default for LevenbergMarquardtConfig {
+    fn default() -> Self {
+        Self {
+            max_iterations: 500,
+            initial_damping: 1e-3,
+            damping_increase: 10.0,
+            damping_decrease: 10.0,
+            max_damping: 1e10,
+            gradient_tolerance: 1e-7,
+            loss_tolerance: 1e-9,
+        }
+    }
+}
+
+/// Why `Gym::train_levenberg_marquardt` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevenbergMarquardtStop {
+    GradientTolerance,
+    LossTolerance,
+    MaxIterations,
+    MaxDamping,
+}
+
+/// Summary of a `Gym::train_levenberg_marquardt` run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevenbergMarquardtReport {
+    /// Training loss at the final params.
+    pub loss: f32,
+    pub n_iterations: usize,
+    /// Number of steps tried, including rejected ones.
+    pub n_evaluations: usize,
+    /// Damping factor at the end, see `LevenbergMarquardtConfig::initial_damping`.
+    pub damping: f32,
+    pub stop: LevenbergMarquardtStop,
+}
+
+/// Floor of the diagonal of `J^T * J` that the damping scales, so that params the outputs do not
+/// depend on are damped too.
+const DIAGONAL_FLOOR: f64 = 1e-9;
+
+impl Gym<'_> {
+    /// Trains full-batch with Levenberg–Marquardt, solving the damped normal equations of the
+    /// Jacobian of the residuals (every output of every sample) by the params.
+    ///
+    /// The Jacobian has a row per output per sample and a column per param, so this is meant for
+    /// small networks of up to a few hundred params. Frozen layers and pruned params are left as
+    /// they are. Every iteration counts as a step, see `validation_loss`.
+    pub fn train_levenberg_marquardt(
+        &mut self,
+        samples: &[f32],
+        config: &LevenbergMarquardtConfig,
+    ) -> Result<LevenbergMarquardtReport, MlpError> {
+        let (n_inputs, n_outputs) = (self.topology.n_inputs(), self.topology.n_outputs());
+        let samples = &*self.preprocess(samples)?;
+        let n_samples = samples.len() / (n_inputs + n_outputs);
+        let free = self.params().free_indices();
+        let mut jacobian = Mat::<f64>::zeros(n_samples * n_outputs, free.len());
+        let mut residuals = Col::<f64>::zeros(n_samples * n_outputs);
+        let mut damping = config.initial_damping as f64;
+        let mut loss = self.linearize(samples, &free, jacobian.as_mut(), residuals.as_mut());
+        let mut n_iterations = 0usize;
+        let mut n_evaluations = 0usize;
+        let stop = 'iterations: loop {
+            // `J^T * r` derives half the summed loss, dividing by the number of samples makes it
+            // the gradient `train_lbfgs` and `train` go by, of half the averaged one.
+            let gradient = jacobian.transpose() * &residuals;
+            let max_gradient = gradient.iter().fold(0.0f64, |max, g| max.max(g.abs()));
+            if max_gradient / n_samples as f64 <= config.gradient_tolerance as f64 {
+                break LevenbergMarquardtStop::GradientTolerance;
+            }
+            if n_iterations == config.max_iterations {
+                break LevenbergMarquardtStop::MaxIterations;
+            }
+            let normal = jacobian.transpose() * &jacobian;
+            let x = self.params().as_slice().to_vec();
+            let new_loss = loop {
+                if damping > config.max_damping as f64 {
+                    break 'iterations LevenbergMarquardtStop::MaxDamping;
+                }
+                let mut damped = normal.clone();
+                for i in 0..free.len() {
+                    damped[(i, i)] += damping * normal[(i, i)].max(DIAGONAL_FLOOR);
+                }
+                let Ok(llt) = damped.llt(Side::Lower) else {
+                    damping *= config.damping_increase as f64;
+                    continue;
+                };
+                let step = llt.solve(&gradient);
+                let params = self.params_mut().as_mut_slice();
+                for (&p, &step) in free.iter().zip(step.iter()) {
+                    params[p] = x[p] - step as f32;
+                }
+                n_evaluations += 1;
+                let (params, results, _) = self.buffers_mut();
+                // Safety: buffers are of the same topology, sample sizes are checked above.
+                let new_loss = unsafe { loss_unchecked(params, results, samples) } as f64;
+                if new_loss < loss {
+                    damping /= config.damping_decrease as f64;
+                    break new_loss;
+                }
+                self.params_mut().as_mut_slice().copy_from_slice(&x);
+                damping *= config.damping_increase as f64;
+            };
+            n_iterations += 1;
+            self.gradient_norm = (gradient.norm_l2() / n_samples as f64) as f32;
+            self.finish_step();
+            let decrease = loss - new_loss;
+            loss = self.linearize(samples, &free, jacobian.as_mut(), residuals.as_mut());
+            if decrease <= config.loss_tolerance as f64 * loss.max(1.0) {
+                break LevenbergMarquardtStop::LossTolerance;
+            }
+        };
+        Ok(LevenbergMarquardtReport {
+            loss: (loss / n_samples as f64) as f32,
+            n_iterations,
+            n_evaluations,
+            damping: damping as f32,
+            stop,
+        })
+    }
+
+    /// Fills in the residuals `a - y` of every output of every sample, and their derivatives by
+    /// the `free` params, both zero for targets that are NaN. Returns the summed squared residual.
+    fn linearize(
+        &mut self,
+        samples: &[f32],
+        free: &[usize],
+        mut jacobian: MatMut<f64>,
+        mut residuals: ColMut<f64>,
+    ) -> f64 {
+        let (n_inputs, n_outputs) = (self.topology.n_inputs(), self.topology.n_outputs());
+        let mut da_output = Col::<f32>::zeros(n_outputs);
+        let (params, results, derivs) = self.buffers_mut();
+        for (i, sample) in samples.chunks(n_inputs + n_outputs).enumerate() {
+            let x = ColRef::from_slice(&sample[..n_inputs]);
+            reset_state(params, results);
+            // Safety: buffers are of the same topology, sample sizes are checked by the caller.
+            unsafe { forward_unchecked(x, params, results) };
+            let a = results.layer(results.n_layers() - 1).unwrap().a;
+            let y = &sample[n_inputs..];
+            for j in 0..n_outputs {
+                // Missing targets have no residual, see `mask_targets`.
+                if y[j].is_nan() {
+                    residuals[i * n_outputs + j] = 0.0;
+                    jacobian.rb_mut().row_mut(i * n_outputs + j).fill(0.0);
+                    continue;
+                }
+                residuals[i * n_outputs + j] = (a[j] - y[j]) as f64;
+                da_output[j] = 1.0;
+                // Safety: same as above.
+                unsafe {
+                    back_propagate_params_unchecked(params, results, derivs, x, da_output.as_ref())
+                };
+                da_output[j] = 0.0;
+                let derivs = derivs.params();
+                for (c, &p) in free.iter().enumerate() {
+                    jacobian[(i * n_outputs + j, c)] = derivs[p] as f64;
+                }
+            }
+        }
+        residuals.squared_norm_l2()
+    }
+}
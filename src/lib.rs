@@ -1,17 +1,53 @@
 pub use faer;
 
 mod activation;
+mod callback;
+mod checkpoint;
+mod conv;
+mod embedding;
+mod error;
+mod evolution;
+mod gradient;
 mod gym;
+mod history;
+mod inference;
+mod lbfgs;
+mod levenberg_marquardt;
+mod metrics;
+mod model;
 mod nn;
+mod preprocess;
 mod pretty_print;
+mod prune;
 mod ptr;
+mod quantize;
+mod sequence;
+mod surgery;
+mod targets;
 
 pub use activation::*;
+pub use callback::*;
+pub use checkpoint::*;
+pub use conv::*;
+pub use embedding::*;
+pub use error::*;
+pub use evolution::*;
 pub use gym::*;
+pub use history::*;
+pub use inference::*;
+pub use lbfgs::*;
+pub use levenberg_marquardt::*;
+pub use metrics::*;
 pub use nn::*;
+pub use preprocess::*;
 pub use pretty_print::*;
+pub use prune::*;
 pub use ptr::*;
+pub use quantize::*;
+pub use sequence::*;
+pub use targets::*;
 
 pub mod core;
 
+pub(crate) mod serialize;
 pub(crate) mod utils;
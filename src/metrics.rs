@@ -0,0 +1,450 @@
+use std::{cmp::Ordering, iter};
+
+use faer::prelude::*;
+
+use crate::{
+    MlpError, NeuralNetwork,
+    error::{check_sample_ids, check_samples},
+    preprocess::preprocess_samples,
+};
+
+/// Outputs of a network over a dataset, alongside the expected outputs.
+///
+/// Everything is stored sample by sample, i.e. `outputs[i * n_outputs..(i + 1) * n_outputs]` is
+/// the output for sample `i`.
+#[derive(Debug, Clone)]
+pub struct Predictions {
+    n_outputs: usize,
+    outputs: Vec<f32>,
+    targets: Vec<f32>,
+}
+
+impl NeuralNetwork {
+    /// Runs every sample in `samples` through the network and collects the outputs.
+    ///
+    /// With a preprocessor, `samples` are raw ones and both the outputs and the targets are in the
+    /// units of the raw targets, see `set_preprocessor`.
+    pub fn predict(&mut self, samples: &[f32]) -> Result<Predictions, MlpError> {
+        let raw_samples = samples;
+        let samples = preprocess_samples(self.preprocessor(), samples)?;
+        let n_inputs = self.n_inputs();
+        let n_outputs = self.n_outputs();
+        let sample_size = n_inputs + n_outputs;
+        check_samples(&samples, sample_size)?;
+        check_sample_ids(&samples, self.topology())?;
+        let n_samples = samples.len() / sample_size;
+        let mut outputs = Vec::with_capacity(n_samples * n_outputs);
+        let mut targets = Vec::with_capacity(n_samples * n_outputs);
+        let raw_sample_size = raw_samples.len() / n_samples;
+        for (sample, raw_sample) in samples
+            .chunks(sample_size)
+            .zip(raw_samples.chunks(raw_sample_size))
+        {
+            // Same as `loss`, every sample starts from a zero state.
+            self.reset_state();
+            // Safety: sample sizes are checked above.
+            let a = unsafe { self.forward_unchecked(ColRef::from_slice(&sample[0..n_inputs])) };
+            outputs.extend(a.iter());
+            targets.extend_from_slice(&raw_sample[raw_sample_size - n_outputs..]);
+        }
+        if let Some(preprocessor) = self.preprocessor() {
+            for output in outputs.chunks_mut(n_outputs) {
+                preprocessor.invert_in_place(ColMut::from_slice_mut(output));
+            }
+        }
+        Ok(Predictions {
+            n_outputs,
+            outputs,
+            targets,
+        })
+    }
+}
+
+impl Predictions {
+    /// # Panics
+    ///
+    /// - if `outputs` and `targets` are of different lengths
+    /// - if their lengths are not a multiple of `n_outputs`
+    pub fn new(n_outputs: usize, outputs: Vec<f32>, targets: Vec<f32>) -> Self {
+        assert!(n_outputs != 0);
+        assert!(outputs.len() == targets.len());
+        assert!(outputs.len().is_multiple_of(n_outputs));
+        Self {
+            n_outputs,
+            outputs,
+            targets,
+        }
+    }
+
+    pub fn n_samples(&self) -> usize {
+        self.outputs.len() / self.n_outputs
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.n_outputs
+    }
+
+    /// Output of the network for sample `i`.
+    pub fn output(&self, i: usize) -> &[f32] {
+        &self.outputs[i * self.n_outputs..(i + 1) * self.n_outputs]
+    }
+
+    /// Expected output for sample `i`.
+    pub fn target(&self, i: usize) -> &[f32] {
+        &self.targets[i * self.n_outputs..(i + 1) * self.n_outputs]
+    }
+
+    /// Squared error of each sample, summed over the outputs whose target is not NaN.
+    ///
+    /// Summing these gives `NeuralNetwork::loss`, unless a preprocessor scales the targets.
+    pub fn losses(&self) -> Vec<f32> {
+        (0..self.n_samples())
+            .map(|i| {
+                iter::zip(self.output(i), self.target(i))
+                    .filter(|(_, y)| !y.is_nan())
+                    .map(|(&a, &y)| (a - y).powi(2))
+                    .sum::<f32>()
+            })
+            .collect()
+    }
+
+    /// Mean of `losses`.
+    pub fn mean_loss(&self) -> f32 {
+        self.losses().iter().sum::<f32>() / (self.n_samples() as f32)
+    }
+
+    /// Number of classes when treating the outputs as a classification.
+    ///
+    /// A single output is treated as a binary classification, multiple outputs as one-hot encoded
+    /// classes.
+    pub fn n_classes(&self) -> usize {
+        match self.n_outputs {
+            1 => 2,
+            n => n,
+        }
+    }
+
+    /// Class predicted by the network for sample `i`.
+    ///
+    /// For a single output, that is whether the output is above `0.5`. Otherwise it is the index of
+    /// the largest output.
+    pub fn predicted_class(&self, i: usize) -> usize {
+        class_of(self.output(i))
+    }
+
+    /// Class of the expected output for sample `i`, see `predicted_class`.
+    pub fn target_class(&self, i: usize) -> usize {
+        class_of(self.target(i))
+    }
+
+    /// Samples whose targets are all known, i.e. not NaN. Classification metrics only count
+    /// these, as for the loss a missing target says nothing about the output.
+    fn labelled(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.n_samples()).filter(|&i| !self.target(i).iter().any(|y| y.is_nan()))
+    }
+
+    /// Fraction of labelled samples, those whose targets are all known, whose predicted class
+    /// matches the target class. Defined to be `0.0` if no sample is labelled.
+    pub fn accuracy(&self) -> f32 {
+        let (mut n_correct, mut n_labelled) = (0, 0);
+        for i in self.labelled() {
+            n_correct += (self.predicted_class(i) == self.target_class(i)) as usize;
+            n_labelled += 1;
+        }
+        ratio(n_correct, n_labelled)
+    }
+
+    /// Fraction of labelled samples whose target class is among the `k` largest outputs.
+    ///
+    /// For a single output this is the same as `accuracy` for `k == 1`, and always `1.0` for
+    /// `k >= 2`.
+    pub fn top_k_accuracy(&self, k: usize) -> f32 {
+        if self.n_outputs == 1 {
+            return match k {
+                0 => 0.0,
+                1 => self.accuracy(),
+                _ => 1.0,
+            };
+        }
+        let (mut n_correct, mut n_labelled) = (0, 0);
+        for i in self.labelled() {
+            let output = self.output(i);
+            let target_score = output[self.target_class(i)];
+            // Number of classes scoring strictly higher than the target class.
+            let rank = output.iter().filter(|&&a| a > target_score).count();
+            n_correct += (rank < k) as usize;
+            n_labelled += 1;
+        }
+        ratio(n_correct, n_labelled)
+    }
+
+    /// Counts of the labelled samples, see `accuracy`.
+    pub fn confusion_matrix(&self) -> ConfusionMatrix {
+        let n_classes = self.n_classes();
+        let mut counts = vec![0usize; n_classes * n_classes];
+        for i in self.labelled() {
+            let actual = self.target_class(i);
+            let predicted = self.predicted_class(i);
+            counts[actual * n_classes + predicted] += 1;
+        }
+        ConfusionMatrix { n_classes, counts }
+    }
+
+    /// Area under the ROC curve of one class against the rest over the labelled samples, using the
+    /// output of that class as the score.
+    ///
+    /// For a single output, class `1` uses the output as the score and class `0` uses its negation.
+    /// Returns `None` if the class is not present, or is the only class present among the targets.
+    pub fn roc_auc_of_class(&self, class: usize) -> Option<f32> {
+        assert!(class < self.n_classes());
+        let mut scored: Vec<(f32, bool)> = self
+            .labelled()
+            .map(|i| {
+                let score = match self.n_outputs {
+                    1 if class == 0 => -self.output(i)[0],
+                    1 => self.output(i)[0],
+                    _ => self.output(i)[class],
+                };
+                (score, self.target_class(i) == class)
+            })
+            .collect();
+        let n_positives = scored.iter().filter(|&&(_, positive)| positive).count();
+        let n_negatives = scored.len() - n_positives;
+        if n_positives == 0 || n_negatives == 0 {
+            return None;
+        }
+        // Mann-Whitney U statistic, ties get the average of their ranks.
+        scored.sort_by(|(lhs, _), (rhs, _)| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal));
+        let mut positive_rank_sum = 0.0f64;
+        let mut i = 0usize;
+        while i < scored.len() {
+            let mut j = i;
+            while j + 1 < scored.len() && scored[j + 1].0 == scored[i].0 {
+                j += 1;
+            }
+            // Ranks are 1-based.
+            let average_rank = (i + j) as f64 / 2.0 + 1.0;
+            let n_tied_positives = scored[i..=j].iter().filter(|&&(_, p)| p).count();
+            positive_rank_sum += average_rank * n_tied_positives as f64;
+            i = j + 1;
+        }
+        let n_positives = n_positives as f64;
+        let n_negatives = n_negatives as f64;
+        let u = positive_rank_sum - n_positives * (n_positives + 1.0) / 2.0;
+        Some((u / (n_positives * n_negatives)) as f32)
+    }
+
+    /// Area under the ROC curve.
+    ///
+    /// For a single output this is the binary ROC-AUC, otherwise it's the macro average of
+    /// `roc_auc_of_class` over the classes for which it is defined.
+    pub fn roc_auc(&self) -> Option<f32> {
+        if self.n_outputs == 1 {
+            return self.roc_auc_of_class(1);
+        }
+        let aucs: Vec<f32> = (0..self.n_classes())
+            .filter_map(|class| self.roc_auc_of_class(class))
+            .collect();
+        match aucs.is_empty() {
+            true => None,
+            false => Some(aucs.iter().sum::<f32>() / aucs.len() as f32),
+        }
+    }
+
+    /// Accuracy, confusion matrix and related metrics for classification tasks.
+    pub fn classification_metrics(&self) -> ClassificationMetrics {
+        let confusion_matrix = self.confusion_matrix();
+        let n_classes = confusion_matrix.n_classes();
+        ClassificationMetrics {
+            accuracy: self.accuracy(),
+            precision: (0..n_classes)
+                .map(|c| confusion_matrix.precision(c))
+                .collect(),
+            recall: (0..n_classes).map(|c| confusion_matrix.recall(c)).collect(),
+            f1: (0..n_classes).map(|c| confusion_matrix.f1(c)).collect(),
+            roc_auc: self.roc_auc(),
+            confusion_matrix,
+        }
+    }
+
+    /// Error metrics of a single output for regression tasks, over the samples whose target of
+    /// that output is not NaN. All of them are NaN if there are no such samples.
+    pub fn regression_metrics_of_output(&self, j: usize) -> RegressionMetrics {
+        assert!(j < self.n_outputs);
+        let known: Vec<(f64, f64)> = (0..self.n_samples())
+            .map(|i| (self.output(i)[j] as f64, self.target(i)[j] as f64))
+            .filter(|(_, y)| !y.is_nan())
+            .collect();
+        if known.is_empty() {
+            return RegressionMetrics {
+                mae: f32::NAN,
+                rmse: f32::NAN,
+                r2: f32::NAN,
+            };
+        }
+        let n_known = known.len() as f64;
+        let mean_target = known.iter().map(|(_, y)| y).sum::<f64>() / n_known;
+        let mut sum_abs_error = 0.0f64;
+        let mut sum_squared_error = 0.0f64;
+        let mut sum_squared_deviation = 0.0f64;
+        for (a, y) in known {
+            sum_abs_error += (a - y).abs();
+            sum_squared_error += (a - y).powi(2);
+            sum_squared_deviation += (y - mean_target).powi(2);
+        }
+        RegressionMetrics {
+            mae: (sum_abs_error / n_known) as f32,
+            rmse: (sum_squared_error / n_known).sqrt() as f32,
+            r2: r2(sum_squared_error, sum_squared_deviation) as f32,
+        }
+    }
+
+    /// Error metrics for regression tasks, averaged over all the outputs.
+    pub fn regression_metrics(&self) -> RegressionMetrics {
+        let per_output: Vec<RegressionMetrics> = (0..self.n_outputs)
+            .map(|j| self.regression_metrics_of_output(j))
+            .collect();
+        let n_outputs = self.n_outputs as f32;
+        let mean_squared_error = per_output.iter().map(|m| m.rmse.powi(2)).sum::<f32>() / n_outputs;
+        RegressionMetrics {
+            mae: per_output.iter().map(|m| m.mae).sum::<f32>() / n_outputs,
+            rmse: mean_squared_error.sqrt(),
+            r2: per_output.iter().map(|m| m.r2).sum::<f32>() / n_outputs,
+        }
+    }
+}
+
+fn class_of(values: &[f32]) -> usize {
+    match values {
+        [value] => (*value > 0.5) as usize,
+        values => {
+            let mut i_max = 0usize;
+            for (i, &value) in values.iter().enumerate() {
+                if value > values[i_max] {
+                    i_max = i;
+                }
+            }
+            i_max
+        }
+    }
+}
+
+/// Coefficient of determination, `1 - SS_res / SS_tot`.
+fn r2(sum_squared_error: f64, sum_squared_deviation: f64) -> f64 {
+    if sum_squared_deviation == 0.0 {
+        // Constant targets, only a perfect fit explains them.
+        return match sum_squared_error == 0.0 {
+            true => 1.0,
+            false => 0.0,
+        };
+    }
+    1.0 - sum_squared_error / sum_squared_deviation
+}
+
+/// Counts of (actual class, predicted class) pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    n_classes: usize,
+    /// Row-major, rows are the actual classes and columns are the predicted classes.
+    counts: Vec<usize>,
+}
+
+impl ConfusionMatrix {
+    pub fn n_classes(&self) -> usize {
+        self.n_classes
+    }
+
+    /// Number of samples of class `actual` that were predicted as `predicted`.
+    pub fn get(&self, actual: usize, predicted: usize) -> usize {
+        assert!(actual < self.n_classes);
+        assert!(predicted < self.n_classes);
+        self.counts[actual * self.n_classes + predicted]
+    }
+
+    pub fn true_positives(&self, class: usize) -> usize {
+        self.get(class, class)
+    }
+
+    /// Number of samples predicted as `class`.
+    pub fn n_predicted(&self, class: usize) -> usize {
+        (0..self.n_classes)
+            .map(|actual| self.get(actual, class))
+            .sum()
+    }
+
+    /// Number of samples that are actually of `class`.
+    pub fn n_actual(&self, class: usize) -> usize {
+        (0..self.n_classes)
+            .map(|predicted| self.get(class, predicted))
+            .sum()
+    }
+
+    /// Defined to be `0.0` if nothing was predicted as `class`.
+    pub fn precision(&self, class: usize) -> f32 {
+        ratio(self.true_positives(class), self.n_predicted(class))
+    }
+
+    /// Defined to be `0.0` if no sample is of `class`.
+    pub fn recall(&self, class: usize) -> f32 {
+        ratio(self.true_positives(class), self.n_actual(class))
+    }
+
+    pub fn f1(&self, class: usize) -> f32 {
+        let precision = self.precision(class);
+        let recall = self.recall(class);
+        match precision + recall {
+            0.0 => 0.0,
+            sum => 2.0 * precision * recall / sum,
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    match denominator {
+        0 => 0.0,
+        denominator => numerator as f32 / denominator as f32,
+    }
+}
+
+/// Metrics for classification tasks, see `Predictions::classification_metrics`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassificationMetrics {
+    pub accuracy: f32,
+    /// Per class.
+    pub precision: Vec<f32>,
+    /// Per class.
+    pub recall: Vec<f32>,
+    /// Per class.
+    pub f1: Vec<f32>,
+    pub roc_auc: Option<f32>,
+    pub confusion_matrix: ConfusionMatrix,
+}
+
+impl ClassificationMetrics {
+    pub fn macro_precision(&self) -> f32 {
+        mean(&self.precision)
+    }
+
+    pub fn macro_recall(&self) -> f32 {
+        mean(&self.recall)
+    }
+
+    pub fn macro_f1(&self) -> f32 {
+        mean(&self.f1)
+    }
+}
+
+fn mean(xs: &[f32]) -> f32 {
+    xs.iter().sum::<f32>() / xs.len() as f32
+}
+
+/// Metrics for regression tasks, see `Predictions::regression_metrics`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionMetrics {
+    /// Mean absolute error.
+    pub mae: f32,
+    /// Root mean squared error.
+    pub rmse: f32,
+    /// Coefficient of determination.
+    pub r2: f32,
+}
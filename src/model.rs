@@ -0,0 +1,57 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    MlpError, NeuralNetwork, Preprocessor,
+    checkpoint::{decode_topology, encode_topology},
+    serialize::{Decoder, Encoder, invalid_data},
+};
+
+const MAGIC: &[u8; 8] = b"MLPMODEL";
+const VERSION: u32 = 1;
+
+impl NeuralNetwork {
+    /// Saves the params and the preprocessor (see `set_preprocessor`) to `path`, for serving
+    /// with exactly the preprocessing the network was trained with.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut encoder = Encoder::new(MAGIC, VERSION);
+        encode_topology(&mut encoder, self.topology());
+        encoder.f32_slice(self.params_as_slice());
+        encoder.bool(self.preprocessor().is_some());
+        if let Some(preprocessor) = self.preprocessor() {
+            preprocessor.encode_to(&mut encoder);
+        }
+        // Same as for checkpoints, a process killed mid-write leaves the previous file intact.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, encoder.into_bytes())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Restores the params and the preprocessor saved by `save` into a network of the same
+    /// topology.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = fs::read(path)?;
+        let (mut decoder, version) = Decoder::new(&bytes, MAGIC)?;
+        if version != VERSION {
+            return Err(invalid_data("unsupported model version"));
+        }
+        decode_topology(&mut decoder, self.topology()).map_err(|error| match error {
+            MlpError::Io(error) => error,
+            error => invalid_data(&error.to_string()),
+        })?;
+        let params = decoder.f32_vec()?;
+        if params.len() != self.params_as_slice().len() {
+            return Err(invalid_data("parameter count mismatch"));
+        }
+        let preprocessor = match decoder.bool()? {
+            true => Some(Preprocessor::decode_from(&mut decoder)?),
+            false => None,
+        };
+        decoder.finish()?;
+        // Only modify state once the whole file is known to be valid.
+        self.set_preprocessor(preprocessor)
+            .map_err(|error| invalid_data(&error.to_string()))?;
+        self.params_as_mut_slice().copy_from_slice(&params);
+        Ok(())
+    }
+}
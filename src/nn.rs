@@ -1,11 +1,18 @@
-use std::{iter, slice::GetDisjointMutError};
+use std::slice::GetDisjointMutError;
 
 use faer::prelude::*;
 use rand::distr::uniform::SampleRange;
 
 use crate::{
-    ActivationFunction, DynActivationFunction,
-    core::{ParamBuffer, ResultBuffer, forward_unchecked, param_buffer, result_buffer},
+    ActivationFunction, Conv, DynActivationFunction, Embedding, MlpError, Pool, PoolKind,
+    Preprocessor, SequenceConfig, Shape, TopologyError,
+    activation_functions::Identity,
+    core::{
+        DerivBuffer, ParamBuffer, ResultBuffer, forward_unchecked, loss_unchecked, param_buffer,
+        reset_state, result_buffer, sequence_loss_unchecked,
+    },
+    error::{check_ids, check_input, check_sample_ids, check_samples, check_sequence_ids},
+    preprocess::{preprocess_samples, preprocess_sequences},
 };
 
 #[derive(Debug, Clone)]
@@ -15,6 +22,7 @@ pub struct Topology {
 }
 
 impl Topology {
+    /// Does not validate the topology, see `try_new` and `builder` for that.
     pub fn new(n_inputs: usize, layer_descriptions: Vec<LayerDescription>) -> Self {
         Self {
             n_inputs,
@@ -22,6 +30,206 @@ impl Topology {
         }
     }
 
+    pub fn try_new(
+        n_inputs: usize,
+        layer_descriptions: Vec<LayerDescription>,
+    ) -> Result<Self, TopologyError> {
+        let topology = Self::new(n_inputs, layer_descriptions);
+        topology.validate()?;
+        Ok(topology)
+    }
+
+    pub fn builder() -> TopologyBuilder {
+        TopologyBuilder::default()
+    }
+
+    /// Checks that buffers can be created from this topology.
+    pub fn validate(&self) -> Result<(), TopologyError> {
+        if self.n_inputs == 0 {
+            return Err(TopologyError::NoInputs);
+        }
+        if self.layer_descriptions.is_empty() {
+            return Err(TopologyError::NoLayers);
+        }
+        for (index, layer_description) in self.layer_descriptions.iter().enumerate() {
+            self.validate_kind(index)?;
+            if layer_description.n_neurons == 0 {
+                return Err(TopologyError::EmptyLayer { index });
+            }
+            let Some(skip) = layer_description.skip else {
+                continue;
+            };
+            let from = skip.from();
+            if from >= index {
+                return Err(TopologyError::SkipForward { index, from });
+            }
+            let n_from = self.layer_descriptions[from].n_neurons;
+            if matches!(skip, Skip::Add { .. }) && n_from != layer_description.n_neurons {
+                return Err(TopologyError::SkipSize { index, from });
+            }
+        }
+        // Buffers cannot be empty.
+        match self.n_params() != 0 {
+            true => Ok(()),
+            false => Err(TopologyError::NoParams),
+        }
+    }
+
+    /// Checks that a convolution, pooling or embedding layer fits its input and output.
+    pub(crate) fn validate_kind(&self, index: usize) -> Result<(), TopologyError> {
+        let layer_description = &self.layer_descriptions[index];
+        let (n_inputs, n_outputs) = match layer_description.kind {
+            LayerKind::Dense => return Ok(()),
+            LayerKind::Recurrent => {
+                return match layer_description.skip {
+                    Some(Skip::Concat { .. }) => Err(TopologyError::SpatialConcat { index }),
+                    _ => Ok(()),
+                };
+            }
+            LayerKind::Conv(conv) => (conv.input.size(), conv.output().map(|s| s.size())),
+            LayerKind::Pool(pool) => (pool.input.size(), pool.output().map(|s| s.size())),
+            LayerKind::Embedding(embedding) => {
+                if index != 0 {
+                    return Err(TopologyError::Embedding { index });
+                }
+                (embedding.n_inputs(), Some(embedding.n_outputs()))
+            }
+        };
+        if let Some(Skip::Concat { .. }) = layer_description.skip {
+            return Err(TopologyError::SpatialConcat { index });
+        }
+        let n_previous = self.layer_n_inputs(index);
+        if n_inputs != n_previous {
+            return Err(TopologyError::InputShape {
+                index,
+                expected: n_inputs,
+                found: n_previous,
+            });
+        }
+        let n_outputs = n_outputs.ok_or(TopologyError::Window { index })?;
+        match n_outputs == layer_description.n_neurons {
+            true => Ok(()),
+            false => Err(TopologyError::OutputShape {
+                index,
+                expected: n_outputs,
+                found: layer_description.n_neurons,
+            }),
+        }
+    }
+
+    /// Number of inputs of layer `index`, which is the number of columns of its `w` if it is dense.
+    ///
+    /// That is the number of neurons in the previous layer (or the number of inputs for the first
+    /// layer), plus the neurons of the concatenated layer if it has a `Skip::Concat`.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn layer_n_inputs(&self, index: usize) -> usize {
+        let n_previous = match index.checked_sub(1) {
+            None => self.n_inputs,
+            Some(u_prev) => self.layer_descriptions[u_prev].n_neurons,
+        };
+        match self.layer_descriptions[index].skip {
+            Some(Skip::Concat { from }) => n_previous + self.layer_descriptions[from].n_neurons,
+            _ => n_previous,
+        }
+    }
+
+    /// Shape of `w` of layer `index`.
+    ///
+    /// That is `n_neurons` by `layer_n_inputs` for dense layers, with `n_neurons` more columns for
+    /// recurrent layers, see `Conv::w_shape` for convolution layers, empty for pooling layers, and
+    /// `dim` by `n_categories` for embedding layers.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn layer_w_shape(&self, index: usize) -> (usize, usize) {
+        match self.layer_descriptions[index].kind {
+            LayerKind::Dense => (
+                self.layer_descriptions[index].n_neurons,
+                self.layer_n_inputs(index),
+            ),
+            LayerKind::Recurrent => {
+                let n = self.layer_descriptions[index].n_neurons;
+                (n, self.layer_n_inputs(index) + n)
+            }
+            LayerKind::Conv(conv) => conv.w_shape(),
+            LayerKind::Pool(_) => (0, 0),
+            LayerKind::Embedding(embedding) => (embedding.dim, embedding.n_categories),
+        }
+    }
+
+    /// Number of biases of layer `index`, `0` if it has none, see `LayerDescription::bias`.
+    ///
+    /// Convolution layers have one per output channel, pooling and embedding layers have none.
+    ///
+    /// # Panics
+    ///
+    /// - if `index` is out of range
+    pub fn layer_n_biases(&self, index: usize) -> usize {
+        let layer_description = &self.layer_descriptions[index];
+        if !layer_description.bias {
+            return 0;
+        }
+        match layer_description.kind {
+            LayerKind::Dense | LayerKind::Recurrent => layer_description.n_neurons,
+            LayerKind::Conv(conv) => conv.out_channels,
+            LayerKind::Pool(_) | LayerKind::Embedding(_) => 0,
+        }
+    }
+
+    /// The first layer if it is an embedding layer.
+    pub(crate) fn embedding(&self) -> Option<Embedding> {
+        match self.layer_descriptions.first()?.kind {
+            LayerKind::Embedding(embedding) => Some(embedding),
+            _ => None,
+        }
+    }
+
+    /// Whether any layer has a skip connection.
+    pub fn has_skips(&self) -> bool {
+        self.layer_descriptions.iter().any(|l| l.skip.is_some())
+    }
+
+    /// Whether all layers are dense, i.e. there is no convolution, pooling, embedding or recurrent
+    /// layer.
+    pub fn is_dense(&self) -> bool {
+        self.layer_descriptions
+            .iter()
+            .all(|l| l.kind == LayerKind::Dense)
+    }
+
+    /// Number of weights and biases, i.e. the length of `ParamBuffer::as_slice`.
+    pub fn n_params(&self) -> usize {
+        let mut n_params = 0usize;
+        for index in 0..self.layer_descriptions.len() {
+            let (n_rows, n_cols) = self.layer_w_shape(index);
+            n_params += n_rows * n_cols; // w
+            n_params += self.layer_n_biases(index); // b
+        }
+        n_params
+    }
+
+    /// Number of neurons across all layers.
+    pub fn n_neurons(&self) -> usize {
+        self.layer_descriptions.iter().map(|l| l.n_neurons).sum()
+    }
+
+    /// Sizes of the buffers created from this topology.
+    pub fn memory_requirements(&self) -> MemoryRequirements {
+        let float_size = size_of::<f32>();
+        MemoryRequirements {
+            // w, b
+            params: self.n_params() * float_size,
+            // z, a
+            results: 2 * self.n_neurons() * float_size,
+            // dw, db, da
+            derivs: (self.n_params() + self.n_neurons()) * float_size,
+        }
+    }
+
     pub fn n_inputs(&self) -> usize {
         self.n_inputs
     }
@@ -41,10 +249,198 @@ impl Topology {
     }
 }
 
+/// Sizes in bytes of the buffers for a topology, see `Topology::memory_requirements`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRequirements {
+    /// Size of a `ParamBuffer`.
+    pub params: usize,
+    /// Size of a `ResultBuffer`, needed once per thread running the network.
+    pub results: usize,
+    /// Size of a `DerivBuffer`, needed once per thread training the network.
+    pub derivs: usize,
+}
+
+impl MemoryRequirements {
+    /// Memory needed for single-threaded training.
+    pub fn training(&self) -> usize {
+        self.params + self.results + self.derivs
+    }
+
+    /// Memory needed for single-threaded inference.
+    pub fn inference(&self) -> usize {
+        self.params + self.results
+    }
+}
+
+/// Builds a `Topology` layer by layer, validating it at the end.
+///
+/// Convolution and pooling layers read the output of the previous layer with the shape it was
+/// built with. The output of a dense layer, or of `flatten`, is read as a single channel 1D
+/// signal.
+///
+/// ```
+/// # use mlp::{PoolKind, Shape, Topology, activation_functions::*};
+/// let topology = Topology::builder()
+///     .input(784)
+///     .dense(64, Relu)
+///     .dense(10, Sigmoid)
+///     .build()
+///     .unwrap();
+/// assert_eq!(topology.n_params(), 784 * 64 + 64 + 64 * 10 + 10);
+///
+/// let topology = Topology::builder()
+///     .input_shape(Shape::new(1, 28, 28))
+///     .conv2d(8, (3, 3), (1, 1), (1, 1), Relu)
+///     .pool2d(PoolKind::Max, (2, 2))
+///     .flatten()
+///     .dense(10, Sigmoid)
+///     .build()
+///     .unwrap();
+/// assert_eq!(topology.n_params(), 8 * 9 + 8 + 8 * 14 * 14 * 10 + 10);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TopologyBuilder {
+    n_inputs: usize,
+    layer_descriptions: Vec<LayerDescription>,
+    /// Shape of the output of the last layer, `None` if it is flat.
+    shape: Option<Shape>,
+}
+
+impl TopologyBuilder {
+    pub fn input(mut self, n_inputs: usize) -> Self {
+        self.n_inputs = n_inputs;
+        self.shape = None;
+        self
+    }
+
+    /// Input of `shape.size()` values, read with `shape` by a following convolution or pooling
+    /// layer.
+    pub fn input_shape(mut self, shape: Shape) -> Self {
+        self.n_inputs = shape.size();
+        self.shape = Some(shape);
+        self
+    }
+
+    /// Shape the next layer reads its input with.
+    fn current_shape(&self) -> Shape {
+        let n_previous = self
+            .layer_descriptions
+            .last()
+            .map_or(self.n_inputs, |l| l.n_neurons);
+        self.shape.unwrap_or(Shape::new(1, 1, n_previous))
+    }
+
+    /// Appends a fully connected layer.
+    pub fn dense(self, n_neurons: usize, phi: impl ActivationFunction) -> Self {
+        self.layer(LayerDescription::new(n_neurons, phi))
+    }
+
+    /// Appends a fully connected layer without bias and activation function, i.e. a linear
+    /// projection.
+    pub fn linear(self, n_neurons: usize) -> Self {
+        self.layer(LayerDescription::new(n_neurons, Identity).without_bias())
+    }
+
+    /// Appends a recurrent layer, see `LayerKind::Recurrent`.
+    pub fn recurrent(self, n_neurons: usize, phi: impl ActivationFunction) -> Self {
+        self.layer(LayerDescription::recurrent(n_neurons, phi))
+    }
+
+    /// Appends a fully connected layer whose output is added to that of layer `from`, see
+    /// `Skip::Add`.
+    pub fn residual(self, n_neurons: usize, phi: impl ActivationFunction, from: usize) -> Self {
+        self.layer(LayerDescription::new(n_neurons, phi).with_skip(Skip::Add { from }))
+    }
+
+    /// Appends an embedding layer for the first `n_ids` inputs, with the rest of the inputs
+    /// concatenated after the embeddings, see `Embedding`. It must be the first layer.
+    pub fn embedding(self, n_ids: usize, n_categories: usize, dim: usize) -> Self {
+        let embedding = Embedding {
+            n_ids,
+            n_numeric: self.n_inputs.saturating_sub(n_ids),
+            n_categories,
+            dim,
+        };
+        self.layer(LayerDescription::embedding(embedding))
+    }
+
+    /// Appends a 1D convolution layer, see `Conv`.
+    pub fn conv1d(
+        self,
+        out_channels: usize,
+        kernel: usize,
+        stride: usize,
+        padding: usize,
+        phi: impl ActivationFunction,
+    ) -> Self {
+        self.conv2d(out_channels, (1, kernel), (1, stride), (0, padding), phi)
+    }
+
+    /// Appends a 2D convolution layer, see `Conv`.
+    pub fn conv2d(
+        self,
+        out_channels: usize,
+        kernel: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+        phi: impl ActivationFunction,
+    ) -> Self {
+        let conv = Conv {
+            input: self.current_shape(),
+            out_channels,
+            kernel,
+            stride,
+            padding,
+        };
+        self.layer(LayerDescription::conv(conv, phi))
+    }
+
+    /// Appends a 1D pooling layer over non-overlapping windows of `size` values.
+    pub fn pool1d(self, kind: PoolKind, size: usize) -> Self {
+        self.pool2d(kind, (1, size))
+    }
+
+    /// Appends a 2D pooling layer over non-overlapping windows of `size` values.
+    pub fn pool2d(self, kind: PoolKind, size: (usize, usize)) -> Self {
+        let pool = Pool {
+            input: self.current_shape(),
+            kind,
+            size,
+            stride: size,
+        };
+        self.layer(LayerDescription::pool(pool))
+    }
+
+    /// Makes the next layer read the output of the last one as a flat signal. Activations are
+    /// always stored flat, so no layer is added.
+    pub fn flatten(mut self) -> Self {
+        self.shape = None;
+        self
+    }
+
+    pub fn layer(mut self, layer_description: LayerDescription) -> Self {
+        self.shape = match layer_description.kind {
+            LayerKind::Dense | LayerKind::Recurrent | LayerKind::Embedding(_) => None,
+            LayerKind::Conv(conv) => conv.output(),
+            LayerKind::Pool(pool) => pool.output(),
+        };
+        self.layer_descriptions.push(layer_description);
+        self
+    }
+
+    pub fn build(self) -> Result<Topology, TopologyError> {
+        Topology::try_new(self.n_inputs, self.layer_descriptions)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LayerDescription {
     pub n_neurons: usize,
     pub phi: DynActivationFunction,
+    pub skip: Option<Skip>,
+    /// Whether the layer has a bias, `b` is empty if not.
+    pub bias: bool,
+    pub kind: LayerKind,
 }
 
 impl LayerDescription {
@@ -52,23 +448,120 @@ impl LayerDescription {
         Self {
             n_neurons,
             phi: DynActivationFunction::new(phi),
+            skip: None,
+            bias: true,
+            kind: LayerKind::Dense,
+        }
+    }
+
+    /// Convolution layer with as many neurons as its output has values, or none if the kernel
+    /// does not fit its input.
+    pub fn conv(conv: Conv, phi: impl ActivationFunction) -> Self {
+        let n_neurons = conv.output().map_or(0, |output| output.size());
+        Self {
+            kind: LayerKind::Conv(conv),
+            ..Self::new(n_neurons, phi)
+        }
+    }
+
+    pub fn recurrent(n_neurons: usize, phi: impl ActivationFunction) -> Self {
+        Self {
+            kind: LayerKind::Recurrent,
+            ..Self::new(n_neurons, phi)
+        }
+    }
+
+    /// Embedding layer without activation function.
+    pub fn embedding(embedding: Embedding) -> Self {
+        Self {
+            kind: LayerKind::Embedding(embedding),
+            ..Self::new(embedding.n_outputs(), Identity)
+        }
+    }
+
+    /// Pooling layer without activation function, see `LayerDescription::conv`.
+    pub fn pool(pool: Pool) -> Self {
+        let n_neurons = pool.output().map_or(0, |output| output.size());
+        Self {
+            kind: LayerKind::Pool(pool),
+            ..Self::new(n_neurons, Identity)
+        }
+    }
+
+    pub fn without_bias(mut self) -> Self {
+        self.bias = false;
+        self
+    }
+
+    pub fn with_skip(mut self, skip: Skip) -> Self {
+        self.skip = Some(skip);
+        self
+    }
+}
+
+/// A connection from the output of an earlier layer, on top of the one from the previous layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    /// Residual connection, `a = phi(z) + a_from`. Layer `from` must have as many neurons as this
+    /// one.
+    Add { from: usize },
+    /// The output of layer `from` is appended to the input, `z = W * [a_prev; a_from] + b`.
+    Concat { from: usize },
+}
+
+impl Skip {
+    /// Index of the layer the connection comes from.
+    pub fn from(self) -> usize {
+        match self {
+            Skip::Add { from } | Skip::Concat { from } => from,
         }
     }
 }
 
+/// What a layer computes `z` from its input with, see `LayerDescription::kind`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LayerKind {
+    /// Fully connected, `z = W * a_prev + b`.
+    #[default]
+    Dense,
+    Conv(Conv),
+    Pool(Pool),
+    Embedding(Embedding),
+    /// Elman recurrent layer, `h_t = phi(W * [a_prev; h_{t-1}] + b)`, where `h_{t-1}` is the
+    /// output of the layer at the previous step of a sequence.
+    ///
+    /// The state is kept in the layer's `a` of the `ResultBuffer`, so `forward_unchecked` carries
+    /// it over from the previous call until `reset_state`. Training on single samples starts each
+    /// sample from a zero state, see `calculate_sequence_derivs` for training on sequences.
+    Recurrent,
+}
+
 pub struct NeuralNetwork {
     topology: Topology,
     params: ParamBuffer,
     results: ResultBuffer,
+    /// Scratch buffer of gradients by the input, created on first use.
+    derivs: Option<DerivBuffer>,
+    preprocessor: Option<Preprocessor>,
+    /// Output of `forward` scaled back by the preprocessor.
+    raw_output: Col<f32>,
 }
 
 impl NeuralNetwork {
+    /// # Panics
+    ///
+    /// - if `topology` is invalid, see `try_new`
     pub fn new(topology: Topology) -> Self {
+        Self::try_new(topology).unwrap()
+    }
+
+    pub fn try_new(topology: Topology) -> Result<Self, MlpError> {
+        topology.validate()?;
         let params = ParamBuffer::create(&topology);
         let results = ResultBuffer::create(&topology);
         // Safety: params and results are of the same topology as they are created from the same
         // n_inputs and layer_descriptions.
-        unsafe { Self::from_raw_parts(topology, params, results) }
+        Ok(unsafe { Self::from_raw_parts(topology, params, results) })
     }
 
     /// # Safety
@@ -83,6 +576,9 @@ impl NeuralNetwork {
             topology,
             params,
             results,
+            derivs: None,
+            preprocessor: None,
+            raw_output: Col::zeros(0),
         }
     }
 
@@ -98,25 +594,102 @@ impl NeuralNetwork {
         self.topology().n_outputs()
     }
 
-    pub fn forward(&mut self, input: ColRef<f32>) -> ColRef<'_, f32> {
-        // Safety: params and results are created from the same topology.
+    /// With a preprocessor, `input` is a raw one and the output is scaled back to the units of the
+    /// raw targets, see `set_preprocessor`.
+    pub fn forward(&mut self, input: ColRef<f32>) -> Result<ColRef<'_, f32>, MlpError> {
+        let Some(preprocessor) = &self.preprocessor else {
+            check_input(input, self.n_inputs())?;
+            check_ids(input, self.topology())?;
+            // Safety: input size is checked above.
+            return Ok(unsafe { self.forward_unchecked(input) });
+        };
+        let input = preprocessor.transform_input(input)?;
+        check_ids(input.as_ref(), &self.topology)?;
+        // Safety: params and results are created from the same topology, the preprocessor is
+        // checked to encode inputs of the network's size by `set_preprocessor`.
+        unsafe { forward_unchecked(input.as_ref(), &self.params, &mut self.results) };
+        let a = self.results.layer(self.results.n_layers() - 1).unwrap().a;
+        self.raw_output.copy_from(a);
+        preprocessor.invert_in_place(self.raw_output.as_mut());
+        Ok(self.raw_output.as_ref())
+    }
+
+    /// Does not apply the preprocessor, `input` must be encoded already.
+    ///
+    /// # Safety
+    ///
+    /// - `input` must have `n_inputs` rows
+    pub unsafe fn forward_unchecked(&mut self, input: ColRef<f32>) -> ColRef<'_, f32> {
+        // Safety: params and results are created from the same topology, input size is guaranteed
+        // by the function's safety contract.
         unsafe { forward_unchecked(input, &self.params, &mut self.results) };
         self.results.layer(self.results.n_layers() - 1).unwrap().a
     }
 
-    pub fn loss(&mut self, samples: &[f32]) -> f32 {
-        let mut loss = 0.0f32;
-        let n_inputs = self.n_inputs();
-        let n_outputs = self.n_outputs();
-        for sample in samples.chunks(n_inputs + n_outputs) {
-            let x = ColRef::from_slice(&sample[0..n_inputs]);
-            let y = ColRef::from_slice(&sample[n_inputs..n_inputs + n_outputs]);
-            let a = self.forward(x);
-            loss += iter::zip(a.iter(), y.iter())
-                .map(|(&ak, &yk)| (ak - yk).powi(2))
-                .sum::<f32>();
+    /// Preprocessing applied to raw inputs and targets, saved along with the params by `save` and
+    /// carried over into an `InferenceModel` and a `Gym`.
+    ///
+    /// With a preprocessor, `forward`, `predict`, `predict_batch`, `loss`, `sequence_loss` and the
+    /// training methods of `Gym` take raw inputs and targets and encode them, and outputs are
+    /// scaled back to the units of the raw targets. Losses are of the scaled targets, as the
+    /// network is trained on them. The unchecked methods, the results buffer, gradients and
+    /// Jacobians are of the network itself, i.e. of encoded inputs and scaled outputs.
+    pub fn set_preprocessor(&mut self, preprocessor: Option<Preprocessor>) -> Result<(), MlpError> {
+        if let Some(preprocessor) = &preprocessor {
+            preprocessor.check_topology(self.topology())?;
         }
-        loss
+        self.raw_output = Col::zeros(self.n_outputs());
+        self.preprocessor = preprocessor;
+        Ok(())
+    }
+
+    pub fn preprocessor(&self) -> Option<&Preprocessor> {
+        self.preprocessor.as_ref()
+    }
+
+    /// Returns the summed squared error over `samples`, of the scaled targets with a
+    /// preprocessor, see `set_preprocessor`.
+    pub fn loss(&mut self, samples: &[f32]) -> Result<f32, MlpError> {
+        let samples = preprocess_samples(self.preprocessor(), samples)?;
+        check_samples(&samples, self.n_inputs() + self.n_outputs())?;
+        check_sample_ids(&samples, self.topology())?;
+        // Safety: sample sizes are checked above.
+        Ok(unsafe { self.loss_unchecked(&samples) })
+    }
+
+    /// Does not apply the preprocessor, `samples` must be preprocessed already.
+    ///
+    /// # Safety
+    ///
+    /// - `samples.len()` must be a multiple of `n_inputs + n_outputs`
+    pub unsafe fn loss_unchecked(&mut self, samples: &[f32]) -> f32 {
+        // Safety: params and results are created from the same topology, sample sizes are
+        // guaranteed by the function's safety contract.
+        unsafe { loss_unchecked(&self.params, &mut self.results, samples) }
+    }
+
+    /// Zeroes the state of the recurrent layers, so that the next `forward` starts a new sequence.
+    /// `forward` carries the state over from the previous call otherwise.
+    pub fn reset_state(&mut self) {
+        reset_state(&self.params, &mut self.results);
+    }
+
+    /// Summed squared error over the targets of `samples`, which are sequences laid out according
+    /// to `config`.
+    pub fn sequence_loss(
+        &mut self,
+        samples: &[f32],
+        config: &SequenceConfig,
+    ) -> Result<f32, MlpError> {
+        let samples = preprocess_sequences(self.preprocessor.as_ref(), samples, config)?;
+        check_samples(
+            &samples,
+            config.sample_size(self.n_inputs(), self.n_outputs()),
+        )?;
+        check_sequence_ids(&samples, config, self.topology())?;
+        // Safety: params and results are created from the same topology, sample sizes are checked
+        // above.
+        Ok(unsafe { sequence_loss_unchecked(&self.params, &mut self.results, &samples, config) })
     }
 
     pub fn topology(&self) -> &Topology {
@@ -127,6 +700,14 @@ impl NeuralNetwork {
         &self.params
     }
 
+    /// Params, results and a deriv buffer of this network's topology, all at once.
+    pub(crate) fn buffers_mut(&mut self) -> (&ParamBuffer, &mut ResultBuffer, &mut DerivBuffer) {
+        let derivs = self
+            .derivs
+            .get_or_insert_with(|| DerivBuffer::create(&self.topology));
+        (&self.params, &mut self.results, derivs)
+    }
+
     /// # Safety
     ///
     /// Topology of `params` must not be changed.
@@ -150,6 +731,35 @@ impl NeuralNetwork {
         unsafe { self.params_unchecked_mut().randomize(range) };
     }
 
+    /// See `ParamBuffer::set_frozen`.
+    pub fn set_frozen(&mut self, index: usize, frozen: bool) {
+        // Safety: freezing does not change the topology.
+        unsafe { self.params_unchecked_mut().set_frozen(index, frozen) };
+    }
+
+    pub fn is_frozen(&self, index: usize) -> bool {
+        self.params().is_frozen(index)
+    }
+
+    /// Freezes all layers except the last `n_trainable` ones, which are unfrozen.
+    pub fn freeze_all_but_last(&mut self, n_trainable: usize) {
+        let n_layers = self.topology().n_layers();
+        for index in 0..n_layers {
+            self.set_frozen(index, index + n_trainable < n_layers);
+        }
+    }
+
+    /// See `ParamBuffer::set_lr_multiplier`.
+    pub fn set_lr_multiplier(&mut self, index: usize, lr_multiplier: f32) {
+        // Safety: learning rate multipliers do not change the topology.
+        let params = unsafe { self.params_unchecked_mut() };
+        params.set_lr_multiplier(index, lr_multiplier);
+    }
+
+    pub fn lr_multiplier(&self, index: usize) -> f32 {
+        self.params().lr_multiplier(index)
+    }
+
     pub fn params_layer(&self, index: usize) -> Option<param_buffer::LayerRef<'_>> {
         self.params().layer(index)
     }
@@ -0,0 +1,492 @@
+use std::{borrow::Cow, io};
+
+use faer::prelude::*;
+
+use crate::{
+    MlpError, SequenceConfig, SequenceTargets, Topology,
+    error::{check_input, check_output, check_samples},
+    serialize::{Decoder, Encoder, invalid_data},
+};
+
+/// How a raw input column is encoded for the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Passed on as it is.
+    Passthrough,
+    /// Shifted and scaled to a mean of `0` and a standard deviation of `1`.
+    Standardize,
+    /// Shifted and scaled so that the fitted range becomes `[0, 1]`.
+    MinMax,
+    /// Expanded into one input per distinct value seen while fitting, the one of the value being
+    /// `1` and the rest `0`. Values not seen while fitting are all `0`.
+    OneHot,
+}
+
+/// How missing (NaN) values of a raw input column are filled in, before encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Imputation {
+    /// Left as NaN, which a one-hot column encodes as all `0`.
+    None,
+    Mean,
+    Median,
+    /// The most frequent value, the smallest of them on a tie.
+    MostFrequent,
+    Constant(f32),
+}
+
+/// How a raw input column is preprocessed, see `Preprocessor::fit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnSpec {
+    pub encoding: Encoding,
+    pub imputation: Imputation,
+}
+
+impl ColumnSpec {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            imputation: Imputation::None,
+        }
+    }
+
+    pub fn with_imputation(mut self, imputation: Imputation) -> Self {
+        self.imputation = imputation;
+        self
+    }
+}
+
+/// How a regression output is scaled, the network being trained on scaled targets and its outputs
+/// scaled back by `Preprocessor::inverse_output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    None,
+    Standardize,
+    MinMax,
+}
+
+/// Input and output transforms fitted on a training set, so that serving applies exactly the
+/// preprocessing the network was trained with. See `NeuralNetwork::set_preprocessor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preprocessor {
+    inputs: Vec<Column>,
+    outputs: Vec<Affine>,
+}
+
+/// A fitted raw input column.
+#[derive(Debug, Clone, PartialEq)]
+struct Column {
+    /// Value NaNs are replaced with, if imputed.
+    fill: Option<f32>,
+    encoding: FittedEncoding,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FittedEncoding {
+    Affine(Affine),
+    /// Distinct values in increasing order.
+    OneHot(Vec<f32>),
+}
+
+/// `x -> (x - offset) / scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Affine {
+    offset: f32,
+    scale: f32,
+}
+
+impl Affine {
+    const IDENTITY: Self = Self {
+        offset: 0.0,
+        scale: 1.0,
+    };
+
+    /// Fits to the values that are not NaN. A constant or empty column is only shifted.
+    fn fit(scaling: Scaling, values: &[f32]) -> Self {
+        let (offset, scale) = match scaling {
+            Scaling::None => return Self::IDENTITY,
+            Scaling::Standardize => {
+                let mean = mean(values);
+                let variance = values
+                    .iter()
+                    .map(|&x| (x as f64 - mean as f64).powi(2))
+                    .sum::<f64>()
+                    / values.len().max(1) as f64;
+                (mean, variance.sqrt() as f32)
+            }
+            Scaling::MinMax => {
+                let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+                let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                match values.is_empty() {
+                    true => (0.0, 1.0),
+                    false => (min, max - min),
+                }
+            }
+        };
+        Self {
+            offset,
+            scale: match scale > 0.0 {
+                true => scale,
+                false => 1.0,
+            },
+        }
+    }
+
+    fn apply(&self, x: f32) -> f32 {
+        (x - self.offset) / self.scale
+    }
+
+    fn invert(&self, x: f32) -> f32 {
+        x * self.scale + self.offset
+    }
+}
+
+impl Preprocessor {
+    /// Fits the transforms on `samples`, laid out as for training with a raw input column per
+    /// entry of `inputs` and a raw target per entry of `outputs`.
+    ///
+    /// NaN values are left out of every statistic, so targets that are missing (see
+    /// `mask_targets`) do not skew the output scaling.
+    pub fn fit(
+        samples: &[f32],
+        inputs: &[ColumnSpec],
+        outputs: &[Scaling],
+    ) -> Result<Self, MlpError> {
+        let n_raw_inputs = inputs.len();
+        let sample_size = n_raw_inputs + outputs.len();
+        check_samples(samples, sample_size)?;
+        let known = |j: usize| -> Vec<f32> {
+            samples
+                .chunks(sample_size)
+                .map(|sample| sample[j])
+                .filter(|x| !x.is_nan())
+                .collect()
+        };
+        let inputs = inputs
+            .iter()
+            .enumerate()
+            .map(|(j, spec)| {
+                let mut values = known(j);
+                values.sort_by(f32::total_cmp);
+                let fill = match spec.imputation {
+                    Imputation::None => None,
+                    Imputation::Mean => Some(mean(&values)),
+                    Imputation::Median => Some(median(&values)),
+                    Imputation::MostFrequent => Some(most_frequent(&values)),
+                    Imputation::Constant(x) => Some(x),
+                };
+                // Imputed values are part of the column the encoding sees.
+                let n_missing = samples.len() / sample_size - values.len();
+                if let Some(fill) = fill {
+                    values.extend(std::iter::repeat_n(fill, n_missing));
+                    values.sort_by(f32::total_cmp);
+                }
+                let encoding = match spec.encoding {
+                    Encoding::Passthrough => FittedEncoding::Affine(Affine::IDENTITY),
+                    Encoding::Standardize => {
+                        FittedEncoding::Affine(Affine::fit(Scaling::Standardize, &values))
+                    }
+                    Encoding::MinMax => {
+                        FittedEncoding::Affine(Affine::fit(Scaling::MinMax, &values))
+                    }
+                    Encoding::OneHot => {
+                        values.dedup();
+                        FittedEncoding::OneHot(values)
+                    }
+                };
+                Column { fill, encoding }
+            })
+            .collect();
+        let outputs = outputs
+            .iter()
+            .enumerate()
+            .map(|(k, &scaling)| Affine::fit(scaling, &known(n_raw_inputs + k)))
+            .collect();
+        Ok(Self { inputs, outputs })
+    }
+
+    /// Number of raw input columns.
+    pub fn n_raw_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Number of encoded inputs, i.e. the `n_inputs` of the network.
+    pub fn n_inputs(&self) -> usize {
+        self.inputs
+            .iter()
+            .map(|column| match &column.encoding {
+                FittedEncoding::Affine(_) => 1,
+                FittedEncoding::OneHot(categories) => categories.len(),
+            })
+            .sum()
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Checks that the network of `topology` reads the encoded inputs and outputs the scaled
+    /// targets.
+    pub(crate) fn check_topology(&self, topology: &Topology) -> Result<(), MlpError> {
+        let found = (self.n_inputs(), self.n_outputs());
+        let expected = (topology.n_inputs(), topology.n_outputs());
+        match found == expected {
+            true => Ok(()),
+            false => Err(MlpError::Preprocessor { expected, found }),
+        }
+    }
+
+    /// Encodes a raw input of `n_raw_inputs` rows into one of `n_inputs` rows.
+    pub fn transform_input(&self, raw: ColRef<f32>) -> Result<Col<f32>, MlpError> {
+        check_input(raw, self.n_raw_inputs())?;
+        let mut input = vec![0.0; self.n_inputs()];
+        self.encode(raw.iter().copied(), &mut input);
+        Ok(Col::from_fn(input.len(), |i| input[i]))
+    }
+
+    /// Encodes the inputs and scales the targets of raw `samples`, laid out as for `fit`, into
+    /// samples to train the network on. Missing targets stay NaN.
+    pub fn transform_samples(&self, samples: &[f32]) -> Result<Vec<f32>, MlpError> {
+        let raw_size = self.n_raw_inputs() + self.n_outputs();
+        check_samples(samples, raw_size)?;
+        let sample_size = self.n_inputs() + self.n_outputs();
+        let mut transformed = vec![0.0; samples.len() / raw_size * sample_size];
+        for (raw, sample) in samples
+            .chunks(raw_size)
+            .zip(transformed.chunks_mut(sample_size))
+        {
+            let (x, y) = raw.split_at(self.n_raw_inputs());
+            let (input, target) = sample.split_at_mut(self.n_inputs());
+            self.encode(x.iter().copied(), input);
+            self.scale_target(y, target);
+        }
+        Ok(transformed)
+    }
+
+    /// Same as `transform_samples` for sequences laid out according to `config`, of raw inputs
+    /// and targets.
+    pub fn transform_sequences(
+        &self,
+        samples: &[f32],
+        config: &SequenceConfig,
+    ) -> Result<Vec<f32>, MlpError> {
+        let raw_size = config.sample_size(self.n_raw_inputs(), self.n_outputs());
+        check_samples(samples, raw_size)?;
+        let (n_raw_inputs, n_inputs) = (self.n_raw_inputs(), self.n_inputs());
+        let sample_size = config.sample_size(n_inputs, self.n_outputs());
+        let mut transformed = vec![0.0; samples.len() / raw_size * sample_size];
+        for (raw, sequence) in samples
+            .chunks(raw_size)
+            .zip(transformed.chunks_mut(sample_size))
+        {
+            match config.targets {
+                // The same layout as samples, a step at a time.
+                SequenceTargets::EveryStep => {
+                    let step_size = n_inputs + self.n_outputs();
+                    let raw_step_size = n_raw_inputs + self.n_outputs();
+                    for (raw, step) in raw
+                        .chunks(raw_step_size)
+                        .zip(sequence.chunks_mut(step_size))
+                    {
+                        let (x, y) = raw.split_at(n_raw_inputs);
+                        let (input, target) = step.split_at_mut(n_inputs);
+                        self.encode(x.iter().copied(), input);
+                        self.scale_target(y, target);
+                    }
+                }
+                SequenceTargets::LastStep => {
+                    let (xs, y) = raw.split_at(config.len * n_raw_inputs);
+                    let (inputs, target) = sequence.split_at_mut(config.len * n_inputs);
+                    for (x, input) in xs.chunks(n_raw_inputs).zip(inputs.chunks_mut(n_inputs)) {
+                        self.encode(x.iter().copied(), input);
+                    }
+                    self.scale_target(y, target);
+                }
+            }
+        }
+        Ok(transformed)
+    }
+
+    /// Scales an output of the network back to the units of the raw targets.
+    pub fn inverse_output(&self, output: ColRef<f32>) -> Result<Col<f32>, MlpError> {
+        check_output(output, self.n_outputs())?;
+        let mut raw = output.to_owned();
+        self.invert_in_place(raw.as_mut());
+        Ok(raw)
+    }
+
+    /// `inverse_output` in place, `output` must be of `n_outputs` rows.
+    pub(crate) fn invert_in_place(&self, output: ColMut<f32>) {
+        for (a, affine) in output.iter_mut().zip(&self.outputs) {
+            *a = affine.invert(*a);
+        }
+    }
+
+    /// Scales the derivatives of a loss by the raw outputs into derivatives by the outputs of the
+    /// network, `da_outputs` must be of `n_outputs` rows.
+    pub(crate) fn scale_output_derivs(&self, da_outputs: MatRef<f32>) -> Mat<f32> {
+        Mat::from_fn(da_outputs.nrows(), da_outputs.ncols(), |k, i| {
+            da_outputs[(k, i)] * self.outputs[k].scale
+        })
+    }
+
+    /// Encodes every column of `inputs`, which must be of `n_raw_inputs` rows.
+    pub(crate) fn transform_batch(&self, inputs: MatRef<f32>) -> Mat<f32> {
+        let mut transformed = Mat::zeros(self.n_inputs(), inputs.ncols());
+        let mut input = vec![0.0; self.n_inputs()];
+        for j in 0..inputs.ncols() {
+            self.encode(inputs.col(j).iter().copied(), &mut input);
+            transformed.col_mut(j).copy_from(ColRef::from_slice(&input));
+        }
+        transformed
+    }
+
+    /// Scales the raw targets `y` into `target`.
+    fn scale_target(&self, y: &[f32], target: &mut [f32]) {
+        for ((target, &y), affine) in target.iter_mut().zip(y).zip(&self.outputs) {
+            *target = affine.apply(y);
+        }
+    }
+
+    /// Encodes the `n_raw_inputs` values of `raw` into `input`, of `n_inputs` values.
+    fn encode(&self, raw: impl Iterator<Item = f32>, input: &mut [f32]) {
+        let mut i = 0;
+        for (column, x) in self.inputs.iter().zip(raw) {
+            let x = match (x.is_nan(), column.fill) {
+                (true, Some(fill)) => fill,
+                _ => x,
+            };
+            match &column.encoding {
+                FittedEncoding::Affine(affine) => {
+                    input[i] = affine.apply(x);
+                    i += 1;
+                }
+                FittedEncoding::OneHot(categories) => {
+                    let one_hot = &mut input[i..i + categories.len()];
+                    one_hot.fill(0.0);
+                    if let Ok(c) = categories.binary_search_by(|c| c.total_cmp(&x)) {
+                        one_hot[c] = 1.0;
+                    }
+                    i += categories.len();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.usize(self.inputs.len());
+        for column in &self.inputs {
+            encoder.option_f32(column.fill);
+            match &column.encoding {
+                FittedEncoding::Affine(affine) => {
+                    encoder.u8(0);
+                    encode_affine(encoder, affine);
+                }
+                FittedEncoding::OneHot(categories) => {
+                    encoder.u8(1);
+                    encoder.f32_slice(categories);
+                }
+            }
+        }
+        encoder.usize(self.outputs.len());
+        for affine in &self.outputs {
+            encode_affine(encoder, affine);
+        }
+    }
+
+    pub(crate) fn decode_from(decoder: &mut Decoder) -> io::Result<Self> {
+        let n_raw_inputs = decoder.usize()?;
+        let mut inputs = Vec::new();
+        for _ in 0..n_raw_inputs {
+            let fill = decoder.option_f32()?;
+            let encoding = match decoder.u8()? {
+                0 => FittedEncoding::Affine(decode_affine(decoder)?),
+                1 => {
+                    let categories = decoder.f32_vec()?;
+                    // `encode` binary searches them.
+                    let is_sorted = categories.windows(2).all(|pair| pair[0] < pair[1]);
+                    if !is_sorted || categories.iter().any(|c| c.is_nan()) {
+                        return Err(invalid_data("invalid one-hot categories"));
+                    }
+                    FittedEncoding::OneHot(categories)
+                }
+                _ => return Err(invalid_data("invalid input encoding")),
+            };
+            inputs.push(Column { fill, encoding });
+        }
+        let n_outputs = decoder.usize()?;
+        let mut outputs = Vec::new();
+        for _ in 0..n_outputs {
+            outputs.push(decode_affine(decoder)?);
+        }
+        Ok(Self { inputs, outputs })
+    }
+}
+
+/// `samples` as the network is trained on, i.e. transformed by `preprocessor` if there is one and
+/// borrowed as they are otherwise.
+pub(crate) fn preprocess_samples<'s>(
+    preprocessor: Option<&Preprocessor>,
+    samples: &'s [f32],
+) -> Result<Cow<'s, [f32]>, MlpError> {
+    match preprocessor {
+        Some(preprocessor) => Ok(Cow::Owned(preprocessor.transform_samples(samples)?)),
+        None => Ok(Cow::Borrowed(samples)),
+    }
+}
+
+/// Same as `preprocess_samples` for sequences laid out according to `config`.
+pub(crate) fn preprocess_sequences<'s>(
+    preprocessor: Option<&Preprocessor>,
+    samples: &'s [f32],
+    config: &SequenceConfig,
+) -> Result<Cow<'s, [f32]>, MlpError> {
+    match preprocessor {
+        Some(preprocessor) => Ok(Cow::Owned(
+            preprocessor.transform_sequences(samples, config)?,
+        )),
+        None => Ok(Cow::Borrowed(samples)),
+    }
+}
+
+fn encode_affine(encoder: &mut Encoder, affine: &Affine) {
+    encoder.f32(affine.offset);
+    encoder.f32(affine.scale);
+}
+
+fn decode_affine(decoder: &mut Decoder) -> io::Result<Affine> {
+    let affine = Affine {
+        offset: decoder.f32()?,
+        scale: decoder.f32()?,
+    };
+    // As `Affine::fit` makes them, which `apply` can divide by.
+    match affine.offset.is_finite() && affine.scale.is_finite() && affine.scale > 0.0 {
+        true => Ok(affine),
+        false => Err(invalid_data("invalid scaling")),
+    }
+}
+
+/// `0` if there are no values.
+fn mean(values: &[f32]) -> f32 {
+    (values.iter().map(|&x| x as f64).sum::<f64>() / values.len().max(1) as f64) as f32
+}
+
+/// Of sorted `values`, `0` if there are none.
+fn median(values: &[f32]) -> f32 {
+    let n = values.len();
+    match n {
+        0 => 0.0,
+        _ if n % 2 == 1 => values[n / 2],
+        _ => (values[n / 2 - 1] + values[n / 2]) / 2.0,
+    }
+}
+
+/// Of sorted `values`, `0` if there are none.
+fn most_frequent(values: &[f32]) -> f32 {
+    values
+        .chunk_by(|a, b| a == b)
+        .fold((0.0, 0), |(best, n_best), run| match run.len() > n_best {
+            true => (run[0], run.len()),
+            false => (best, n_best),
+        })
+        .0
+}
@@ -32,7 +32,10 @@ impl Display for PrettyPrintParams<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let w = self.layer.w.rb();
         let b = self.layer.b.rb();
-        let center_line = self.layer.n / 2;
+        let has_bias = b.nrows() != 0;
+        // One line per row of `w`, which is one per output channel for convolution layers.
+        let n_lines = w.nrows();
+        let center_line = n_lines / 2;
         let phi = self.layer.phi.name();
         let i_layer = self.i_layer;
         let i_layer_length = n_digits(i_layer);
@@ -40,7 +43,7 @@ impl Display for PrettyPrintParams<'_> {
             None => 1, // "x"
             Some(i_previous) => n_digits(i_previous),
         };
-        for i_line in 0..self.layer.n {
+        for i_line in 0..n_lines {
             if i_line == center_line {
                 write!(f, "a_{i_layer} = {phi}(")?;
             } else {
@@ -64,30 +67,36 @@ impl Display for PrettyPrintParams<'_> {
             write!(f, "]")?;
             if i_line == center_line {
                 match i_layer.checked_sub(1) {
-                    None => write!(f, " x + ")?,
-                    Some(i_previous) => write!(f, " a_{} + ", i_previous)?,
+                    None => write!(f, " x")?,
+                    Some(i_previous) => write!(f, " a_{}", i_previous)?,
                 }
-            } else {
-                write!(f, "    ")?;
-                for _ in 0..i_previous_layer_length {
+            }
+            // Layers without bias end here.
+            if has_bias {
+                if i_line == center_line {
+                    write!(f, " + ")?;
+                } else {
+                    write!(f, "    ")?;
+                    for _ in 0..i_previous_layer_length {
+                        write!(f, " ")?;
+                    }
+                }
+                write!(f, "[")?;
+                let b_element = b.get(i_line);
+                if b_element.is_sign_positive() {
+                    write!(f, " {:.04?}", b_element)?;
+                } else {
+                    write!(f, "{:.04?}", b_element)?;
+                }
+                if iter.size_hint().0 != 0 {
                     write!(f, " ")?;
                 }
+                write!(f, "]")?;
             }
-            write!(f, "[")?;
-            let b_element = b.get(i_line);
-            if b_element.is_sign_positive() {
-                write!(f, " {:.04?}", b_element)?;
-            } else {
-                write!(f, "{:.04?}", b_element)?;
-            }
-            if iter.size_hint().0 != 0 {
-                write!(f, " ")?;
-            }
-            write!(f, "]")?;
             if i_line == center_line {
                 write!(f, ")")?;
             }
-            if i_line != self.layer.n - 1 {
+            if i_line + 1 != n_lines {
                 writeln!(f)?;
             }
         }
@@ -116,14 +125,16 @@ impl Display for PrettyPrintDerivs<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let dw = self.layer.dw.rb();
         let db = self.layer.db.rb();
-        let center_line = self.layer.n / 2;
+        let has_bias = db.nrows() != 0;
+        let n_lines = dw.nrows();
+        let center_line = n_lines / 2;
         let i_layer = self.i_layer;
         let i_layer_length = n_digits(i_layer);
         let i_previous_layer_length = match i_layer.checked_sub(1) {
             None => 1, // "x"
             Some(i_previous) => n_digits(i_previous),
         };
-        for i_line in 0..self.layer.n {
+        for i_line in 0..n_lines {
             if i_line == center_line {
                 write!(f, "a_{i_layer} = phi(")?;
             } else {
@@ -147,30 +158,36 @@ impl Display for PrettyPrintDerivs<'_> {
             write!(f, "]")?;
             if i_line == center_line {
                 match i_layer.checked_sub(1) {
-                    None => write!(f, " x + ")?,
-                    Some(i_previous) => write!(f, " a_{} + ", i_previous)?,
+                    None => write!(f, " x")?,
+                    Some(i_previous) => write!(f, " a_{}", i_previous)?,
                 }
-            } else {
-                write!(f, "    ")?;
-                for _ in 0..i_previous_layer_length {
+            }
+            // Layers without bias end here.
+            if has_bias {
+                if i_line == center_line {
+                    write!(f, " + ")?;
+                } else {
+                    write!(f, "    ")?;
+                    for _ in 0..i_previous_layer_length {
+                        write!(f, " ")?;
+                    }
+                }
+                write!(f, "[")?;
+                let b_element = db.get(i_line);
+                if b_element.is_sign_positive() {
+                    write!(f, " {:.012?}", b_element)?;
+                } else {
+                    write!(f, "{:.012?}", b_element)?;
+                }
+                if iter.size_hint().0 != 0 {
                     write!(f, " ")?;
                 }
+                write!(f, "]")?;
             }
-            write!(f, "[")?;
-            let b_element = db.get(i_line);
-            if b_element.is_sign_positive() {
-                write!(f, " {:.012?}", b_element)?;
-            } else {
-                write!(f, "{:.012?}", b_element)?;
-            }
-            if iter.size_hint().0 != 0 {
-                write!(f, " ")?;
-            }
-            write!(f, "]")?;
             if i_line == center_line {
                 write!(f, ")")?;
             }
-            if i_line != self.layer.n - 1 {
+            if i_line + 1 != n_lines {
                 writeln!(f)?;
             }
         }
@@ -0,0 +1,149 @@
+use crate::{
+    MlpError, NeuralNetwork, Topology,
+    core::ParamBuffer,
+    surgery::{LayerSource, check_surgery, rebuild},
+};
+
+/// Which weights `prune_by_magnitude` ranks against each other.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PruningScope {
+    /// Every layer is pruned to the target sparsity on its own.
+    #[default]
+    PerLayer,
+    /// Weights of all layers are ranked together, so layers end up with different sparsities.
+    Global,
+}
+
+impl ParamBuffer {
+    /// Prunes the weights of smallest magnitude, so that a `sparsity` fraction of the weights are
+    /// pruned. Biases are never pruned.
+    ///
+    /// Pruned weights are recorded in the mask (see `set_mask`), which keeps them at zero during
+    /// fine-tuning. Already pruned weights stay pruned, even if `sparsity` is lower than the
+    /// current sparsity.
+    ///
+    /// Returns the number of pruned weights.
+    ///
+    /// # Panics
+    ///
+    /// - if `sparsity` is not within `0.0..=1.0`
+    pub fn prune_by_magnitude(&mut self, sparsity: f32, scope: PruningScope) -> usize {
+        assert!((0.0..=1.0).contains(&sparsity));
+        let mut mask = match self.mask() {
+            Some(mask) => Box::from(mask),
+            None => vec![true; self.as_slice().len()].into_boxed_slice(),
+        };
+        let w_ranges: Vec<_> = (0..self.n_layers())
+            .map(|u| self.layer_ranges(u).0)
+            .collect();
+        let groups = match scope {
+            PruningScope::PerLayer => w_ranges.into_iter().map(|range| vec![range]).collect(),
+            PruningScope::Global => vec![w_ranges],
+        };
+        let params = self.as_slice();
+        for ranges in groups {
+            let mut indices: Vec<usize> = ranges.into_iter().flatten().collect();
+            let n_pruned = (sparsity * indices.len() as f32).round() as usize;
+            // Pruned weights sort first, as their magnitude is zero.
+            indices.sort_unstable_by(|&i, &j| {
+                let magnitude = |i: usize| match mask[i] {
+                    true => params[i].abs(),
+                    false => -1.0,
+                };
+                magnitude(i).total_cmp(&magnitude(j))
+            });
+            for &i in &indices[..n_pruned] {
+                mask[i] = false;
+            }
+        }
+        let n_pruned = mask.iter().filter(|&&keep| !keep).count();
+        self.set_mask(Some(mask));
+        n_pruned
+    }
+
+    /// Fraction of the weights that are pruned.
+    pub fn sparsity(&self) -> f32 {
+        let Some(mask) = self.mask() else {
+            return 0.0;
+        };
+        let (mut n_weights, mut n_pruned) = (0usize, 0usize);
+        for u in 0..self.n_layers() {
+            let w_range = self.layer_ranges(u).0;
+            n_weights += w_range.len();
+            n_pruned += mask[w_range].iter().filter(|&&keep| !keep).count();
+        }
+        n_pruned as f32 / n_weights as f32
+    }
+}
+
+impl NeuralNetwork {
+    /// See `ParamBuffer::prune_by_magnitude`.
+    pub fn prune_by_magnitude(&mut self, sparsity: f32, scope: PruningScope) -> usize {
+        // Safety: pruning does not change the topology.
+        unsafe { self.params_unchecked_mut() }.prune_by_magnitude(sparsity, scope)
+    }
+
+    /// Returns a smaller network with `neurons` of layer `index` removed, along with their
+    /// incoming weights and biases, and the matching columns of the next layer's weights.
+    ///
+    /// The mask of pruned weights, frozen layers and learning rate multipliers are carried over.
+    pub fn remove_neurons(&self, index: usize, neurons: &[usize]) -> Result<Self, MlpError> {
+        check_surgery(self.topology())?;
+        let topology = self.topology();
+        let n_layers = topology.n_layers();
+        if index >= n_layers {
+            return Err(MlpError::LayerIndex { index, n_layers });
+        }
+        if index + 1 == n_layers {
+            return Err(MlpError::OutputLayer);
+        }
+        let n_neurons = topology.layer_descriptions()[index].n_neurons;
+        let mut keep = vec![true; n_neurons];
+        for &k in neurons {
+            if k >= n_neurons {
+                return Err(MlpError::NeuronIndex {
+                    index: k,
+                    n_neurons,
+                });
+            }
+            keep[k] = false;
+        }
+        let kept: Vec<usize> = (0..n_neurons).filter(|&k| keep[k]).collect();
+        let mut layer_descriptions = topology.layer_descriptions().to_vec();
+        layer_descriptions[index].n_neurons = kept.len();
+        let topology = Topology::try_new(topology.n_inputs(), layer_descriptions)?;
+        let params = self.params();
+        let sources: Vec<_> = (0..n_layers)
+            .map(|u| {
+                let mut source = LayerSource::identity(params, u);
+                if u == index {
+                    source.rows = kept.iter().map(|&k| Some(k)).collect();
+                }
+                if u == index + 1 {
+                    source.cols = kept.iter().map(|&k| Some((k, 1.0))).collect();
+                }
+                Some(source)
+            })
+            .collect();
+        rebuild(topology, &sources)
+    }
+
+    /// Removes the `n_removed` neurons of layer `index` whose outgoing weights (the matching
+    /// columns of the next layer's weights) have the smallest L2 norm, see `remove_neurons`.
+    pub fn prune_neurons(&self, index: usize, n_removed: usize) -> Result<Self, MlpError> {
+        check_surgery(self.topology())?;
+        let n_layers = self.topology().n_layers();
+        if index >= n_layers {
+            return Err(MlpError::LayerIndex { index, n_layers });
+        }
+        if index + 1 == n_layers {
+            return Err(MlpError::OutputLayer);
+        }
+        let w_next = self.params_layer(index + 1).unwrap().w;
+        let norm = |g: usize| w_next.col(g).iter().map(|w| w * w).sum::<f32>();
+        let mut neurons: Vec<usize> = (0..w_next.ncols()).collect();
+        neurons.sort_unstable_by(|&g, &h| norm(g).total_cmp(&norm(h)));
+        neurons.truncate(n_removed);
+        self.remove_neurons(index, &neurons)
+    }
+}
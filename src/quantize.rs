@@ -0,0 +1,273 @@
+use std::iter;
+
+use faer::prelude::*;
+
+use crate::{
+    DynActivationFunction, LayerKind, MlpError, NeuralNetwork, Predictions, Skip,
+    core::ParamBuffer,
+    error::{check_input, check_samples},
+};
+
+/// A layer with int8 weights.
+#[derive(Debug, Clone)]
+pub struct QuantizedLayer {
+    /// Number of neurons in this layer.
+    pub n: usize,
+    /// Number of inputs, see `Topology::layer_n_inputs`.
+    pub n_previous: usize,
+    /// Quantized weights, row-major `n * n_previous`.
+    pub w: Box<[i8]>,
+    /// Scale of each row of `w`, i.e. `W[(k, g)] ≈ w[k * n_previous + g] * w_scales[k]`.
+    pub w_scales: Box<[f32]>,
+    /// Biases are kept in `f32`, they are a tiny part of the parameters. Empty if the layer has no
+    /// bias.
+    pub b: Box<[f32]>,
+    pub phi: DynActivationFunction,
+    pub skip: Option<Skip>,
+}
+
+/// A network with int8 weights and int64 accumulation, for post-training quantization.
+///
+/// Weights are quantized symmetrically with one scale per neuron. Activations are quantized to
+/// int8 on the fly before each layer, with one scale per activation vector.
+#[derive(Debug, Clone)]
+pub struct QuantizedModel {
+    n_inputs: usize,
+    layers: Box<[QuantizedLayer]>,
+}
+
+/// Scratch memory for `QuantizedModel::forward`.
+#[derive(Debug, Clone)]
+pub struct QuantizedScratch {
+    /// Input of the current layer.
+    input: Vec<f32>,
+    /// Output of every layer, kept around for skip connections.
+    a: Box<[Vec<f32>]>,
+    x: Vec<i8>,
+}
+
+/// Symmetric int8 quantization of `xs`, returns the scale.
+fn quantize_into(xs: &[f32], out: &mut [i8]) -> f32 {
+    let max_abs = xs.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    let scale = match max_abs {
+        0.0 => 1.0,
+        max_abs => max_abs / 127.0,
+    };
+    for (q, &x) in iter::zip(out, xs) {
+        *q = (x / scale).round().clamp(-127.0, 127.0) as i8;
+    }
+    scale
+}
+
+impl QuantizedModel {
+    /// Only networks of dense layers can be quantized, see `Topology::is_dense`.
+    pub fn quantize(params: &ParamBuffer) -> Result<Self, MlpError> {
+        if (0..params.n_layers()).any(|u| params.kind(u) != LayerKind::Dense) {
+            return Err(MlpError::NotDense);
+        }
+        let layers = (0..params.n_layers())
+            .map(|u| {
+                let layer = params.layer(u).unwrap();
+                let (n, n_previous) = (layer.n, layer.n_previous);
+                let mut w = vec![0i8; n * n_previous].into_boxed_slice();
+                let mut row = vec![0.0; n_previous];
+                let w_scales = (0..n)
+                    .map(|k| {
+                        // Rows of `W` are strided, copy them out first.
+                        for (x, &w) in iter::zip(&mut row, layer.w.row(k).iter()) {
+                            *x = w;
+                        }
+                        quantize_into(&row, &mut w[k * n_previous..(k + 1) * n_previous])
+                    })
+                    .collect();
+                QuantizedLayer {
+                    n,
+                    n_previous,
+                    w,
+                    w_scales,
+                    b: layer.b.iter().copied().collect(),
+                    phi: layer.phi,
+                    skip: params.skip(u),
+                }
+            })
+            .collect::<Box<[QuantizedLayer]>>();
+        Ok(Self {
+            n_inputs: layers[0].n_previous,
+            layers,
+        })
+    }
+
+    pub fn n_inputs(&self) -> usize {
+        self.n_inputs
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.layers.last().unwrap().n
+    }
+
+    pub fn layers(&self) -> &[QuantizedLayer] {
+        &self.layers
+    }
+
+    /// Size of the weights, scales and biases in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| {
+                layer.w.len() * size_of::<i8>()
+                    + (layer.w_scales.len() + layer.b.len()) * size_of::<f32>()
+            })
+            .sum()
+    }
+
+    pub fn create_scratch(&self) -> QuantizedScratch {
+        let max_n_inputs = self
+            .layers
+            .iter()
+            .map(|layer| layer.n_previous)
+            .max()
+            .unwrap();
+        QuantizedScratch {
+            input: Vec::with_capacity(max_n_inputs),
+            a: self.layers.iter().map(|layer| vec![0.0; layer.n]).collect(),
+            x: vec![0; max_n_inputs],
+        }
+    }
+
+    /// # Panics
+    ///
+    /// - if `scratch` is not created from this model
+    pub fn forward<'s>(
+        &self,
+        input: ColRef<f32>,
+        scratch: &'s mut QuantizedScratch,
+    ) -> Result<&'s [f32], MlpError> {
+        check_input(input, self.n_inputs)?;
+        let QuantizedScratch {
+            input: layer_input,
+            a,
+            x,
+        } = scratch;
+        for (u, layer) in self.layers.iter().enumerate() {
+            let (a_before, a_after) = a.split_at_mut(u);
+            layer_input.clear();
+            match a_before.last() {
+                None => layer_input.extend(input.iter()),
+                Some(a_prev) => layer_input.extend_from_slice(a_prev),
+            }
+            if let Some(Skip::Concat { from }) = layer.skip {
+                layer_input.extend_from_slice(&a_before[from]);
+            }
+            let n_g = layer.n_previous;
+            let x = &mut x[..n_g];
+            let x_scale = quantize_into(layer_input, x);
+            let a = &mut a_after[0];
+            let w_rows = layer.w.chunks_exact(n_g);
+            for (k, (w_row, a)) in iter::zip(w_rows, a.iter_mut()).enumerate() {
+                // Products are up to `127^2`, so `i32` would overflow past about 133k inputs.
+                let accumulator: i64 = iter::zip(w_row, x.iter())
+                    .map(|(&w, &x)| (w as i64) * (x as i64))
+                    .sum();
+                let b = layer.b.get(k).copied().unwrap_or(0.0);
+                let z = (accumulator as f32) * layer.w_scales[k] * x_scale + b;
+                *a = layer.phi.apply(z);
+            }
+            if let Some(Skip::Add { from }) = layer.skip {
+                for (a, &a_from) in iter::zip(a.iter_mut(), &a_before[from]) {
+                    *a += a_from;
+                }
+            }
+        }
+        Ok(a.last().unwrap())
+    }
+
+    /// Runs every sample in `samples` through the quantized model and collects the outputs.
+    pub fn predict(&self, samples: &[f32]) -> Result<Predictions, MlpError> {
+        let n_inputs = self.n_inputs;
+        let n_outputs = self.n_outputs();
+        check_samples(samples, n_inputs + n_outputs)?;
+        let mut scratch = self.create_scratch();
+        let mut outputs = Vec::new();
+        let mut targets = Vec::new();
+        for sample in samples.chunks(n_inputs + n_outputs) {
+            let a = self.forward(ColRef::from_slice(&sample[..n_inputs]), &mut scratch)?;
+            outputs.extend_from_slice(a);
+            targets.extend_from_slice(&sample[n_inputs..]);
+        }
+        Ok(Predictions::new(n_outputs, outputs, targets))
+    }
+}
+
+impl TryFrom<&NeuralNetwork> for QuantizedModel {
+    type Error = MlpError;
+
+    fn try_from(nn: &NeuralNetwork) -> Result<Self, MlpError> {
+        Self::quantize(nn.params())
+    }
+}
+
+/// Comparison of a quantized model against the original `f32` model, see `quantization_report`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationReport {
+    /// Mean absolute difference between the outputs of the two models.
+    pub mean_abs_output_delta: f32,
+    /// Largest absolute difference between the outputs of the two models.
+    pub max_abs_output_delta: f32,
+    /// Mean loss per sample of the `f32` model.
+    pub loss: f32,
+    /// Mean loss per sample of the quantized model.
+    pub quantized_loss: f32,
+    /// Classification accuracy of the `f32` model, see `Predictions::accuracy`.
+    pub accuracy: f32,
+    /// Classification accuracy of the quantized model.
+    pub quantized_accuracy: f32,
+    /// Size of the `f32` parameters in bytes.
+    pub size_in_bytes: usize,
+    /// Size of the quantized parameters in bytes.
+    pub quantized_size_in_bytes: usize,
+}
+
+impl QuantizationReport {
+    /// Change in accuracy from quantization, negative if the quantized model is worse.
+    pub fn accuracy_delta(&self) -> f32 {
+        self.quantized_accuracy - self.accuracy
+    }
+
+    /// Size of the `f32` model divided by the size of the quantized one.
+    pub fn compression_ratio(&self) -> f32 {
+        self.size_in_bytes as f32 / self.quantized_size_in_bytes as f32
+    }
+}
+
+/// Compares `quantized` against the network it was quantized from over the calibration set
+/// `samples`.
+pub fn quantization_report(
+    nn: &mut NeuralNetwork,
+    quantized: &QuantizedModel,
+    samples: &[f32],
+) -> Result<QuantizationReport, MlpError> {
+    let predictions = nn.predict(samples)?;
+    let quantized_predictions = quantized.predict(samples)?;
+    let n_outputs = predictions.n_outputs();
+    let mut sum_abs_delta = 0.0f64;
+    let mut max_abs_delta = 0.0f32;
+    for i in 0..predictions.n_samples() {
+        for (&a, &a_quantized) in iter::zip(predictions.output(i), quantized_predictions.output(i))
+        {
+            let abs_delta = (a - a_quantized).abs();
+            sum_abs_delta += abs_delta as f64;
+            max_abs_delta = max_abs_delta.max(abs_delta);
+        }
+    }
+    Ok(QuantizationReport {
+        mean_abs_output_delta: (sum_abs_delta / (predictions.n_samples() * n_outputs) as f64)
+            as f32,
+        max_abs_output_delta: max_abs_delta,
+        loss: predictions.mean_loss(),
+        quantized_loss: quantized_predictions.mean_loss(),
+        accuracy: predictions.accuracy(),
+        quantized_accuracy: quantized_predictions.accuracy(),
+        size_in_bytes: size_of_val(nn.params_as_slice()),
+        quantized_size_in_bytes: quantized.size_in_bytes(),
+    })
+}
@@ -0,0 +1,109 @@
+use faer::prelude::*;
+
+/// Which steps of a sequence have a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceTargets {
+    /// A target after every step, each sequence is laid out as `[x_0, y_0, x_1, y_1, ..]`.
+    EveryStep,
+    /// A target after the last step only, each sequence is laid out as `[x_0, x_1, .., y]`.
+    LastStep,
+}
+
+/// How samples are grouped into sequences, for networks with recurrent layers.
+///
+/// Every sequence starts from a zero state, and the state is carried from one step to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceConfig {
+    /// Number of steps of each sequence.
+    pub len: usize,
+    pub targets: SequenceTargets,
+    /// Back propagates through at most this many steps, the state is still carried over across
+    /// the cut. `None` back propagates through the whole sequence.
+    pub truncation: Option<usize>,
+}
+
+impl SequenceConfig {
+    /// # Panics
+    ///
+    /// - if `len` is `0`
+    pub fn new(len: usize, targets: SequenceTargets) -> Self {
+        assert!(len != 0);
+        Self {
+            len,
+            targets,
+            truncation: None,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// - if `truncation` is `0`
+    pub fn with_truncation(self, truncation: usize) -> Self {
+        assert!(truncation != 0);
+        Self {
+            truncation: Some(truncation),
+            ..self
+        }
+    }
+
+    /// Number of floats of a sequence.
+    pub fn sample_size(&self, n_inputs: usize, n_outputs: usize) -> usize {
+        match self.targets {
+            SequenceTargets::EveryStep => self.len * (n_inputs + n_outputs),
+            SequenceTargets::LastStep => self.len * n_inputs + n_outputs,
+        }
+    }
+
+    /// Number of steps back propagated through at once.
+    pub(crate) fn chunk_len(&self) -> usize {
+        self.truncation.unwrap_or(self.len).min(self.len)
+    }
+
+    /// Input and target (if any) of step `t` of `sequence`.
+    ///
+    /// # Panics
+    ///
+    /// - if `sequence` is not of `sample_size`, or `t` is out of range
+    pub(crate) fn step<'a>(
+        &self,
+        sequence: &'a [f32],
+        t: usize,
+        n_inputs: usize,
+        n_outputs: usize,
+    ) -> (ColRef<'a, f32>, Option<ColRef<'a, f32>>) {
+        assert!(t < self.len);
+        match self.targets {
+            SequenceTargets::EveryStep => {
+                let step = &sequence[t * (n_inputs + n_outputs)..][..n_inputs + n_outputs];
+                let (x, y) = step.split_at(n_inputs);
+                (ColRef::from_slice(x), Some(ColRef::from_slice(y)))
+            }
+            SequenceTargets::LastStep => {
+                let x = &sequence[t * n_inputs..][..n_inputs];
+                let y = (t == self.len - 1)
+                    .then(|| ColRef::from_slice(&sequence[self.len * n_inputs..][..n_outputs]));
+                (ColRef::from_slice(x), y)
+            }
+        }
+    }
+}
+
+/// Cuts a time series into windows of `len` steps for forecasting the step right after each
+/// window, laid out for `SequenceTargets::LastStep`.
+///
+/// `series` has `n_features` values per step, which are both the inputs and the targets. Empty if
+/// `series` is not longer than `len` steps.
+///
+/// # Panics
+///
+/// - if `series.len()` is not a multiple of `n_features`
+pub fn forecasting_windows(series: &[f32], n_features: usize, len: usize) -> Vec<f32> {
+    assert!(series.len().is_multiple_of(n_features));
+    let n_steps = series.len() / n_features;
+    let n_windows = n_steps.saturating_sub(len);
+    let mut samples = Vec::with_capacity(n_windows * (len + 1) * n_features);
+    for start in 0..n_windows {
+        samples.extend_from_slice(&series[start * n_features..(start + len + 1) * n_features]);
+    }
+    samples
+}
@@ -0,0 +1,171 @@
+//! Minimal little-endian binary encoding for the on-disk formats.
+
+use std::io;
+
+pub(crate) struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new(magic: &[u8; 8], version: u32) -> Self {
+        let mut encoder = Self { bytes: Vec::new() };
+        encoder.bytes.extend_from_slice(magic);
+        encoder.u32(version);
+        encoder
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub(crate) fn u8(&mut self, x: u8) {
+        self.bytes.push(x);
+    }
+
+    pub(crate) fn bool(&mut self, x: bool) {
+        self.u8(x as u8);
+    }
+
+    pub(crate) fn u32(&mut self, x: u32) {
+        self.bytes.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub(crate) fn usize(&mut self, x: usize) {
+        self.bytes.extend_from_slice(&(x as u64).to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, x: f32) {
+        self.bytes.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub(crate) fn option_f32(&mut self, x: Option<f32>) {
+        self.bool(x.is_some());
+        self.f32(x.unwrap_or(0.0));
+    }
+
+    pub(crate) fn option_usize(&mut self, x: Option<usize>) {
+        self.bool(x.is_some());
+        self.usize(x.unwrap_or(0));
+    }
+
+    /// Length-prefixed.
+    pub(crate) fn f32_slice(&mut self, xs: &[f32]) {
+        self.usize(xs.len());
+        for &x in xs {
+            self.f32(x);
+        }
+    }
+
+    /// Length-prefixed.
+    pub(crate) fn bool_slice(&mut self, xs: &[bool]) {
+        self.usize(xs.len());
+        for &x in xs {
+            self.bool(x);
+        }
+    }
+
+    /// Length-prefixed.
+    pub(crate) fn str(&mut self, s: &str) {
+        self.usize(s.len());
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Checks the magic bytes, returns the decoder and the format version.
+    pub(crate) fn new(bytes: &'a [u8], magic: &[u8; 8]) -> io::Result<(Self, u32)> {
+        let mut decoder = Self { bytes };
+        if decoder.take(8)? != magic {
+            return Err(invalid_data("unrecognized file format"));
+        }
+        let version = decoder.u32()?;
+        Ok((decoder, version))
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file is truncated",
+            ));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(invalid_data("trailing bytes at the end of file")),
+        }
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid boolean")),
+        }
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn usize(&mut self) -> io::Result<usize> {
+        let x = u64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        usize::try_from(x).map_err(|_| invalid_data("integer out of range"))
+    }
+
+    pub(crate) fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn option_f32(&mut self) -> io::Result<Option<f32>> {
+        let is_some = self.bool()?;
+        let x = self.f32()?;
+        Ok(is_some.then_some(x))
+    }
+
+    pub(crate) fn option_usize(&mut self) -> io::Result<Option<usize>> {
+        let is_some = self.bool()?;
+        let x = self.usize()?;
+        Ok(is_some.then_some(x))
+    }
+
+    pub(crate) fn f32_vec(&mut self) -> io::Result<Vec<f32>> {
+        let len = self.usize()?;
+        let n_bytes = len
+            .checked_mul(4)
+            .ok_or_else(|| invalid_data("length overflow"))?;
+        let bytes = self.take(n_bytes)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    pub(crate) fn bool_vec(&mut self) -> io::Result<Vec<bool>> {
+        let len = self.usize()?;
+        (0..len).map(|_| self.bool()).collect()
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data("invalid UTF-8"))
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
@@ -0,0 +1,377 @@
+use std::cmp::Ordering;
+
+use rand::{Rng, distr::uniform::SampleRange, rngs::ThreadRng};
+
+use crate::{
+    ActivationFunction, DynActivationFunction, LayerDescription, MlpError, NeuralNetwork, Topology,
+    core::ParamBuffer,
+};
+
+/// Operations that change the shape of a network, carrying the existing weights over into a new
+/// one.
+///
+/// The mask of pruned weights, frozen layers and learning rate multipliers are carried over along
+/// with the weights.
+impl NeuralNetwork {
+    /// Returns a network with `layer_description` inserted at `index`, whose params are drawn
+    /// uniformly from `range`, as by `randomize_params`.
+    ///
+    /// The layer after it keeps its weights if its number of inputs does not change, otherwise
+    /// only its biases are kept and its weights are drawn from `range` too.
+    pub fn insert_layer(
+        &self,
+        index: usize,
+        layer_description: LayerDescription,
+        range: impl SampleRange<f32> + Clone,
+    ) -> Result<Self, MlpError> {
+        check_surgery(self.topology())?;
+        let n_layers = self.topology().n_layers();
+        if index > n_layers {
+            return Err(MlpError::LayerIndex { index, n_layers });
+        }
+        let mut layer_descriptions = self.topology().layer_descriptions().to_vec();
+        layer_descriptions.insert(index, layer_description);
+        let topology = Topology::try_new(self.n_inputs(), layer_descriptions)?;
+        let params = self.params();
+        let sources: Vec<_> = (0..n_layers + 1)
+            .map(|u| match u.cmp(&index) {
+                Ordering::Less => Some(LayerSource::identity(params, u)),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(LayerSource::keep_if_fits(
+                    params,
+                    u - 1,
+                    n_previous(&topology, u),
+                )),
+            })
+            .collect();
+        let mut nn = rebuild(topology, &sources)?;
+        randomize_new_params(&mut nn, &sources, range);
+        Ok(nn)
+    }
+
+    /// Returns a network with `layer_description` appended as the new output layer, see
+    /// `insert_layer`.
+    pub fn push_layer(
+        &self,
+        layer_description: LayerDescription,
+        range: impl SampleRange<f32> + Clone,
+    ) -> Result<Self, MlpError> {
+        self.insert_layer(self.topology().n_layers(), layer_description, range)
+    }
+
+    /// Returns a network without layer `index`.
+    ///
+    /// The layer after it keeps its weights if its number of inputs does not change, otherwise
+    /// only its biases are kept and its weights are drawn uniformly from `range`.
+    pub fn remove_layer(
+        &self,
+        index: usize,
+        range: impl SampleRange<f32> + Clone,
+    ) -> Result<Self, MlpError> {
+        check_surgery(self.topology())?;
+        let n_layers = self.topology().n_layers();
+        if index >= n_layers {
+            return Err(MlpError::LayerIndex { index, n_layers });
+        }
+        let mut layer_descriptions = self.topology().layer_descriptions().to_vec();
+        layer_descriptions.remove(index);
+        let topology = Topology::try_new(self.n_inputs(), layer_descriptions)?;
+        let params = self.params();
+        let sources: Vec<_> = (0..n_layers - 1)
+            .map(|u| match u < index {
+                true => Some(LayerSource::identity(params, u)),
+                false => Some(LayerSource::keep_if_fits(
+                    params,
+                    u + 1,
+                    n_previous(&topology, u),
+                )),
+            })
+            .collect();
+        let mut nn = rebuild(topology, &sources)?;
+        randomize_new_params(&mut nn, &sources, range);
+        Ok(nn)
+    }
+
+    /// Returns a network where layer `index` is widened to `n_neurons`, computing the same
+    /// function as this one (Net2Net's "wider" operation).
+    ///
+    /// New neurons are copies of existing ones, and the outgoing weights of every copied neuron
+    /// are split evenly between it and its copies. Since copies would otherwise stay identical
+    /// during training, uniform noise within `-noise..noise` is added to the incoming weights of
+    /// the new neurons, `0.0` for none.
+    pub fn widen_layer(
+        &self,
+        index: usize,
+        n_neurons: usize,
+        noise: f32,
+    ) -> Result<Self, MlpError> {
+        check_surgery(self.topology())?;
+        let n_layers = self.topology().n_layers();
+        if index >= n_layers {
+            return Err(MlpError::LayerIndex { index, n_layers });
+        }
+        if index + 1 == n_layers {
+            return Err(MlpError::OutputLayer);
+        }
+        let n_old = self.topology().layer_descriptions()[index].n_neurons;
+        if n_neurons < n_old {
+            return Err(MlpError::Narrowing {
+                index,
+                n_neurons,
+                found: n_old,
+            });
+        }
+        // New neurons copy the existing ones in turn.
+        let origins: Vec<usize> = (0..n_neurons).map(|k| k % n_old).collect();
+        let mut n_copies = vec![0usize; n_old];
+        for &origin in &origins {
+            n_copies[origin] += 1;
+        }
+        let mut layer_descriptions = self.topology().layer_descriptions().to_vec();
+        layer_descriptions[index].n_neurons = n_neurons;
+        let topology = Topology::try_new(self.n_inputs(), layer_descriptions)?;
+        let params = self.params();
+        let sources: Vec<_> = (0..n_layers)
+            .map(|u| {
+                let mut source = LayerSource::identity(params, u);
+                if u == index {
+                    source.rows = origins.iter().map(|&origin| Some(origin)).collect();
+                }
+                if u == index + 1 {
+                    source.cols = origins
+                        .iter()
+                        .map(|&origin| Some((origin, 1.0 / n_copies[origin] as f32)))
+                        .collect();
+                }
+                Some(source)
+            })
+            .collect();
+        let mut nn = rebuild(topology, &sources)?;
+        if noise > 0.0 {
+            let mut rng = ThreadRng::default();
+            // Safety: only params are changed, not topology.
+            let params = unsafe { nn.params_unchecked_mut() };
+            let mut layer = params.layer_mut(index).unwrap();
+            for k in n_old..n_neurons {
+                for g in 0..layer.n_previous {
+                    layer.w[(k, g)] += rng.random_range(-noise..noise);
+                }
+            }
+            params.apply_mask();
+        }
+        Ok(nn)
+    }
+
+    /// Returns a network where the activation function of layer `index` is replaced by `phi`.
+    pub fn with_activation(
+        &self,
+        index: usize,
+        phi: impl ActivationFunction,
+    ) -> Result<Self, MlpError> {
+        let n_layers = self.topology().n_layers();
+        if index >= n_layers {
+            return Err(MlpError::LayerIndex { index, n_layers });
+        }
+        let mut layer_descriptions = self.topology().layer_descriptions().to_vec();
+        layer_descriptions[index].phi = DynActivationFunction::new(phi);
+        let topology = Topology::try_new(self.n_inputs(), layer_descriptions)?;
+        let params = self.params();
+        let sources: Vec<_> = (0..n_layers)
+            .map(|u| Some(LayerSource::identity(params, u)))
+            .collect();
+        rebuild(topology, &sources)
+    }
+
+    /// Copies the weights and biases of layer `other_index` of `other` into layer `index`, which
+    /// must be of the same shape and kind, and either both or neither have a bias. The activation
+    /// function is not copied.
+    pub fn copy_layer_from(
+        &mut self,
+        index: usize,
+        other: &NeuralNetwork,
+        other_index: usize,
+    ) -> Result<(), MlpError> {
+        let source = other
+            .params_layer(other_index)
+            .ok_or(MlpError::LayerIndex {
+                index: other_index,
+                n_layers: other.topology().n_layers(),
+            })?;
+        let n_layers = self.topology().n_layers();
+        // Conv layers of different inputs, kernels or strides can have the same shape of params.
+        let kind = other.topology().layer_descriptions()[other_index].kind;
+        let layer_description = self.topology().layer_descriptions().get(index);
+        if layer_description.is_some_and(|layer_description| layer_description.kind != kind) {
+            return Err(MlpError::KindMismatch { index });
+        }
+        // Safety: only params are changed, not topology.
+        let params = unsafe { self.params_unchecked_mut() };
+        let mut layer = params
+            .layer_mut(index)
+            .ok_or(MlpError::LayerIndex { index, n_layers })?;
+        if (layer.n, layer.n_previous) != (source.n, source.n_previous)
+            || layer.w.shape() != source.w.shape()
+            || layer.b.nrows() != source.b.nrows()
+        {
+            return Err(MlpError::LayerShape {
+                index,
+                expected: (layer.n, layer.n_previous),
+                found: (source.n, source.n_previous),
+            });
+        }
+        layer.w.copy_from(source.w);
+        layer.b.copy_from(source.b);
+        params.apply_mask();
+        Ok(())
+    }
+}
+
+/// Where the params of a layer of a rebuilt network come from.
+pub(crate) struct LayerSource<'a> {
+    params: &'a ParamBuffer,
+    index: usize,
+    /// Source row of each row of `w` (neuron for dense layers), `None` for new neurons, whose
+    /// params are zero.
+    pub(crate) rows: Vec<Option<usize>>,
+    /// Source input of each input along with the factor its weights are scaled by, `None` for new
+    /// inputs, whose weights are zero.
+    pub(crate) cols: Vec<Option<(usize, f32)>>,
+}
+
+impl<'a> LayerSource<'a> {
+    /// Layer `index` of `params` as it is.
+    pub(crate) fn identity(params: &'a ParamBuffer, index: usize) -> Self {
+        let layer = params.layer(index).unwrap();
+        Self {
+            params,
+            index,
+            rows: (0..layer.w.nrows()).map(Some).collect(),
+            cols: (0..layer.w.ncols()).map(|g| Some((g, 1.0))).collect(),
+        }
+    }
+
+    /// Layer `index` of `params`, without its weights if it does not have `n_previous` inputs.
+    fn keep_if_fits(params: &'a ParamBuffer, index: usize, n_previous: usize) -> Self {
+        let mut source = Self::identity(params, index);
+        if source.cols.len() != n_previous {
+            source.cols = vec![None; n_previous];
+        }
+        source
+    }
+}
+
+/// Layers and neurons can only be inserted, removed or widened in networks of dense layers without
+/// skip connections.
+pub(crate) fn check_surgery(topology: &Topology) -> Result<(), MlpError> {
+    if topology.has_skips() {
+        return Err(MlpError::Skips);
+    }
+    match topology.is_dense() {
+        true => Ok(()),
+        false => Err(MlpError::NotDense),
+    }
+}
+
+/// Number of inputs of layer `index`.
+fn n_previous(topology: &Topology, index: usize) -> usize {
+    match index.checked_sub(1) {
+        None => topology.n_inputs(),
+        Some(u_prev) => topology.layer_descriptions()[u_prev].n_neurons,
+    }
+}
+
+/// Draws the params that `rebuild` left at zero for lack of a source uniformly from `range`, i.e.
+/// those of layers without a source, and of the rows and inputs their source has no counterpart
+/// for. Without it, new neurons would all be the same and stay so during training.
+fn randomize_new_params(
+    nn: &mut NeuralNetwork,
+    sources: &[Option<LayerSource>],
+    range: impl SampleRange<f32> + Clone,
+) {
+    let mut rng = ThreadRng::default();
+    // Safety: only params are changed, not topology.
+    let params = unsafe { nn.params_unchecked_mut() };
+    for (u, source) in sources.iter().enumerate() {
+        let mut layer = params.layer_mut(u).unwrap();
+        for k in 0..layer.w.nrows() {
+            let is_new = source
+                .as_ref()
+                .is_none_or(|source| source.rows[k].is_none());
+            for g in 0..layer.w.ncols() {
+                if is_new
+                    || source
+                        .as_ref()
+                        .is_some_and(|source| source.cols[g].is_none())
+                {
+                    layer.w[(k, g)] = rng.random_range(range.clone());
+                }
+            }
+            if is_new && layer.b.nrows() != 0 {
+                layer.b[k] = rng.random_range(range.clone());
+            }
+        }
+    }
+    params.apply_mask();
+}
+
+/// Creates a network of `topology`, copying the params of each layer from its source. Layers
+/// without a source have zero params.
+pub(crate) fn rebuild(
+    topology: Topology,
+    sources: &[Option<LayerSource>],
+) -> Result<NeuralNetwork, MlpError> {
+    let mut nn = NeuralNetwork::try_new(topology)?;
+    // Safety: only params, mask and training settings are changed, not topology.
+    let params = unsafe { nn.params_unchecked_mut() };
+    let has_mask = sources
+        .iter()
+        .flatten()
+        .any(|source| source.params.mask().is_some());
+    let mut mask = has_mask.then(|| vec![true; params.as_slice().len()].into_boxed_slice());
+    for (u, source) in sources.iter().enumerate() {
+        let Some(source) = source else {
+            continue;
+        };
+        let source_layer = source.params.layer(source.index).unwrap();
+        let mut layer = params.layer_mut(u).unwrap();
+        debug_assert!(source.rows.len() == layer.w.nrows());
+        debug_assert!(source.cols.len() == layer.w.ncols());
+        for (k, &row) in source.rows.iter().enumerate() {
+            let Some(row) = row else {
+                continue;
+            };
+            if layer.b.nrows() != 0 {
+                layer.b[k] = source_layer.b[row];
+            }
+            for (g, &col) in source.cols.iter().enumerate() {
+                if let Some((col, scale)) = col {
+                    layer.w[(k, g)] = source_layer.w[(row, col)] * scale;
+                }
+            }
+        }
+        params.set_frozen(u, source.params.is_frozen(source.index));
+        params.set_lr_multiplier(u, source.params.lr_multiplier(source.index));
+        if let (Some(mask), Some(source_mask)) = (&mut mask, source.params.mask()) {
+            let (w, b) = params.layer_ranges(u);
+            let (source_w, source_b) = source.params.layer_ranges(source.index);
+            let n = source.rows.len();
+            let n_source = source_layer.w.nrows();
+            for (k, &row) in source.rows.iter().enumerate() {
+                let Some(row) = row else {
+                    continue;
+                };
+                if !b.is_empty() {
+                    mask[b.start + k] = source_mask[source_b.start + row];
+                }
+                for (g, &col) in source.cols.iter().enumerate() {
+                    if let Some((col, _)) = col {
+                        mask[w.start + g * n + k] =
+                            source_mask[source_w.start + col * n_source + row];
+                    }
+                }
+            }
+        }
+    }
+    params.set_mask(mask);
+    Ok(nn)
+}
@@ -0,0 +1,22 @@
+/// Marks the targets of `samples` that `mask` leaves out as missing, by setting them to NaN.
+///
+/// `mask` has a flag per output per sample, `mask[i * n_outputs + k]` being whether output `k` of
+/// sample `i` is labelled. Missing targets contribute neither loss nor derivative, so samples that
+/// are only partially labelled can still be trained on.
+///
+/// # Panics
+///
+/// - if `samples.len()` is not a multiple of `n_inputs + n_outputs`
+/// - if `mask` does not have `n_outputs` flags per sample
+pub fn mask_targets(samples: &mut [f32], n_inputs: usize, n_outputs: usize, mask: &[bool]) {
+    let sample_size = n_inputs + n_outputs;
+    assert!(samples.len().is_multiple_of(sample_size));
+    assert_eq!(mask.len(), samples.len() / sample_size * n_outputs);
+    for (sample, mask) in samples.chunks_mut(sample_size).zip(mask.chunks(n_outputs)) {
+        for (y, &is_labelled) in sample[n_inputs..].iter_mut().zip(mask) {
+            if !is_labelled {
+                *y = f32::NAN;
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use mlp::{
+    Gym, InferenceModel, MlpError, NeuralNetwork, Topology, activation_functions::*,
+    faer::prelude::*,
+};
+
+fn network() -> NeuralNetwork {
+    let topology = Topology::builder()
+        .input(2)
+        .dense(3, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+#[test]
+fn forward_and_loss_check_sizes() {
+    let mut nn = network();
+    assert!(matches!(
+        nn.forward(col![1.0, 2.0, 3.0].as_ref()),
+        Err(MlpError::InputSize {
+            expected: 2,
+            found: 3
+        })
+    ));
+    assert!(matches!(
+        nn.loss(&[1.0, 2.0, 3.0, 4.0]),
+        Err(MlpError::SampleSize {
+            sample_size: 3,
+            len: 4
+        })
+    ));
+    assert!(matches!(nn.loss(&[]), Err(MlpError::NoSamples)));
+    assert!(matches!(
+        nn.predict(&[1.0, 2.0]),
+        Err(MlpError::SampleSize { .. })
+    ));
+    assert!(nn.loss(&[1.0, 2.0, 3.0]).is_ok());
+}
+
+#[test]
+fn training_checks_sizes_and_leaves_the_params() {
+    let mut nn = network();
+    let params = nn.params_as_slice().to_vec();
+    let mut gym = Gym::new(&mut nn);
+    let samples = [0.0, 1.0, 1.0, 1.0, 0.0];
+    assert!(matches!(
+        gym.train(2, 0.1, &samples),
+        Err(MlpError::SampleSize { .. })
+    ));
+    assert!(matches!(
+        gym.train_single_threaded(0.1, &samples),
+        Err(MlpError::SampleSize { .. })
+    ));
+    assert!(matches!(
+        gym.train_weighted(0.1, &samples[..3], &[1.0, 1.0]),
+        Err(MlpError::SampleCount {
+            expected: 1,
+            found: 2
+        })
+    ));
+    assert!(matches!(
+        gym.train_weighted(0.1, &samples[..3], &[-1.0]),
+        Err(MlpError::SampleWeights)
+    ));
+    assert!(matches!(
+        gym.train_with_output_derivs(
+            0.1,
+            Mat::zeros(2, 3).as_ref(),
+            Mat::zeros(1, 2).as_ref(),
+            None
+        ),
+        Err(MlpError::BatchSize {
+            n_inputs: 3,
+            n_outputs: 2
+        })
+    ));
+    drop(gym);
+    assert_eq!(nn.params_as_slice(), params);
+}
+
+#[test]
+fn inference_checks_sizes() {
+    let model = InferenceModel::from(network());
+    assert!(matches!(
+        model.predict(col![1.0].as_ref()),
+        Err(MlpError::InputSize {
+            expected: 2,
+            found: 1
+        })
+    ));
+    let mut outputs = Mat::zeros(2, 4);
+    assert!(matches!(
+        model.predict_batch(Mat::zeros(2, 4).as_ref(), outputs.as_mut()),
+        Err(MlpError::OutputSize {
+            expected: 1,
+            found: 2
+        })
+    ));
+    let mut outputs = Mat::zeros(1, 3);
+    assert!(matches!(
+        model.predict_batch(Mat::zeros(2, 4).as_ref(), outputs.as_mut()),
+        Err(MlpError::BatchSize {
+            n_inputs: 4,
+            n_outputs: 3
+        })
+    ));
+    let mut results = model.create_results();
+    assert!(matches!(
+        model.forward(col![1.0, 2.0, 3.0].as_ref(), &mut results),
+        Err(MlpError::InputSize { .. })
+    ));
+}
@@ -0,0 +1,82 @@
+use std::{fs, path::PathBuf};
+
+use mlp::{
+    CheckpointConfig, Gym, Hyperparameters, MlpError, NeuralNetwork, PruningScope, Topology,
+    activation_functions::*,
+};
+
+fn network(n_hidden: usize) -> NeuralNetwork {
+    let topology = Topology::builder()
+        .input(2)
+        .dense(n_hidden, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+const SAMPLES: [f32; 12] = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0];
+
+fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mlp-{name}-{}", std::process::id()))
+}
+
+#[test]
+fn save_and_load() {
+    let dir = temp_dir("checkpoint-roundtrip");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("checkpoint.bin");
+    let mut nn = network(3);
+    nn.set_frozen(0, true);
+    nn.set_lr_multiplier(1, 0.5);
+    nn.prune_by_magnitude(0.25, PruningScope::Global);
+    let mut hyperparameters = Hyperparameters::new(0.1);
+    hyperparameters.batch_size = Some(2);
+    let mut gym = Gym::new(&mut nn);
+    gym.fit(hyperparameters, &SAMPLES, 3, &mut []).unwrap();
+    gym.save_checkpoint(&path).unwrap();
+    let (i_step, i_epoch) = (gym.i_step(), gym.i_epoch());
+    drop(gym);
+    let params = nn.params_as_slice().to_vec();
+    let mask = nn.params().mask().unwrap().to_vec();
+
+    let mut restored = network(3);
+    let mut gym = Gym::new(&mut restored);
+    assert_eq!(gym.load_checkpoint(&path).unwrap(), Some(hyperparameters));
+    assert_eq!((gym.i_step(), gym.i_epoch()), (i_step, i_epoch));
+    drop(gym);
+    assert_eq!(restored.params_as_slice(), params);
+    assert_eq!(restored.params().mask().unwrap(), mask);
+    assert!(restored.is_frozen(0));
+    assert_eq!(restored.lr_multiplier(1), 0.5);
+
+    let mut other = network(4);
+    let mut gym = Gym::new(&mut other);
+    assert!(matches!(
+        gym.load_checkpoint(&path),
+        Err(MlpError::TopologyMismatch)
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keeps_the_last_checkpoints() {
+    let dir = temp_dir("checkpoint-rotation");
+    let mut nn = network(3);
+    let mut gym = Gym::new(&mut nn);
+    let config = CheckpointConfig::new(&dir, 2, 2);
+    gym.set_checkpointing(config.clone());
+    gym.fit(Hyperparameters::new(0.1), &SAMPLES, 9, &mut [])
+        .unwrap();
+    // Written after epochs 2, 4, 6 and 8.
+    assert_eq!(
+        config.list().unwrap(),
+        [config.path_for_epoch(6), config.path_for_epoch(8)]
+    );
+    assert_eq!(config.latest().unwrap(), Some(config.path_for_epoch(8)));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,86 @@
+//! Finite-difference checks of the derivatives calculated by back propagation, shared by the
+//! integration tests.
+//!
+//! `calculate_derivs` and `calculate_sequence_derivs` derive half the squared error, averaged over
+//! the samples or sequences.
+
+// Not every test crate uses every helper.
+#![allow(dead_code)]
+
+use mlp::{
+    NeuralNetwork, Topology,
+    core::{DerivBuffer, ResultBuffer, calculate_derivs, loss_unchecked},
+};
+
+pub const EPS: f32 = 1e-2;
+
+/// Network of `topology` with deterministic params of magnitude up to `0.5`.
+pub fn network(topology: Topology) -> NeuralNetwork {
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+/// Deterministic values within `-1..1`.
+pub fn values(n: usize, seed: f32) -> Vec<f32> {
+    (0..n).map(|i| (0.77 * i as f32 + seed).sin()).collect()
+}
+
+/// Derivatives in the layout of `ParamBuffer::as_slice`.
+pub fn flatten(derivs: &DerivBuffer) -> Vec<f32> {
+    let mut flat = Vec::new();
+    for u in 0..derivs.n_layers() {
+        let layer = derivs.layer(u).unwrap();
+        for g in 0..layer.dw.ncols() {
+            flat.extend(layer.dw.col(g).iter());
+        }
+        flat.extend(layer.db.iter());
+    }
+    flat
+}
+
+/// Compares `analytic` with the central differences of `loss` by every param of `nn`.
+pub fn assert_matches_finite_differences(
+    nn: &mut NeuralNetwork,
+    analytic: &[f32],
+    mut loss: impl FnMut(&NeuralNetwork) -> f32,
+) {
+    assert_eq!(analytic.len(), nn.params_as_slice().len());
+    let mut n_nonzero = 0;
+    for (i, &analytic) in analytic.iter().enumerate() {
+        let p = nn.params_as_slice()[i];
+        nn.params_as_mut_slice()[i] = p + EPS;
+        let loss_plus = loss(nn);
+        nn.params_as_mut_slice()[i] = p - EPS;
+        let loss_minus = loss(nn);
+        nn.params_as_mut_slice()[i] = p;
+        let numeric = (loss_plus - loss_minus) / (2.0 * EPS);
+        assert!(
+            (numeric - analytic).abs() <= 1e-3 + 1e-2 * analytic.abs(),
+            "param {i}: back propagation gives {analytic}, finite differences {numeric}",
+        );
+        if analytic.abs() > 1e-4 {
+            n_nonzero += 1;
+        }
+    }
+    // Guards against a check that passes because everything is zero.
+    assert!(n_nonzero > analytic.len() / 4);
+}
+
+/// Checks `calculate_derivs` on `samples` against the loss of `loss_unchecked`.
+pub fn check_derivs(topology: Topology, samples: &[f32]) {
+    let n_samples = samples.len() / (topology.n_inputs() + topology.n_outputs());
+    let mut nn = network(topology.clone());
+    let mut results = ResultBuffer::create(&topology);
+    let mut derivs = DerivBuffer::create(&topology);
+    // Safety: buffers are of the same topology, samples are of whole samples.
+    unsafe { calculate_derivs(nn.params(), &mut results, &mut derivs, samples) };
+    let analytic = flatten(&derivs);
+    assert_matches_finite_differences(&mut nn, &analytic, |nn| {
+        // Safety: same as above.
+        let loss = unsafe { loss_unchecked(nn.params(), &mut results, samples) };
+        loss / 2.0 / n_samples as f32
+    });
+}
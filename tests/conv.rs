@@ -0,0 +1,31 @@
+mod common;
+
+use mlp::{PoolKind, Shape, Topology, activation_functions::*};
+
+use common::{check_derivs, values};
+
+#[test]
+fn conv_and_max_pool() {
+    let topology = Topology::builder()
+        .input_shape(Shape::new(1, 4, 4))
+        .conv2d(2, (3, 3), (1, 1), (1, 1), Tanh)
+        .pool2d(PoolKind::Max, (2, 2))
+        .flatten()
+        .dense(2, Identity)
+        .build()
+        .unwrap();
+    check_derivs(topology, &values(3 * 18, 0.1));
+}
+
+#[test]
+fn conv_and_average_pool() {
+    let topology = Topology::builder()
+        .input_shape(Shape::new(2, 1, 8))
+        .conv1d(3, 3, 1, 0, Tanh)
+        .pool1d(PoolKind::Average, 2)
+        .flatten()
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    check_derivs(topology, &values(3 * 17, 0.6));
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use mlp::{Topology, activation_functions::*};
+
+use common::{check_derivs, values};
+
+#[test]
+fn embedding() {
+    let topology = Topology::builder()
+        .input(3)
+        .embedding(2, 5, 3)
+        .dense(4, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut samples = Vec::new();
+    for i in 0..6 {
+        // Two ids, one numeric input and a target.
+        samples.extend([(i % 5) as f32, ((i * 2 + 1) % 5) as f32]);
+        samples.extend(values(2, i as f32));
+    }
+    check_derivs(topology, &samples);
+}
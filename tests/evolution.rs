@@ -0,0 +1,146 @@
+use mlp::{
+    CmaEsConfig, Evolution, GeneticConfig, LayerDescription, NeuralNetwork, Topology,
+    activation_functions::*, faer::prelude::*,
+};
+
+fn network(topology: Topology) -> NeuralNetwork {
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+fn linear() -> NeuralNetwork {
+    network(
+        Topology::builder()
+            .input(1)
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    )
+}
+
+/// Samples of `y = 2x - 1`.
+fn samples() -> Vec<f32> {
+    (0..8)
+        .flat_map(|i| {
+            let x = i as f32 / 4.0 - 1.0;
+            [x, 2.0 * x - 1.0]
+        })
+        .collect()
+}
+
+fn fitness(nn: &mut NeuralNetwork) -> f32 {
+    -nn.loss(&samples()).unwrap()
+}
+
+#[test]
+fn genetic_improves_the_fitness() {
+    let mut nn = linear();
+    let fitness_before = fitness(&mut nn);
+    let mut evolution = Evolution::genetic(&mut nn, GeneticConfig::default());
+    let mut best_so_far = f32::NEG_INFINITY;
+    for i in 0..50 {
+        let generation = evolution.step(fitness);
+        assert_eq!(generation.i_generation, i);
+        assert!(generation.best_fitness_so_far >= best_so_far);
+        assert!(generation.best_fitness_so_far >= generation.best_fitness);
+        assert!(generation.best_fitness >= generation.mean_fitness);
+        best_so_far = generation.best_fitness_so_far;
+    }
+    assert_eq!(evolution.best_fitness(), Some(best_so_far));
+    drop(evolution);
+    // The network holds the fittest params, which the first generation already starts from.
+    assert_eq!(fitness(&mut nn), best_so_far);
+    assert!(best_so_far > fitness_before / 10.0);
+}
+
+#[test]
+fn cma_es_fits_a_line() {
+    let mut nn = linear();
+    let config = CmaEsConfig {
+        population: Some(8),
+        sigma: 0.5,
+    };
+    let mut evolution = Evolution::cma_es(&mut nn, config);
+    for _ in 0..200 {
+        evolution.step(fitness);
+    }
+    drop(evolution);
+    let params = nn.params_layer(0).unwrap();
+    assert!((params.w[(0, 0)] - 2.0).abs() < 1e-2, "{:?}", params.w);
+    assert!((params.b[0] + 1.0).abs() < 1e-2, "{:?}", params.b);
+}
+
+#[test]
+fn nan_fitness_ranks_lowest() {
+    let mut nn = linear();
+    let params = nn.params_as_slice().to_vec();
+    let mut evolution = Evolution::genetic(&mut nn, GeneticConfig::default());
+    // The network's own params, the first candidate, are the only ones of a finite fitness.
+    let generation = evolution.step(|nn| match nn.params_as_slice() == params {
+        true => 1.0,
+        false => f32::NAN,
+    });
+    assert_eq!(generation.best_fitness, 1.0);
+    assert_eq!(generation.mean_fitness, 1.0);
+    let generation = evolution.step(|_| f32::NAN);
+    assert!(generation.mean_fitness.is_nan());
+    assert_eq!(generation.best_fitness_so_far, 1.0);
+    drop(evolution);
+    assert_eq!(nn.params_as_slice(), params);
+}
+
+#[test]
+fn candidates_start_from_the_network_and_a_zero_state() {
+    let mut nn = network(
+        Topology::builder()
+            .input(1)
+            .dense(2, Tanh)
+            .layer(LayerDescription::recurrent(2, Tanh))
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    );
+    nn.set_frozen(0, true);
+    let frozen = nn.params_layer(0).unwrap().w.to_owned();
+    let x = col![0.5];
+    let fitness = |nn: &mut NeuralNetwork| {
+        assert_eq!(nn.params_layer(0).unwrap().w, frozen);
+        let first = nn.forward(x.as_ref()).unwrap()[0];
+        nn.reset_state();
+        assert_eq!(nn.forward(x.as_ref()).unwrap()[0], first);
+        // Leaves the state and the frozen params changed for the next candidate on the thread.
+        nn.params_as_mut_slice().fill(1.0);
+        first
+    };
+    let config = GeneticConfig {
+        population: 16,
+        ..GeneticConfig::default()
+    };
+    let mut evolution = Evolution::genetic(&mut nn, config);
+    for _ in 0..3 {
+        evolution.step(fitness);
+    }
+    let config = CmaEsConfig {
+        population: Some(16),
+        ..CmaEsConfig::default()
+    };
+    drop(evolution);
+    let mut evolution = Evolution::cma_es(&mut nn, config);
+    for _ in 0..3 {
+        evolution.step(fitness);
+    }
+}
+
+#[test]
+#[should_panic]
+fn genetic_rejects_a_rate_above_one() {
+    let mut nn = linear();
+    let config = GeneticConfig {
+        mutation_rate: 1.5,
+        ..GeneticConfig::default()
+    };
+    Evolution::genetic(&mut nn, config);
+}
@@ -0,0 +1,90 @@
+use mlp::{Gym, NeuralNetwork, Topology, activation_functions::*};
+
+fn network() -> NeuralNetwork {
+    let topology = Topology::builder()
+        .input(2)
+        .dense(4, Tanh)
+        .dense(3, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+const SAMPLES: [f32; 12] = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0];
+
+/// Params of each layer, `w` then `b`.
+fn layers(nn: &NeuralNetwork) -> Vec<Vec<f32>> {
+    (0..nn.topology().n_layers())
+        .map(|u| {
+            let layer = nn.params_layer(u).unwrap();
+            let w = (0..layer.w.ncols()).flat_map(|g| layer.w.col(g).iter().copied());
+            w.chain(layer.b.iter().copied()).collect()
+        })
+        .collect()
+}
+
+#[test]
+fn frozen_layers_are_unchanged_by_training() {
+    let mut nn = network();
+    // The first two layers, as when fine-tuning the last layer of a pretrained network.
+    nn.freeze_all_but_last(1);
+    assert!(nn.is_frozen(0) && nn.is_frozen(1) && !nn.is_frozen(2));
+    let before = layers(&nn);
+    let mut gym = Gym::new(&mut nn);
+    for _ in 0..10 {
+        gym.train(2, 0.1, &SAMPLES).unwrap();
+        gym.train_single_threaded(0.1, &SAMPLES).unwrap();
+    }
+    drop(gym);
+    let after = layers(&nn);
+    assert_eq!(after[..2], before[..2]);
+    assert_ne!(after[2], before[2]);
+}
+
+#[test]
+fn layers_before_a_frozen_one_still_train() {
+    let mut nn = network();
+    nn.set_frozen(1, true);
+    let before = layers(&nn);
+    let mut gym = Gym::new(&mut nn);
+    for _ in 0..10 {
+        gym.train_single_threaded(0.1, &SAMPLES).unwrap();
+    }
+    drop(gym);
+    let after = layers(&nn);
+    assert_ne!(after[0], before[0]);
+    assert_eq!(after[1], before[1]);
+    assert_ne!(after[2], before[2]);
+}
+
+#[test]
+fn lr_multipliers_scale_the_step() {
+    let step = |lr_multiplier: f32| {
+        let mut nn = network();
+        nn.set_lr_multiplier(0, lr_multiplier);
+        let before = layers(&nn);
+        Gym::new(&mut nn)
+            .train_single_threaded(0.1, &SAMPLES)
+            .unwrap();
+        let after = layers(&nn);
+        (0..3)
+            .map(|u| {
+                let delta = after[u].iter().zip(&before[u]);
+                delta.map(|(a, b)| a - b).collect::<Vec<f32>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    let (once, twice, none) = (step(1.0), step(2.0), step(0.0));
+    assert!(none[0].iter().all(|&d| d == 0.0));
+    for (&d_once, &d_twice) in once[0].iter().zip(&twice[0]) {
+        assert!((2.0 * d_once - d_twice).abs() <= 1e-6, "{d_once} {d_twice}");
+    }
+    // Other layers take the same step.
+    assert_eq!(once[1..], twice[1..]);
+    assert_eq!(once[1..], none[1..]);
+}
@@ -0,0 +1,67 @@
+use mlp::{Gym, Hyperparameters, MlpError, NeuralNetwork, Topology, activation_functions::*};
+
+fn network() -> NeuralNetwork {
+    let topology = Topology::builder()
+        .input(1)
+        .dense(4, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+/// Samples of `y = sign * x`.
+fn samples(sign: f32) -> Vec<f32> {
+    (0..8)
+        .flat_map(|i| {
+            let x = i as f32 / 4.0 - 1.0;
+            [x, sign * x]
+        })
+        .collect()
+}
+
+#[test]
+fn validation_interval_must_not_be_zero() {
+    let mut nn = network();
+    let mut gym = Gym::new(&mut nn);
+    let validation = samples(1.0);
+    assert!(matches!(
+        gym.set_validation(&validation, 0),
+        Err(MlpError::ValidationInterval)
+    ));
+    assert_eq!(gym.validate(), None);
+}
+
+#[test]
+fn early_stopping_without_validation_has_no_effect() {
+    let mut nn = network();
+    let mut gym = Gym::new(&mut nn);
+    gym.set_early_stopping(0, 0.0);
+    let report = gym
+        .fit(Hyperparameters::new(0.1), &samples(1.0), 5, &mut [])
+        .unwrap();
+    assert!(!report.stopped_early);
+    assert_eq!(report.n_epochs, 5);
+}
+
+#[test]
+fn early_stopping_restores_the_best_params() {
+    let mut nn = network();
+    let mut gym = Gym::new(&mut nn);
+    // Fitting the training samples only makes the validation loss worse, past some point.
+    let validation = samples(-1.0);
+    gym.set_validation(&validation, 1).unwrap();
+    gym.set_early_stopping(3, 0.0);
+    let report = gym
+        .fit(Hyperparameters::new(0.1), &samples(1.0), 1000, &mut [])
+        .unwrap();
+    assert!(report.stopped_early);
+    assert!(report.n_epochs < 1000);
+    let best = report.best_validation_loss.unwrap();
+    assert_eq!(gym.best_validation_loss(), Some(best));
+    assert_eq!(gym.validate(), Some(best));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use mlp::{
+    LayerDescription, NeuralNetwork, Topology,
+    activation_functions::*,
+    core::{ResultBuffer, forward_unchecked, reset_state},
+    faer::prelude::*,
+};
+
+use common::{EPS, assert_matches_finite_differences, network, values};
+
+fn topology() -> Topology {
+    Topology::builder()
+        .input(3)
+        .dense(4, Tanh)
+        .layer(LayerDescription::recurrent(4, Tanh))
+        .residual(4, Sigmoid, 0)
+        .dense(2, Identity)
+        .build()
+        .unwrap()
+}
+
+/// Output `j` of `nn` at `x`, from a zero state.
+fn output(nn: &NeuralNetwork, results: &mut ResultBuffer, x: ColRef<f32>, j: usize) -> f32 {
+    reset_state(nn.params(), results);
+    // Safety: `results` is of the topology of `nn`, `x` of its number of inputs.
+    unsafe { forward_unchecked(x, nn.params(), results) };
+    results.layer(results.n_layers() - 1).unwrap().a[j]
+}
+
+#[test]
+fn jacobian_params_matches_finite_differences() {
+    let mut nn = network(topology());
+    // Frozen layers are derived too.
+    nn.set_frozen(0, true);
+    let mut results = ResultBuffer::create(nn.topology());
+    let x = Col::from_fn(3, |i| values(3, 0.3)[i]);
+    let jacobian = nn.jacobian_params(x.as_ref()).unwrap();
+    assert_eq!(jacobian.shape(), (2, nn.params_as_slice().len()));
+    for j in 0..2 {
+        let analytic: Vec<f32> = jacobian.row(j).iter().copied().collect();
+        assert_matches_finite_differences(&mut nn, &analytic, |nn| {
+            output(nn, &mut results, x.as_ref(), j)
+        });
+    }
+}
+
+#[test]
+fn jacobian_input_matches_finite_differences() {
+    let mut nn = network(topology());
+    let mut results = ResultBuffer::create(nn.topology());
+    let mut x = Col::from_fn(3, |i| values(3, 1.1)[i]);
+    let jacobian = nn.jacobian_input(x.as_ref()).unwrap();
+    assert_eq!(jacobian.shape(), (2, 3));
+    for j in 0..2 {
+        for g in 0..3 {
+            let x_g = x[g];
+            x[g] = x_g + EPS;
+            let plus = output(&nn, &mut results, x.as_ref(), j);
+            x[g] = x_g - EPS;
+            let minus = output(&nn, &mut results, x.as_ref(), j);
+            x[g] = x_g;
+            let numeric = (plus - minus) / (2.0 * EPS);
+            let analytic = jacobian[(j, g)];
+            assert!(
+                (numeric - analytic).abs() <= 1e-3 + 1e-2 * analytic.abs(),
+                "output {j} by input {g}: {analytic} != {numeric}"
+            );
+        }
+    }
+}
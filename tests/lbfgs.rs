@@ -0,0 +1,108 @@
+use mlp::{Gym, LbfgsConfig, LbfgsStop, NeuralNetwork, Topology, activation_functions::*};
+
+fn network(topology: Topology) -> NeuralNetwork {
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+const XOR: [f32; 12] = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0];
+
+fn xor_network() -> NeuralNetwork {
+    network(
+        Topology::builder()
+            .input(2)
+            .dense(4, Tanh)
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    )
+}
+
+#[test]
+fn fits_a_line() {
+    // `y = 2x - 1`, which a single linear neuron fits exactly.
+    let samples: Vec<f32> = (0..8)
+        .flat_map(|i| {
+            let x = i as f32 / 4.0 - 1.0;
+            [x, 2.0 * x - 1.0]
+        })
+        .collect();
+    let mut nn = network(
+        Topology::builder()
+            .input(1)
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    );
+    let mut gym = Gym::new(&mut nn);
+    let config = LbfgsConfig {
+        gradient_tolerance: 1e-5,
+        loss_tolerance: 0.0,
+        ..LbfgsConfig::default()
+    };
+    let report = gym.train_lbfgs(&samples, &config).unwrap();
+    assert_eq!(report.stop, LbfgsStop::GradientTolerance, "{report:?}");
+    // A quadratic of 2 params, which L-BFGS minimizes in about as many iterations.
+    assert!(report.n_iterations <= 5, "{report:?}");
+    assert!(report.loss < 1e-8, "{report:?}");
+    assert_eq!(gym.i_step(), report.n_iterations);
+    drop(gym);
+    let params = nn.params_layer(0).unwrap();
+    assert!((params.w[(0, 0)] - 2.0).abs() < 1e-3);
+    assert!((params.b[0] + 1.0).abs() < 1e-3);
+    assert!(nn.loss(&samples).unwrap() / 8.0 < 1e-8);
+}
+
+#[test]
+fn fits_xor() {
+    let mut nn = xor_network();
+    let loss_before = nn.loss(&XOR).unwrap();
+    let mut gym = Gym::new(&mut nn);
+    let report = gym.train_lbfgs(&XOR, &LbfgsConfig::default()).unwrap();
+    assert_eq!(report.stop, LbfgsStop::LossTolerance, "{report:?}");
+    // Gradient descent takes thousands of steps.
+    assert!(report.n_iterations <= 50, "{report:?}");
+    assert!(report.n_evaluations > report.n_iterations, "{report:?}");
+    assert!(report.loss < 1e-6 * loss_before, "{report:?}");
+    drop(gym);
+    // `loss` is summed over the samples, the report's is averaged.
+    assert!((nn.loss(&XOR).unwrap() / 4.0 - report.loss).abs() < 1e-6);
+}
+
+#[test]
+fn stops_at_max_iterations() {
+    let mut nn = xor_network();
+    let loss_before = nn.loss(&XOR).unwrap();
+    let mut gym = Gym::new(&mut nn);
+    let config = LbfgsConfig {
+        max_iterations: 3,
+        ..LbfgsConfig::default()
+    };
+    let report = gym.train_lbfgs(&XOR, &config).unwrap();
+    assert_eq!(report.stop, LbfgsStop::MaxIterations);
+    assert_eq!(report.n_iterations, 3);
+    assert!(report.loss < loss_before);
+}
+
+#[test]
+fn failed_line_search_leaves_the_params() {
+    let mut nn = xor_network();
+    let params = nn.params_as_slice().to_vec();
+    let loss_before = nn.loss(&XOR).unwrap();
+    let mut gym = Gym::new(&mut nn);
+    // No evaluations along the search direction, so no step is ever found.
+    let config = LbfgsConfig {
+        max_line_search: 0,
+        ..LbfgsConfig::default()
+    };
+    let report = gym.train_lbfgs(&XOR, &config).unwrap();
+    assert_eq!(report.stop, LbfgsStop::LineSearch);
+    assert_eq!(report.n_iterations, 0);
+    assert_eq!(report.n_evaluations, 1);
+    assert_eq!(report.loss, loss_before / 4.0);
+    drop(gym);
+    assert_eq!(nn.params_as_slice(), params);
+}
@@ -0,0 +1,116 @@
+use mlp::{
+    Gym, LbfgsConfig, LevenbergMarquardtConfig, LevenbergMarquardtStop, NeuralNetwork, Topology,
+    activation_functions::*,
+};
+
+fn network(topology: Topology) -> NeuralNetwork {
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+fn curve_network() -> NeuralNetwork {
+    network(
+        Topology::builder()
+            .input(1)
+            .dense(1, Tanh)
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    )
+}
+
+/// Samples of `y = 0.5 * tanh(2x - 0.5) + 0.25`, which `curve_network` fits exactly.
+fn curve() -> Vec<f32> {
+    (0..16)
+        .flat_map(|i| {
+            let x = i as f32 / 8.0 - 1.0;
+            [x, 0.5 * (2.0 * x - 0.5).tanh() + 0.25]
+        })
+        .collect()
+}
+
+#[test]
+fn fits_a_curve() {
+    let samples = curve();
+    let mut nn = curve_network();
+    let loss_before = nn.loss(&samples).unwrap();
+    let mut gym = Gym::new(&mut nn);
+    let report = gym
+        .train_levenberg_marquardt(&samples, &LevenbergMarquardtConfig::default())
+        .unwrap();
+    assert_ne!(
+        report.stop,
+        LevenbergMarquardtStop::MaxIterations,
+        "{report:?}"
+    );
+    assert!(report.n_iterations <= 100, "{report:?}");
+    assert!(report.n_evaluations >= report.n_iterations);
+    assert!(report.loss < 1e-8, "{report:?}");
+    assert_eq!(gym.i_step(), report.n_iterations);
+    drop(gym);
+    // `loss` is summed over the samples, the report's is averaged.
+    assert!(nn.loss(&samples).unwrap() / 16.0 < 1e-8);
+    assert!(nn.loss(&samples).unwrap() < loss_before);
+}
+
+#[test]
+fn stops_at_max_damping_if_no_step_improves() {
+    // Two samples of the same input and opposite targets, of which a zero output is the optimum.
+    let samples = [1.0, 1.0, 1.0, -1.0];
+    let mut nn = network(
+        Topology::builder()
+            .input(1)
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    );
+    nn.params_as_mut_slice().copy_from_slice(&[0.5, -0.5]);
+    let mut gym = Gym::new(&mut nn);
+    let config = LevenbergMarquardtConfig {
+        // Never met, the gradient being zero.
+        gradient_tolerance: -1.0,
+        initial_damping: 1.0,
+        max_damping: 5e3,
+        ..LevenbergMarquardtConfig::default()
+    };
+    let report = gym.train_levenberg_marquardt(&samples, &config).unwrap();
+    assert_eq!(report.stop, LevenbergMarquardtStop::MaxDamping);
+    assert_eq!(report.n_iterations, 0);
+    // Damping 1, 10, 100 and 1000.
+    assert_eq!(report.n_evaluations, 4);
+    assert!(report.damping > config.max_damping);
+    assert_eq!(report.loss, 1.0);
+    drop(gym);
+    assert_eq!(nn.params_as_slice(), [0.5, -0.5]);
+}
+
+#[test]
+fn gradient_norm_is_the_same_as_with_lbfgs() {
+    let samples = curve();
+    let mut nn = curve_network();
+    let mut gym = Gym::new(&mut nn);
+    let config = LevenbergMarquardtConfig {
+        max_iterations: 1,
+        ..LevenbergMarquardtConfig::default()
+    };
+    gym.train_levenberg_marquardt(&samples, &config).unwrap();
+    let gradient_norm = gym.gradient_norm();
+    drop(gym);
+
+    let mut nn = curve_network();
+    let mut gym = Gym::new(&mut nn);
+    let config = LbfgsConfig {
+        max_iterations: 1,
+        ..LbfgsConfig::default()
+    };
+    gym.train_lbfgs(&samples, &config).unwrap();
+    // Both of the gradient at the initial params.
+    let expected = gym.gradient_norm();
+    assert!(
+        (gradient_norm - expected).abs() <= 1e-4 * expected,
+        "{gradient_norm} != {expected}"
+    );
+}
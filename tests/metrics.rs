@@ -0,0 +1,112 @@
+use mlp::Predictions;
+
+#[test]
+fn missing_targets_are_left_out() {
+    let predictions = Predictions::new(
+        1,
+        vec![0.9, 0.2, 0.7, 0.4, 5.0],
+        vec![1.0, 0.0, f32::NAN, 1.0, f32::NAN],
+    );
+    assert_eq!(predictions.accuracy(), 2.0 / 3.0);
+    let confusion_matrix = predictions.confusion_matrix();
+    assert_eq!(confusion_matrix.get(0, 0), 1);
+    assert_eq!(confusion_matrix.get(1, 0), 1);
+    assert_eq!(confusion_matrix.get(1, 1), 1);
+    assert_eq!(confusion_matrix.get(0, 1), 0);
+    assert_eq!(predictions.roc_auc(), Some(1.0));
+    // Errors of 0.1, 0.2 and 0.6 on the targets 1, 0 and 1.
+    let metrics = predictions.regression_metrics();
+    assert!((metrics.mae - 0.3).abs() < 1e-6);
+    assert!((metrics.rmse - (0.41f32 / 3.0).sqrt()).abs() < 1e-6);
+    assert!((metrics.r2 - (1.0 - 0.41 / (2.0 / 3.0))).abs() < 1e-6);
+}
+
+fn assert_close(lhs: f32, rhs: f32) {
+    assert!((lhs - rhs).abs() < 1e-6, "{lhs} != {rhs}");
+}
+
+#[test]
+fn classification_metrics() {
+    #[rustfmt::skip]
+    let outputs = vec![
+        0.7, 0.2, 0.1,
+        0.1, 0.6, 0.3,
+        0.5, 0.3, 0.2,
+        0.2, 0.2, 0.6,
+        0.3, 0.4, 0.3,
+        0.1, 0.3, 0.6,
+    ];
+    // Classes 0, 1, 1, 2, 2 and 0, predicted as 0, 1, 0, 2, 1 and 2.
+    #[rustfmt::skip]
+    let targets = vec![
+        1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0,
+        0.0, 1.0, 0.0,
+        0.0, 0.0, 1.0,
+        0.0, 0.0, 1.0,
+        1.0, 0.0, 0.0,
+    ];
+    let predictions = Predictions::new(3, outputs, targets);
+    assert_eq!(predictions.n_samples(), 6);
+    assert_eq!(predictions.n_classes(), 3);
+    let losses = predictions.losses();
+    assert_close(losses[0], 0.14);
+    assert_close(losses[5], 1.26);
+    assert_eq!(predictions.accuracy(), 0.5);
+    // Only the target of the last sample is not among its two largest outputs.
+    assert_eq!(predictions.top_k_accuracy(2), 5.0 / 6.0);
+    assert_eq!(predictions.top_k_accuracy(3), 1.0);
+    let metrics = predictions.classification_metrics();
+    let confusion_matrix = &metrics.confusion_matrix;
+    for (actual, predicted) in [(0, 0), (0, 2), (1, 0), (1, 1), (2, 1), (2, 2)] {
+        assert_eq!(confusion_matrix.get(actual, predicted), 1);
+    }
+    assert_eq!(confusion_matrix.get(0, 1), 0);
+    assert_eq!(confusion_matrix.n_predicted(0), 2);
+    assert_eq!(confusion_matrix.n_actual(2), 2);
+    assert_eq!(metrics.precision, [0.5; 3]);
+    assert_eq!(metrics.recall, [0.5; 3]);
+    assert_eq!(metrics.f1, [0.5; 3]);
+    assert_eq!(metrics.macro_f1(), 0.5);
+    // Of the 8 positive-negative pairs of each class, 4.5, 6.5 and 6 are ranked right, ties
+    // counting a half.
+    assert_eq!(predictions.roc_auc_of_class(0), Some(4.5 / 8.0));
+    assert_eq!(predictions.roc_auc_of_class(1), Some(6.5 / 8.0));
+    assert_eq!(predictions.roc_auc_of_class(2), Some(6.0 / 8.0));
+    assert_close(metrics.roc_auc.unwrap(), 17.0 / 24.0);
+}
+
+#[test]
+fn binary_classification_metrics() {
+    let predictions = Predictions::new(1, vec![0.8, 0.3, 0.6, 0.1], vec![1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(predictions.n_classes(), 2);
+    assert_eq!(predictions.accuracy(), 0.5);
+    assert_eq!(predictions.top_k_accuracy(2), 1.0);
+    let confusion_matrix = predictions.confusion_matrix();
+    assert_eq!(confusion_matrix.get(1, 1), 1);
+    assert_eq!(confusion_matrix.get(1, 0), 1);
+    assert_eq!(confusion_matrix.get(0, 1), 1);
+    assert_eq!(confusion_matrix.get(0, 0), 1);
+    // 0.8 is above both negatives, 0.1 below both.
+    assert_eq!(predictions.roc_auc(), Some(0.5));
+    assert_eq!(predictions.roc_auc_of_class(0), Some(0.5));
+    // No positives.
+    let predictions = Predictions::new(1, vec![0.8, 0.3], vec![0.0, 0.0]);
+    assert_eq!(predictions.roc_auc(), None);
+}
+
+#[test]
+fn regression_metrics() {
+    // Errors of 0, 1, 1 and 1, targets of mean 2.5 and summed squared deviation 5.
+    let predictions = Predictions::new(1, vec![1.0, 2.0, 3.0, 5.0], vec![1.0, 3.0, 2.0, 4.0]);
+    let metrics = predictions.regression_metrics();
+    assert_close(metrics.mae, 0.75);
+    assert_close(metrics.rmse, 0.75f32.sqrt());
+    assert_close(metrics.r2, 0.4);
+    assert_close(predictions.mean_loss(), 0.75);
+    // Constant targets, which only a perfect fit explains.
+    let predictions = Predictions::new(1, vec![1.0, 1.0], vec![1.0, 1.0]);
+    assert_eq!(predictions.regression_metrics().r2, 1.0);
+    let predictions = Predictions::new(1, vec![1.0, 2.0], vec![1.0, 1.0]);
+    assert_eq!(predictions.regression_metrics().r2, 0.0);
+}
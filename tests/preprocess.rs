@@ -0,0 +1,184 @@
+use std::{fs, path::PathBuf};
+
+use mlp::{
+    ColumnSpec, Encoding, Gym, Imputation, InferenceModel, NeuralNetwork, Preprocessor, Scaling,
+    Topology, activation_functions::*, faer::prelude::*,
+};
+
+/// Samples of a categorical input, a numeric input with a missing value and a target.
+const SAMPLES: [f32; 12] = [
+    10.0,
+    1.0,
+    5.0, //
+    20.0,
+    5.0,
+    7.0, //
+    30.0,
+    f32::NAN,
+    9.0, //
+    10.0,
+    3.0,
+    5.0,
+];
+
+fn preprocessor() -> Preprocessor {
+    Preprocessor::fit(
+        &SAMPLES,
+        &[
+            ColumnSpec::new(Encoding::OneHot),
+            ColumnSpec::new(Encoding::Standardize).with_imputation(Imputation::Mean),
+        ],
+        &[Scaling::Standardize],
+    )
+    .unwrap()
+}
+
+fn network() -> NeuralNetwork {
+    let preprocessor = preprocessor();
+    let topology = Topology::builder()
+        .input(preprocessor.n_inputs())
+        .dense(3, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn.set_preprocessor(Some(preprocessor)).unwrap();
+    nn
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mlp-{name}-{}", std::process::id()))
+}
+
+fn assert_close(lhs: f32, rhs: f32) {
+    assert!(
+        (lhs - rhs).abs() <= 1e-5 * rhs.abs().max(1.0),
+        "{lhs} != {rhs}"
+    );
+}
+
+#[test]
+fn fit_transform_and_inverse() {
+    let preprocessor = preprocessor();
+    assert_eq!(preprocessor.n_raw_inputs(), 2);
+    assert_eq!(preprocessor.n_inputs(), 4);
+    assert_eq!(preprocessor.n_outputs(), 1);
+    // The missing value is imputed with the mean 3, the column is then 1, 5, 3, 3 of a standard
+    // deviation of sqrt(2).
+    let input = preprocessor
+        .transform_input(col![20.0, 5.0].as_ref())
+        .unwrap();
+    assert_eq!(
+        input.iter().copied().collect::<Vec<_>>()[..3],
+        [0.0, 1.0, 0.0]
+    );
+    assert_close(input[3], 2.0 / 2.0f32.sqrt());
+    let input = preprocessor
+        .transform_input(col![40.0, f32::NAN].as_ref())
+        .unwrap();
+    assert_eq!(
+        input.iter().copied().collect::<Vec<_>>(),
+        [0.0, 0.0, 0.0, 0.0]
+    );
+    // Targets 5, 7, 9, 5 are of mean 6.5 and variance 2.75.
+    let samples = preprocessor.transform_samples(&SAMPLES).unwrap();
+    assert_eq!(samples.len(), 4 * 5);
+    assert_close(samples[4], -1.5 / 2.75f32.sqrt());
+    let output = preprocessor
+        .inverse_output(col![samples[4]].as_ref())
+        .unwrap();
+    assert_close(output[0], 5.0);
+}
+
+#[test]
+fn forward_predict_and_loss_apply_the_preprocessor() {
+    let mut nn = network();
+    let preprocessor = nn.preprocessor().unwrap().clone();
+    let mut bare = network();
+    bare.set_preprocessor(None).unwrap();
+    // Same as the input of sample 2.
+    let raw = col![30.0, f32::NAN];
+    let encoded = bare
+        .forward(preprocessor.transform_input(raw.as_ref()).unwrap().as_ref())
+        .unwrap()
+        .to_owned();
+    let expected = preprocessor.inverse_output(encoded.as_ref()).unwrap()[0];
+    assert_close(nn.forward(raw.as_ref()).unwrap()[0], expected);
+    let model = InferenceModel::from(network());
+    assert_close(model.predict(raw.as_ref()).unwrap()[0], expected);
+    let mut outputs = Mat::zeros(1, 2);
+    model
+        .predict_batch(Mat::from_fn(2, 2, |i, _| raw[i]).as_ref(), outputs.as_mut())
+        .unwrap();
+    assert_close(outputs[(0, 1)], expected);
+    // The loss is of the scaled targets, the predictions are in the units of the raw ones.
+    let transformed = preprocessor.transform_samples(&SAMPLES).unwrap();
+    assert_close(nn.loss(&SAMPLES).unwrap(), bare.loss(&transformed).unwrap());
+    let predictions = nn.predict(&SAMPLES).unwrap();
+    assert_eq!(predictions.target(2), [9.0]);
+    assert_close(predictions.output(2)[0], expected);
+}
+
+#[test]
+fn training_applies_the_preprocessor() {
+    let mut nn = network();
+    let loss_before = nn.loss(&SAMPLES).unwrap();
+    let mut gym = Gym::new(&mut nn);
+    for _ in 0..200 {
+        gym.train(0, 0.1, &SAMPLES).unwrap();
+    }
+    assert!(nn.loss(&SAMPLES).unwrap() < loss_before / 10.0);
+}
+
+#[test]
+fn save_and_load() {
+    let path = temp_path("preprocess-model");
+    let nn = network();
+    nn.save(&path).unwrap();
+    let mut loaded = network();
+    loaded.set_preprocessor(None).unwrap();
+    loaded.params_as_mut_slice().fill(0.0);
+    loaded.load(&path).unwrap();
+    assert_eq!(loaded.params_as_slice(), nn.params_as_slice());
+    assert_eq!(loaded.preprocessor(), nn.preprocessor());
+    fs::remove_file(&path).unwrap();
+}
+
+/// Replaces the first occurrence of `pattern` in the model file at `path`.
+fn corrupt(path: &PathBuf, pattern: &[f32], replacement: &[f32]) {
+    let bytes = fs::read(path).unwrap();
+    let pattern: Vec<u8> = pattern.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let replacement: Vec<u8> = replacement.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let i = bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+        .unwrap();
+    let mut corrupted = bytes.clone();
+    corrupted[i..i + pattern.len()].copy_from_slice(&replacement);
+    fs::write(path, corrupted).unwrap();
+}
+
+#[test]
+fn load_rejects_invalid_transforms() {
+    let path = temp_path("preprocess-invalid");
+    let nn = network();
+    let scale = 2.75f64.sqrt() as f32;
+    for (pattern, replacement) in [
+        // Unsorted and NaN one-hot categories.
+        ([10.0, 20.0], [20.0, 10.0]),
+        ([10.0, 20.0], [10.0, f32::NAN]),
+        // Target scaling of 0 and of infinity.
+        ([6.5, scale], [6.5, 0.0]),
+        ([6.5, scale], [6.5, f32::INFINITY]),
+    ] {
+        nn.save(&path).unwrap();
+        corrupt(&path, &pattern, &replacement);
+        let mut loaded = network();
+        assert!(loaded.load(&path).is_err());
+        assert_eq!(loaded.preprocessor(), nn.preprocessor());
+    }
+    fs::remove_file(&path).unwrap();
+}
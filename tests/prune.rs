@@ -0,0 +1,83 @@
+use mlp::{Gym, MlpError, NeuralNetwork, PruningScope, Topology, activation_functions::*};
+
+fn network() -> NeuralNetwork {
+    let topology = Topology::builder()
+        .input(2)
+        .dense(4, Tanh)
+        .dense(3, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+const SAMPLES: [f32; 12] = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0];
+
+fn assert_pruned_are_zero(nn: &NeuralNetwork) {
+    let mask = nn.params().mask().unwrap();
+    for (&p, &keep) in nn.params_as_slice().iter().zip(mask) {
+        assert!(keep || p == 0.0);
+    }
+}
+
+#[test]
+fn mask_survives_training() {
+    let mut nn = network();
+    let n_pruned = nn.prune_by_magnitude(0.5, PruningScope::Global);
+    assert!(n_pruned > 0);
+    let mask = nn.params().mask().unwrap().to_vec();
+    let sparsity = nn.params().sparsity();
+    assert_pruned_are_zero(&nn);
+    let mut gym = Gym::new(&mut nn);
+    for _ in 0..20 {
+        gym.train_single_threaded(0.1, &SAMPLES).unwrap();
+        gym.train(2, 0.1, &SAMPLES).unwrap();
+    }
+    drop(gym);
+    assert_eq!(nn.params().mask().unwrap(), mask);
+    assert_eq!(nn.params().sparsity(), sparsity);
+    assert_pruned_are_zero(&nn);
+
+    // Carried over by `remove_neurons`, and kept through training of the smaller network.
+    let mut smaller = nn.prune_neurons(0, 1).unwrap();
+    assert_pruned_are_zero(&smaller);
+    let mask = smaller.params().mask().unwrap().to_vec();
+    let mut gym = Gym::new(&mut smaller);
+    for _ in 0..20 {
+        gym.train_single_threaded(0.1, &SAMPLES).unwrap();
+    }
+    drop(gym);
+    assert_eq!(smaller.params().mask().unwrap(), mask);
+    assert_pruned_are_zero(&smaller);
+}
+
+#[test]
+fn prune_neurons_removes_the_smallest_outgoing_weights() {
+    let mut nn = network();
+    // Neuron 2 of layer 0 has the smallest outgoing weights.
+    let mut layer = nn.params_layer_mut(1).unwrap();
+    for k in 0..3 {
+        layer.w[(k, 2)] = 1e-3;
+    }
+    let smaller = nn.prune_neurons(0, 1).unwrap();
+    assert_eq!(smaller.topology().layer_descriptions()[0].n_neurons, 3);
+    let (w, w_smaller) = (
+        nn.params_layer(1).unwrap().w,
+        smaller.params_layer(1).unwrap().w,
+    );
+    for (g, g_smaller) in [(0, 0), (1, 1), (3, 2)] {
+        assert_eq!(w.col(g), w_smaller.col(g_smaller));
+    }
+    assert!(matches!(nn.prune_neurons(2, 0), Err(MlpError::OutputLayer)));
+    assert!(matches!(
+        nn.prune_neurons(3, 0),
+        Err(MlpError::LayerIndex {
+            index: 3,
+            n_layers: 3
+        })
+    ));
+}
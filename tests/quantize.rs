@@ -0,0 +1,57 @@
+use mlp::{NeuralNetwork, QuantizedModel, Topology, activation_functions::*, faer::prelude::*};
+
+/// A single linear neuron of `n_inputs` inputs, of weights `w(g)` and bias `0.25`.
+fn linear(n_inputs: usize, w: impl Fn(usize) -> f32) -> NeuralNetwork {
+    let topology = Topology::builder()
+        .input(n_inputs)
+        .dense(1, Identity)
+        .build()
+        .unwrap();
+    let mut nn = NeuralNetwork::new(topology);
+    let params = nn.params_as_mut_slice();
+    for (g, p) in params[..n_inputs].iter_mut().enumerate() {
+        *p = w(g);
+    }
+    params[n_inputs] = 0.25;
+    nn
+}
+
+#[test]
+fn error_is_within_half_a_step_of_each_operand() {
+    let n_inputs = 300;
+    let w = |g: usize| 0.5 * (1.3 * g as f32 + 0.4).sin();
+    let mut nn = linear(n_inputs, w);
+    let model = QuantizedModel::try_from(&nn).unwrap();
+    let mut scratch = model.create_scratch();
+    for seed in 0..5 {
+        let x = Col::<f32>::from_fn(n_inputs, |g| 2.0 * (0.77 * g as f32 + seed as f32).sin());
+        let expected = nn.forward(x.as_ref()).unwrap()[0];
+        let found = model.forward(x.as_ref(), &mut scratch).unwrap()[0];
+        // Every weight and input is off by at most half its scale.
+        let w_scale = model.layers()[0].w_scales[0];
+        let x_scale = x.iter().fold(0.0f32, |max, x| max.max(x.abs())) / 127.0;
+        let bound: f32 = (0..n_inputs)
+            .map(|g| {
+                w_scale / 2.0 * x[g].abs() + w(g).abs() * x_scale / 2.0 + w_scale * x_scale / 4.0
+            })
+            .sum();
+        assert!(
+            (expected - found).abs() <= bound,
+            "|{expected} - {found}| > {bound}"
+        );
+        // Far within the worst case, the errors mostly cancelling out.
+        assert!((expected - found).abs() <= 0.1 * bound);
+    }
+}
+
+#[test]
+fn accumulates_wide_layers_without_overflow() {
+    // The accumulator reaches `140_000 * 127^2`, past `i32::MAX`.
+    let n_inputs = 140_000;
+    let nn = linear(n_inputs, |_| 0.5);
+    let model = QuantizedModel::try_from(&nn).unwrap();
+    let mut scratch = model.create_scratch();
+    let x = Col::<f32>::ones(n_inputs);
+    let found = model.forward(x.as_ref(), &mut scratch).unwrap()[0];
+    assert!((found - 70_000.25).abs() <= 1e-2, "{found}");
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use mlp::{
+    SequenceConfig, SequenceTargets, Topology,
+    activation_functions::*,
+    core::{
+        DerivBuffer, ParamBuffer, ResultBuffer, calculate_sequence_derivs, forward_unchecked,
+        reset_state, sequence_loss_unchecked,
+    },
+    faer::prelude::*,
+};
+
+use common::{assert_matches_finite_differences, flatten, network, values};
+
+/// Steps of each sequence, of 2 inputs and 1 output, see `recurrent_topology`.
+const SEQUENCE_LEN: usize = 5;
+
+/// Checks `calculate_sequence_derivs` on `samples` laid out according to `config`.
+fn check_sequence_derivs(topology: Topology, samples: &[f32], config: &SequenceConfig) {
+    let sample_size = config.sample_size(topology.n_inputs(), topology.n_outputs());
+    let n_sequences = samples.len() / sample_size;
+    let mut nn = network(topology.clone());
+    let mut result_buffers: Vec<_> = (0..=config.len)
+        .map(|_| ResultBuffer::create(&topology))
+        .collect();
+    let mut derivs = DerivBuffer::create(&topology);
+    // Safety: buffers are of the same topology, there is one more than the steps of a sequence,
+    // samples are of whole sequences.
+    unsafe {
+        calculate_sequence_derivs(
+            nn.params(),
+            &mut result_buffers,
+            &mut derivs,
+            samples,
+            config,
+        )
+    };
+    let analytic = flatten(&derivs);
+    let base = network(topology.clone());
+    let mut results = ResultBuffer::create(&topology);
+    assert_matches_finite_differences(&mut nn, &analytic, |nn| {
+        let loss = match config.truncation {
+            // Safety: same as above.
+            None => unsafe { sequence_loss_unchecked(nn.params(), &mut results, samples, config) },
+            Some(truncation) => truncated_loss(
+                base.params(),
+                nn.params(),
+                &mut results,
+                samples,
+                truncation,
+            ),
+        };
+        loss / 2.0 / n_sequences as f32
+    });
+}
+
+/// Summed squared error of `samples`, sequences of `[x_0, y_0, x_1, y_1, ..]`, where each
+/// truncation window starts from the state `base` ends the previous window with, and is run with
+/// `params`. That is the loss truncated back propagation through time derives.
+fn truncated_loss(
+    base: &ParamBuffer,
+    params: &ParamBuffer,
+    results: &mut ResultBuffer,
+    samples: &[f32],
+    truncation: usize,
+) -> f32 {
+    let (n_inputs, n_outputs) = (2, 1);
+    let step_size = n_inputs + n_outputs;
+    let len = SEQUENCE_LEN;
+    let mut loss = 0.0;
+    for sequence in samples.chunks(len * step_size) {
+        for start in (0..len).step_by(truncation) {
+            reset_state(base, results);
+            for (t, step) in sequence
+                .chunks(step_size)
+                .enumerate()
+                .take(start + truncation)
+            {
+                let x = ColRef::from_slice(&step[..n_inputs]);
+                let params = match t < start {
+                    true => base,
+                    false => params,
+                };
+                // Safety: buffers are of the same topology, steps are of whole samples.
+                unsafe { forward_unchecked(x, params, results) };
+                if t >= start {
+                    let a = results.layer(results.n_layers() - 1).unwrap().a;
+                    loss += (a[0] - step[n_inputs]).powi(2);
+                }
+            }
+        }
+    }
+    loss
+}
+
+fn recurrent_topology() -> Topology {
+    Topology::builder()
+        .input(2)
+        .recurrent(3, Tanh)
+        .dense(1, Identity)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn recurrent() {
+    let config = SequenceConfig::new(SEQUENCE_LEN, SequenceTargets::EveryStep);
+    check_sequence_derivs(recurrent_topology(), &values(2 * 15, 0.2), &config);
+}
+
+#[test]
+fn recurrent_last_step() {
+    let config = SequenceConfig::new(SEQUENCE_LEN, SequenceTargets::LastStep);
+    check_sequence_derivs(recurrent_topology(), &values(3 * 11, 0.5), &config);
+}
+
+#[test]
+fn recurrent_truncated() {
+    // Windows of steps `0..2`, `2..4` and `4..5`.
+    let config = SequenceConfig::new(SEQUENCE_LEN, SequenceTargets::EveryStep).with_truncation(2);
+    check_sequence_derivs(recurrent_topology(), &values(2 * 15, 0.2), &config);
+}
+
+#[test]
+fn predict_starts_every_sample_from_a_zero_state() {
+    let mut nn = network(recurrent_topology());
+    let samples = values(3 * 8, 0.9);
+    let losses = nn.predict(&samples).unwrap().losses();
+    let loss = nn.loss(&samples).unwrap();
+    assert!((losses.iter().sum::<f32>() - loss).abs() <= 1e-5 * loss.max(1.0));
+    // Running a sample twice gives the same output.
+    let sample = &samples[..3];
+    let twice = nn.predict(&[sample, sample].concat()).unwrap();
+    assert_eq!(twice.output(0), twice.output(1));
+}
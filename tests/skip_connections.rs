@@ -0,0 +1,18 @@
+mod common;
+
+use mlp::{LayerDescription, Skip, Topology, activation_functions::*};
+
+use common::{check_derivs, values};
+
+#[test]
+fn dense_with_skips() {
+    let topology = Topology::builder()
+        .input(3)
+        .dense(4, Tanh)
+        .residual(4, Tanh, 0)
+        .layer(LayerDescription::new(3, Sigmoid).with_skip(Skip::Concat { from: 0 }))
+        .dense(2, Identity)
+        .build()
+        .unwrap();
+    check_derivs(topology, &values(4 * 5, 0.3));
+}
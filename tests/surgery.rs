@@ -0,0 +1,87 @@
+use mlp::{
+    LayerDescription, LayerKind, MlpError, NeuralNetwork, Topology, activation_functions::*,
+    faer::prelude::*,
+};
+
+fn network(topology: Topology) -> NeuralNetwork {
+    let mut nn = NeuralNetwork::new(topology);
+    for (i, p) in nn.params_as_mut_slice().iter_mut().enumerate() {
+        *p = 0.5 * (1.3 * i as f32 + 0.4).sin();
+    }
+    nn
+}
+
+fn dense() -> Topology {
+    Topology::builder()
+        .input(2)
+        .dense(3, Tanh)
+        .dense(2, Sigmoid)
+        .dense(1, Identity)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn widen_layer_preserves_outputs() {
+    let mut nn = network(dense());
+    let mut wide = nn.widen_layer(0, 7, 0.0).unwrap();
+    assert_eq!(wide.topology().layer_descriptions()[0].n_neurons, 7);
+    for i in 0..5 {
+        let x = col![i as f32 - 2.0, 0.3 * i as f32];
+        let expected = nn.forward(x.as_ref()).unwrap()[0];
+        let found = wide.forward(x.as_ref()).unwrap()[0];
+        assert!((expected - found).abs() < 1e-5, "{expected} != {found}");
+    }
+    assert!(matches!(
+        nn.widen_layer(1, 1, 0.0),
+        Err(MlpError::Narrowing { index: 1, .. })
+    ));
+    assert!(matches!(
+        nn.widen_layer(2, 3, 0.0),
+        Err(MlpError::OutputLayer)
+    ));
+}
+
+#[test]
+fn copy_layer_from_checks_the_kind() {
+    // A recurrent layer of 2 neurons after 1 input has a `w` of 2 x 3, as has a dense one after 3
+    // inputs.
+    let recurrent = network(
+        Topology::builder()
+            .input(1)
+            .layer(LayerDescription::recurrent(2, Tanh))
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    );
+    let mut nn = network(
+        Topology::builder()
+            .input(3)
+            .dense(2, Tanh)
+            .dense(1, Identity)
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(
+        recurrent.topology().layer_descriptions()[0].kind,
+        LayerKind::Recurrent
+    );
+    assert_eq!(
+        recurrent.topology().layer_w_shape(0),
+        nn.topology().layer_w_shape(0)
+    );
+    assert!(matches!(
+        nn.copy_layer_from(0, &recurrent, 0),
+        Err(MlpError::KindMismatch { index: 0 })
+    ));
+    let other = network(dense());
+    assert!(matches!(
+        nn.copy_layer_from(0, &other, 0),
+        Err(MlpError::LayerShape { index: 0, .. })
+    ));
+    nn.copy_layer_from(1, &recurrent, 1).unwrap();
+    assert_eq!(
+        nn.params_layer(1).unwrap().w,
+        recurrent.params_layer(1).unwrap().w
+    );
+}
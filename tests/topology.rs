@@ -0,0 +1,69 @@
+use mlp::{
+    LayerDescription, MemoryRequirements, Skip, Topology, TopologyError, activation_functions::*,
+};
+
+#[test]
+fn builder_reports_invalid_topologies() {
+    assert_eq!(
+        Topology::builder().dense(2, Tanh).build().unwrap_err(),
+        TopologyError::NoInputs
+    );
+    assert_eq!(
+        Topology::builder().input(2).build().unwrap_err(),
+        TopologyError::NoLayers
+    );
+    assert_eq!(
+        Topology::builder()
+            .input(2)
+            .dense(3, Tanh)
+            .dense(0, Tanh)
+            .dense(1, Identity)
+            .build()
+            .unwrap_err(),
+        TopologyError::EmptyLayer { index: 1 }
+    );
+    assert_eq!(
+        Topology::builder()
+            .input(2)
+            .dense(3, Tanh)
+            .layer(LayerDescription::new(3, Tanh).with_skip(Skip::Add { from: 1 }))
+            .build()
+            .unwrap_err(),
+        TopologyError::SkipForward { index: 1, from: 1 }
+    );
+    assert_eq!(
+        Topology::builder()
+            .input(2)
+            .dense(3, Tanh)
+            .residual(4, Tanh, 0)
+            .build()
+            .unwrap_err(),
+        TopologyError::SkipSize { index: 1, from: 0 }
+    );
+}
+
+#[test]
+fn builder_counts_params_and_memory() {
+    let topology = Topology::builder()
+        .input(3)
+        .dense(4, Tanh)
+        .layer(LayerDescription::new(2, Identity).without_bias())
+        .build()
+        .unwrap();
+    assert_eq!(topology.n_inputs(), 3);
+    assert_eq!(topology.n_outputs(), 2);
+    assert_eq!(topology.n_layers(), 2);
+    assert_eq!(topology.n_params(), 3 * 4 + 4 + 4 * 2);
+    assert_eq!(topology.n_neurons(), 6);
+    let memory_requirements = topology.memory_requirements();
+    assert_eq!(
+        memory_requirements,
+        MemoryRequirements {
+            params: 24 * 4,
+            results: 2 * 6 * 4,
+            derivs: (24 + 6) * 4,
+        }
+    );
+    assert_eq!(memory_requirements.inference(), (24 + 12) * 4);
+    assert_eq!(memory_requirements.training(), (24 + 12 + 30) * 4);
+}